@@ -0,0 +1,288 @@
+//! Keyframe animation of the camera and of object placement.
+//!
+//! A [`Track`] holds values at key times and interpolates between them, holding the first and
+//! last values outside the keys. An [`Animation`] gathers the tracks of a scene and says how
+//! many frames to render at what rate; `Scene::at` poses the scene for a moment in time.
+
+use std::ops::{Add, Sub};
+
+use serde::Serialize;
+
+use crate::hit::*;
+use crate::ray::*;
+use crate::scene::*;
+use crate::transform::*;
+use crate::vec3::*;
+
+/// How to get from one key to the next.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub enum Interpolation {
+    /// Straight lines between keys, changing speed abruptly at each one.
+    Linear,
+    /// A Catmull-Rom spline through the keys, so motion eases smoothly through them.
+    #[default]
+    Spline,
+}
+
+/// Values that can be keyframed: anything that can be summed and scaled.
+pub trait Keyable: Copy + Add<Output = Self> + Sub<Output = Self> {
+    fn scale(self, k: f32) -> Self;
+}
+
+impl Keyable for f32 {
+    fn scale(self, k: f32) -> f32 {
+        self * k
+    }
+}
+
+impl Keyable for Vec3 {
+    fn scale(self, k: f32) -> Vec3 {
+        k * self
+    }
+}
+
+/// Values at key times, in increasing order of time.
+#[derive(Clone, Debug, Serialize)]
+pub struct Track<T> {
+    keys: Vec<(f32, T)>,
+    interpolation: Interpolation,
+}
+
+impl<T: Keyable> Track<T> {
+    /// A track through `keys`, which must be in strictly increasing order of time.
+    pub fn new(keys: Vec<(f32, T)>, interpolation: Interpolation) -> Result<Track<T>, String> {
+        if keys.is_empty() {
+            return Err("a track needs at least one key".to_string());
+        }
+        if let Some(t) = keys.iter().map(|(t, _)| *t).find(|t| !t.is_finite()) {
+            return Err(format!("key time must be finite, got {}", t));
+        }
+        if let Some(pair) = keys.windows(2).find(|pair| pair[0].0 >= pair[1].0) {
+            return Err(format!("key times must increase, but {} is followed by {}", pair[0].0, pair[1].0));
+        }
+        Ok(Track { keys, interpolation })
+    }
+
+    /// A track that holds one value throughout.
+    pub fn constant(value: T) -> Track<T> {
+        Track {
+            keys: vec![(0.0, value)],
+            interpolation: Interpolation::Linear,
+        }
+    }
+
+    /// The same track, `by` seconds earlier.
+    pub fn shifted(&self, by: f32) -> Track<T> {
+        Track {
+            keys: self.keys.iter().map(|(t, value)| (t - by, *value)).collect(),
+            interpolation: self.interpolation,
+        }
+    }
+
+    /// The time of the last key.
+    pub fn end(&self) -> f32 {
+        self.keys[self.keys.len() - 1].0
+    }
+
+    pub fn at(&self, time: f32) -> T {
+        let keys = &self.keys;
+        // The first key at or after `time`.
+        let i = keys.partition_point(|(t, _)| *t < time);
+        if i == 0 {
+            return keys[0].1;
+        }
+        if i == keys.len() {
+            return keys[i - 1].1;
+        }
+
+        let ((t0, p0), (t1, p1)) = (keys[i - 1], keys[i]);
+        let h = t1 - t0;
+        let s = (time - t0) / h;
+        match self.interpolation {
+            Interpolation::Linear => p0 + (p1 - p0).scale(s),
+            Interpolation::Spline => {
+                // Cubic Hermite, with the tangent at each key taken from its neighbours (one-sided
+                // at the ends). Keys needn't be evenly spaced in time.
+                let m0 = self.tangent(i - 1);
+                let m1 = self.tangent(i);
+                let (s2, s3) = (s * s, s * s * s);
+                p0.scale(2.0 * s3 - 3.0 * s2 + 1.0)
+                    + m0.scale(h * (s3 - 2.0 * s2 + s))
+                    + p1.scale(-2.0 * s3 + 3.0 * s2)
+                    + m1.scale(h * (s3 - s2))
+            }
+        }
+    }
+
+    fn tangent(&self, i: usize) -> T {
+        let before = self.keys[i.saturating_sub(1)];
+        let after = self.keys[usize::min(i + 1, self.keys.len() - 1)];
+        (after.1 - before.1).scale(1.0 / (after.0 - before.0))
+    }
+}
+
+/// Tracks for the camera's pose. Properties without a track keep the scene camera's value.
+#[derive(Clone, Debug, Default)]
+pub struct CameraTracks {
+    pub lookfrom: Option<Track<Vec3>>,
+    pub lookat: Option<Track<Vec3>>,
+    pub vfov: Option<Track<f32>>,
+    pub focus_dist: Option<Track<f32>>,
+    /// Without a `focus_dist` track, keep `lookat` in focus as the camera moves.
+    pub focus_on_lookat: bool,
+}
+
+impl CameraTracks {
+    pub fn at(&self, camera: &CameraSettings, time: f32) -> CameraSettings {
+        let mut camera = camera.clone();
+        if let Some(track) = &self.lookfrom {
+            camera.lookfrom = track.at(time);
+        }
+        if let Some(track) = &self.lookat {
+            camera.lookat = track.at(time);
+        }
+        if let Some(track) = &self.vfov {
+            camera.vfov = track.at(time);
+        }
+        match &self.focus_dist {
+            Some(track) => camera.focus_dist = track.at(time),
+            None if self.focus_on_lookat => camera.focus_dist = (camera.lookfrom - camera.lookat).length(),
+            None => {}
+        }
+        camera
+    }
+
+    /// Whether there is nothing to move the camera.
+    pub fn is_empty(&self) -> bool {
+        self.lookfrom.is_none() && self.lookat.is_none() && self.vfov.is_none() && self.focus_dist.is_none()
+    }
+
+    /// The same tracks, `by` seconds earlier.
+    pub fn shifted(&self, by: f32) -> CameraTracks {
+        CameraTracks {
+            lookfrom: self.lookfrom.as_ref().map(|track| track.shifted(by)),
+            lookat: self.lookat.as_ref().map(|track| track.shifted(by)),
+            vfov: self.vfov.as_ref().map(|track| track.shifted(by)),
+            focus_dist: self.focus_dist.as_ref().map(|track| track.shifted(by)),
+            focus_on_lookat: self.focus_on_lookat,
+        }
+    }
+
+    fn end(&self) -> f32 {
+        let ends = [self.lookfrom.as_ref().map(Track::end), self.lookat.as_ref().map(Track::end)];
+        let more = [self.vfov.as_ref().map(Track::end), self.focus_dist.as_ref().map(Track::end)];
+        ends.into_iter().chain(more).flatten().fold(0.0, f32::max)
+    }
+}
+
+/// Moves `scene.world[index]`: `object` is the object before any transform, placed at each
+/// moment by rotating then translating it.
+#[derive(Clone, Debug)]
+pub struct ObjectTrack {
+    pub index: usize,
+    pub object: Object,
+    pub rotate: Track<Vec3>,
+    pub translate: Track<Vec3>,
+}
+
+impl ObjectTrack {
+    pub fn at(&self, time: f32) -> Object {
+        let transform = Transform::new(self.rotate.at(time), self.translate.at(time));
+        self.object.clone().transformed(transform)
+    }
+
+    /// The object moving along its tracks from `start` on, for motion blur over a shutter
+    /// that opens at `start`.
+    pub fn during(&self, start: f32) -> Object {
+        Moving {
+            object: Box::new(self.object.clone()),
+            rotate: self.rotate.clone(),
+            translate: self.translate.clone(),
+            start,
+        }
+        .into()
+    }
+}
+
+/// An object placed at the time a ray was cast: its tracks at `start` plus the ray's time.
+#[derive(Clone, Debug, Serialize)]
+pub struct Moving {
+    pub object: Box<Object>,
+    pub rotate: Track<Vec3>,
+    pub translate: Track<Vec3>,
+    pub start: f32,
+}
+
+impl Moving {
+    /// The object as placed `time` seconds after `start`.
+    pub fn at(&self, time: f32) -> Object {
+        let time = self.start + time;
+        self.object.as_ref().clone().transformed(Transform::new(self.rotate.at(time), self.translate.at(time)))
+    }
+}
+
+impl Hittable for Moving {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let time = self.start + r.time;
+        let transform = Transform::new(self.rotate.at(time), self.translate.at(time));
+        hit_transformed(&self.object, &transform, r, t_min, t_max)
+    }
+}
+
+/// How a scene changes over time, and which moments to render.
+#[derive(Clone, Debug)]
+pub struct Animation {
+    pub fps: f32,
+    pub frames: u32,
+    pub camera: CameraTracks,
+    pub objects: Vec<ObjectTrack>,
+}
+
+impl Default for Animation {
+    /// A still: one frame, nothing moving.
+    fn default() -> Animation {
+        Animation {
+            fps: 24.0,
+            frames: 1,
+            camera: CameraTracks::default(),
+            objects: vec![],
+        }
+    }
+}
+
+impl Animation {
+    /// The time of a frame, in seconds from the first.
+    pub fn time(&self, frame: u32) -> f32 {
+        frame as f32 / self.fps
+    }
+
+    /// Enough frames at `fps` to reach the last key of any track.
+    pub fn frames_to_end(&self) -> u32 {
+        let end = self.objects.iter().flat_map(|o| [o.rotate.end(), o.translate.end()]).fold(self.camera.end(), f32::max);
+        (end * self.fps).round() as u32 + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolates_between_keys() {
+        let keys = vec![(0.0, 0.0), (1.0, 10.0), (3.0, 10.0)];
+        let linear = Track::new(keys.clone(), Interpolation::Linear).unwrap();
+        assert_eq!(linear.at(-1.0), 0.0);
+        assert_eq!(linear.at(0.25), 2.5);
+        assert_eq!(linear.at(2.0), 10.0);
+        assert_eq!(linear.at(5.0), 10.0);
+
+        // The spline passes through every key, but overshoots after the climb.
+        let spline = Track::new(keys, Interpolation::Spline).unwrap();
+        for (t, v) in [(0.0, 0.0), (1.0, 10.0), (3.0, 10.0)] {
+            assert!((spline.at(t) - v).abs() < 1e-5);
+        }
+        assert!(spline.at(1.5) > 10.0);
+
+        assert!(Track::new(vec![(1.0, 0.0), (1.0, 1.0)], Interpolation::Linear).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! The shape of a thin-lens camera's aperture, which out-of-focus highlights take on.
+//!
+//! Shapes are sampled over the unit disc (or, for a mask, the square around it) and scaled by
+//! the camera's aperture radius.
+
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::image::*;
+use crate::util::{random_f32, PI};
+use crate::vec3::*;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum ApertureShape {
+    /// A perfectly round opening.
+    #[default]
+    Disc,
+    /// The opening left by `blades` straight blades, with a corner `rotation` degrees
+    /// anticlockwise from the right.
+    Polygon { blades: u32, rotation: f32 },
+    /// Any shape, drawn as a greyscale image of how much light passes where.
+    Mask(Arc<ApertureMask>),
+}
+
+impl ApertureShape {
+    /// A point on the aperture, with x to the right and y up. Points are spread evenly over the
+    /// opening, or in proportion to a mask's transmission.
+    pub fn sample(&self) -> Vec3 {
+        match self {
+            ApertureShape::Disc => Vec3::random_in_unit_disk(),
+            ApertureShape::Polygon { blades, rotation } => {
+                // Pick one of the triangles between the centre and each edge, then a point in it.
+                let n = *blades as f32;
+                let edge = ((random_f32() * n) as u32).min(blades - 1) as f32;
+                let angle = |k: f32| rotation.to_radians() + 2.0 * PI * k / n;
+                let (a, b) = (angle(edge), angle(edge + 1.0));
+                let (r1, r2) = (random_f32().sqrt(), random_f32());
+                let (wa, wb) = (r1 * (1.0 - r2), r1 * r2);
+                Vec3::new(wa * a.cos() + wb * b.cos(), wa * a.sin() + wb * b.sin(), 0.0)
+            }
+            ApertureShape::Mask(mask) => mask.sample(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ApertureShape::Polygon { blades, .. } if *blades < 3 => {
+                Err(format!("an aperture needs at least 3 blades, got {}", blades))
+            }
+            ApertureShape::Polygon { rotation, .. } if !rotation.is_finite() => {
+                Err(format!("aperture rotation must be finite, got {}", rotation))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A greyscale transmission image covering the square around the aperture, with its longer
+/// side across the aperture's diameter.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(into = "MaskWeights", try_from = "MaskWeights")]
+pub struct ApertureMask {
+    pub width: usize,
+    pub height: usize,
+    /// Transmission per pixel, row by row from the top.
+    pub weights: Vec<f32>,
+    // Running totals of `weights`, to pick pixels by importance.
+    cdf: Vec<f32>,
+}
+
+impl ApertureMask {
+    pub fn new(width: usize, height: usize, weights: Vec<f32>) -> Result<ApertureMask, String> {
+        if width == 0 || height == 0 || weights.len() != width * height {
+            return Err(format!("an aperture mask needs {} by {} weights, got {}", width, height, weights.len()));
+        }
+        if let Some(w) = weights.iter().find(|w| !(w.is_finite() && **w >= 0.0)) {
+            return Err(format!("aperture mask weights must be non-negative, got {}", w));
+        }
+        let cdf: Vec<f32> = weights
+            .iter()
+            .scan(0.0, |total, w| {
+                *total += w;
+                Some(*total)
+            })
+            .collect();
+        if cdf[cdf.len() - 1] <= 0.0 {
+            return Err("an aperture mask must let some light through".to_string());
+        }
+        Ok(ApertureMask { width, height, weights, cdf })
+    }
+
+    /// Read a mask from an image, taking the average of each pixel's channels as its
+    /// transmission.
+    pub fn load(path: &Path) -> Result<ApertureMask, String> {
+        let image = read_image(path, false).map_err(|e| format!("could not read aperture mask: {}", e))?;
+        let weights = image.pixels.iter().map(|c| (c.x + c.y + c.z) / 3.0).collect();
+        ApertureMask::new(image.width, image.height, weights).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn sample(&self) -> Vec3 {
+        let u = random_f32() * self.cdf[self.cdf.len() - 1];
+        let i = self.cdf.partition_point(|c| *c <= u).min(self.cdf.len() - 1);
+        let (x, y) = ((i % self.width) as f32 + random_f32(), (i / self.width) as f32 + random_f32());
+        let side = self.width.max(self.height) as f32;
+        Vec3::new((2.0 * x - self.width as f32) / side, (self.height as f32 - 2.0 * y) / side, 0.0)
+    }
+}
+
+// How a mask is written out: its weights, from which the running totals are rebuilt and checked.
+#[derive(Serialize, Deserialize)]
+struct MaskWeights {
+    width: usize,
+    height: usize,
+    weights: Vec<f32>,
+}
+
+impl From<ApertureMask> for MaskWeights {
+    fn from(mask: ApertureMask) -> MaskWeights {
+        MaskWeights { width: mask.width, height: mask.height, weights: mask.weights }
+    }
+}
+
+impl TryFrom<MaskWeights> for ApertureMask {
+    type Error = String;
+
+    fn try_from(mask: MaskWeights) -> Result<ApertureMask, String> {
+        ApertureMask::new(mask.width, mask.height, mask.weights)
+    }
+}
+
+impl fmt::Debug for ApertureMask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ApertureMask({}x{})", self.width, self.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::seed_rng;
+
+    #[test]
+    fn samples_stay_inside_the_shape() {
+        seed_rng(1, 0);
+        // A square's corners are at 45 degrees, so its edges are 1/sqrt(2) from the centre.
+        let square = ApertureShape::Polygon { blades: 4, rotation: 45.0 };
+        for _ in 0..1000 {
+            let p = square.sample();
+            assert!(p.x.abs() <= 0.7072 && p.y.abs() <= 0.7072);
+        }
+
+        // Only the right half of this mask lets light through.
+        let mask = ApertureMask::new(2, 1, vec![0.0, 1.0]).unwrap();
+        let shape = ApertureShape::Mask(Arc::new(mask));
+        for _ in 0..1000 {
+            let p = shape.sample();
+            assert!((0.0..=1.0).contains(&p.x) && (-0.5..=0.5).contains(&p.y));
+        }
+
+        assert!(ApertureShape::Polygon { blades: 2, rotation: 0.0 }.validate().is_err());
+        assert!(ApertureMask::new(2, 1, vec![0.0, 0.0]).is_err());
+    }
+
+    #[test]
+    fn samples_masks_in_proportion() {
+        seed_rng(2, 0);
+        let weights = [0.1, 0.2, 0.3, 0.4];
+        let mask = ApertureMask::new(4, 1, weights.to_vec()).unwrap();
+        let n = 100_000;
+        let mut counts = [0; 4];
+        for _ in 0..n {
+            // The pixels span x from -1 to 1 in quarters.
+            let x = mask.sample().x;
+            counts[(((x + 1.0) * 2.0) as usize).min(3)] += 1;
+        }
+        for (count, weight) in counts.iter().zip(weights) {
+            let share = *count as f32 / n as f32;
+            assert!((share - weight).abs() < 0.01, "{:?}", counts);
+        }
+    }
+}
@@ -1,8 +1,38 @@
+use enum_dispatch::enum_dispatch;
+use serde::{Deserialize, Serialize};
+
+use crate::aperture::ApertureShape;
+use crate::lens::LensCamera;
 use crate::ray::*;
+use crate::transform::{Mat3, Transform};
 use crate::vec3::*;
-use crate::util::degrees_to_radians;
+use crate::util::{degrees_to_radians, random_f32, PI};
+
+#[enum_dispatch]
+pub trait CameraModel {
+    /// The ray through (s, t) on the image, from (0, 0) at the bottom left to (1, 1) at the top
+    /// right. None where the image shows nothing, such as outside a fisheye's circle.
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray>;
+
+    /// The ray through (s, t) with how much light it brings to the film, for cameras whose
+    /// lens passes more towards the middle of the image than the edges.
+    fn get_weighted_ray(&self, s: f32, t: f32) -> Option<(Ray, f32)> {
+        self.get_ray(s, t).map(|r| (r, 1.0))
+    }
+}
+
+/// The camera's orthonormal frame: u to the right, v up and w back, away from what it looks at.
+pub(crate) fn basis(lookfrom: Point3, lookat: Point3, vup: Vec3) -> (Vec3, Vec3, Vec3) {
+    let w = Vec3::unit_vector(&(lookfrom - lookat));
+    let u = Vec3::unit_vector(&Vec3::cross(&vup, &w));
+    let v = Vec3::cross(&w, &u);
+    (u, v, w)
+}
 
-pub struct Camera {
+/// A pinhole camera, or a thin lens with depth of field when the aperture is open. The lens
+/// can be shifted off the centre of the image and tilted, as on a view camera.
+#[derive(Clone, Debug)]
+pub struct Perspective {
     origin: Point3,
     lower_left_corner: Point3,
     horizontal: Vec3,
@@ -11,10 +41,13 @@ pub struct Camera {
     v: Vec3,
     w: Vec3,
     lens_radius: f32,
+    shape: ApertureShape,
+    focus_dist: f32,
+    // Faces the camera; `w` unless the lens is tilted.
+    focus_normal: Vec3,
 }
 
-
-impl Camera {
+impl Perspective {
     pub fn new(
         lookfrom: Point3,
         lookat: Point3,
@@ -23,15 +56,13 @@ impl Camera {
         aspect_ratio: f32,
         aperture: f32,
         focus_dist: f32,
-    ) -> Camera {
+    ) -> Perspective {
         let theta = degrees_to_radians(vfov);
         let h = (theta / 2.0).tan();
         let viewport_height = 2.0 * h;
         let viewport_width = aspect_ratio * viewport_height;
 
-        let w = Vec3::unit_vector(&(lookfrom - lookat));
-        let u = Vec3::unit_vector(&Vec3::cross(&vup, &w));
-        let v = Vec3::cross(&w, &u);
+        let (u, v, w) = basis(lookfrom, lookat, vup);
 
         // let focal_length = 1.0;
 
@@ -43,7 +74,7 @@ impl Camera {
 
         let lens_radius = aperture / 2.0;
 
-        Camera {
+        Perspective {
             origin,
             lower_left_corner,
             horizontal,
@@ -52,15 +83,734 @@ impl Camera {
             v,
             w,
             lens_radius,
+            shape: ApertureShape::Disc,
+            focus_dist,
+            focus_normal: w,
         }
     }
 
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
+    /// Move the image across the lens by fractions of its width and height, so the view moves
+    /// without turning the camera. Shifting up keeps the verticals of a tall building parallel.
+    pub fn with_shift(mut self, x: f32, y: f32) -> Perspective {
+        self.lower_left_corner += x * self.horizontal + y * self.vertical;
+        self
+    }
+
+    /// Tilt the plane of focus, after Scheimpflug: `tilt` degrees about the horizontal, so
+    /// positive angles take its top further away, and `swing` degrees about the vertical, so
+    /// positive angles take its right further away. A tilted plane can lie along the ground.
+    pub fn with_tilt(mut self, tilt: f32, swing: f32) -> Perspective {
+        let (tilt, swing) = (degrees_to_radians(tilt), degrees_to_radians(swing));
+        self.focus_normal = Vec3::unit_vector(&(self.w + tilt.tan() * self.v + swing.tan() * self.u));
+        self
+    }
+
+    /// Open the aperture in `shape` rather than a disc.
+    pub fn with_aperture_shape(mut self, shape: ApertureShape) -> Perspective {
+        self.shape = shape;
+        self
+    }
+}
+
+impl CameraModel for Perspective {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        let rd = self.lens_radius * self.shape.sample();
         let offset = rd.x * self.u + rd.y * self.v;
-        Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + (s * self.horizontal) + (t * self.vertical) - self.origin - offset
-        )
+        let mut target = self.lower_left_corner + (s * self.horizontal) + (t * self.vertical);
+        if self.focus_normal != self.w {
+            // Focus where the ray through the centre of the lens meets the tilted plane, or at
+            // infinity if it never does.
+            let dir = target - self.origin;
+            let facing = Vec3::dot(&self.focus_normal, &dir);
+            if facing >= 0.0 {
+                return Some(Ray::new(self.origin + offset, dir));
+            }
+            target = self.origin + (-self.focus_dist * Vec3::dot(&self.focus_normal, &self.w) / facing) * dir;
+        }
+        Some(Ray::new(self.origin + offset, target - self.origin - offset))
+    }
+}
+
+/// A camera's calibration in OpenCV's conventions: focal lengths, skew and principal point in
+/// pixels of a `width` by `height` image, with the centre of the top left pixel at (0, 0) and y
+/// down. The distortion is measured in coordinates divided by depth, as OpenCV measures it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Intrinsics {
+    pub fx: f32,
+    pub fy: f32,
+    pub cx: f32,
+    pub cy: f32,
+    pub skew: f32,
+    pub width: f32,
+    pub height: f32,
+    pub distortion: Option<Distortion>,
+}
+
+impl Intrinsics {
+    /// From the camera matrix [[fx, skew, cx], [0, fy, cy], [0, 0, 1]].
+    pub fn from_matrix(k: &Mat3, width: f32, height: f32) -> Intrinsics {
+        Intrinsics {
+            fx: k.rows[0].x,
+            fy: k.rows[1].y,
+            cx: k.rows[0].z,
+            cy: k.rows[1].z,
+            skew: k.rows[0].y,
+            width,
+            height,
+            distortion: None,
+        }
+    }
+
+    /// The angle between the top and bottom edges of the image, in degrees, as seen through
+    /// the principal point.
+    pub fn vfov(&self) -> f32 {
+        2.0 * f32::atan(0.5 * self.height / self.fy).to_degrees()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [("fx", self.fx), ("fy", self.fy), ("width", self.width), ("height", self.height)] {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("camera intrinsics: {} must be positive, got {}", name, value));
+            }
+        }
+        if !(self.cx.is_finite() && self.cy.is_finite() && self.skew.is_finite()) {
+            return Err(format!("camera intrinsics: the principal point and skew must be finite, got ({}, {}) and {}", self.cx, self.cy, self.skew));
+        }
+        Ok(())
+    }
+}
+
+/// `lookfrom`, `lookat` and `vup` for a camera that OpenCV would place with `rotation` and
+/// `translation`, taking world points x to rotation * x + translation in its frame of x right, y
+/// down and z forward. `lookat` is on the view axis, as far ahead as the world origin is (or 1
+/// ahead if the origin is behind the camera), so it makes a good default focus.
+pub fn pose_from_extrinsics(rotation: &Mat3, translation: Vec3) -> (Point3, Point3, Vec3) {
+    let lookfrom = -(rotation.transpose() * translation);
+    let depth = if translation.z > 0.0 { translation.z } else { 1.0 };
+    (lookfrom, lookfrom + depth * rotation.rows[2], -rotation.rows[1])
+}
+
+/// A perspective camera matching a calibrated real one, so renders line up with its photographs
+/// pixel for pixel, including its distortion.
+#[derive(Clone, Debug)]
+pub struct Calibrated {
+    intrinsics: Intrinsics,
+    // The same camera without distortion.
+    pinhole: Perspective,
+}
+
+impl Calibrated {
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3, intrinsics: Intrinsics, aperture: f32, focus_dist: f32) -> Calibrated {
+        let (u, v, w) = basis(lookfrom, lookat, vup);
+        let k = &intrinsics;
+        // The view through (0, 0), the bottom left corner of the bottom left pixel, at unit
+        // depth; moving across the image goes along u, and moving up goes along v and, with
+        // skew, u too.
+        let y0 = (k.height - 0.5 - k.cy) / k.fy;
+        let x0 = (-0.5 - k.cx - k.skew * y0) / k.fx;
+        let horizontal = focus_dist * (k.width / k.fx) * u;
+        let vertical = focus_dist * (k.height / k.fy) * (v + (k.skew / k.fx) * u);
+        let pinhole = Perspective {
+            origin: lookfrom,
+            lower_left_corner: lookfrom + focus_dist * (x0 * u - y0 * v - w),
+            horizontal,
+            vertical,
+            u,
+            v,
+            w,
+            lens_radius: aperture / 2.0,
+            shape: ApertureShape::Disc,
+            focus_dist,
+            focus_normal: w,
+        };
+        Calibrated { intrinsics, pinhole }
+    }
+
+    /// The camera from OpenCV's matrices: the camera matrix `k` for a `width` by `height`
+    /// image, and the world-to-camera `rotation` and `translation`.
+    pub fn from_opencv(k: &Mat3, width: f32, height: f32, rotation: &Mat3, translation: Vec3) -> Calibrated {
+        let (lookfrom, lookat, vup) = pose_from_extrinsics(rotation, translation);
+        let focus_dist = (lookat - lookfrom).length();
+        Calibrated::new(lookfrom, lookat, vup, Intrinsics::from_matrix(k, width, height), 0.0, focus_dist)
+    }
+}
+
+impl CameraModel for Calibrated {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        let Some(distortion) = &self.intrinsics.distortion else {
+            return self.pinhole.get_ray(s, t);
+        };
+        // Find where the pixel's light would land without distortion.
+        let k = &self.intrinsics;
+        let (px, py) = (s * k.width - 0.5, (1.0 - t) * k.height - 0.5);
+        let y = (py - k.cy) / k.fy;
+        let x = (px - k.cx - k.skew * y) / k.fx;
+        let (x, y) = distortion.undistort(x, y)?;
+        let (px, py) = (k.fx * x + k.skew * y + k.cx, k.fy * y + k.cy);
+        self.pinhole.get_ray((px + 0.5) / k.width, 1.0 - (py + 0.5) / k.height)
+    }
+}
+
+/// Parallel rays from a rectangle `view_height` high, centred on `lookfrom`, so sizes don't
+/// change with distance. Everything is in focus.
+#[derive(Clone, Copy, Debug)]
+pub struct Orthographic {
+    lower_left_corner: Point3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    direction: Vec3,
+}
+
+impl Orthographic {
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3, view_height: f32, aspect_ratio: f32) -> Orthographic {
+        let (u, v, w) = basis(lookfrom, lookat, vup);
+        let horizontal = aspect_ratio * view_height * u;
+        let vertical = view_height * v;
+        Orthographic {
+            lower_left_corner: lookfrom - horizontal / 2.0 - vertical / 2.0,
+            horizontal,
+            vertical,
+            direction: -w,
+        }
+    }
+}
+
+impl CameraModel for Orthographic {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        Some(Ray::new(self.lower_left_corner + (s * self.horizontal) + (t * self.vertical), self.direction))
+    }
+}
+
+/// The whole sphere of directions around `lookfrom`: longitude across the image, with `lookat` in
+/// the middle, and latitude from straight down at the bottom to straight up at the top. The image
+/// should be twice as wide as it is high.
+#[derive(Clone, Copy, Debug)]
+pub struct Equirectangular {
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    eye_offset: f32,
+}
+
+impl Equirectangular {
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3) -> Equirectangular {
+        let (u, v, w) = basis(lookfrom, lookat, vup);
+        Equirectangular { origin: lookfrom, u, v, w, eye_offset: 0.0 }
+    }
+
+    /// See the scene from one eye of a head turning on the spot, as in omnidirectional stereo:
+    /// each ray starts `offset` to the right of `lookfrom` as seen looking along its longitude,
+    /// so negative offsets give the left eye's view.
+    pub fn with_eye_offset(mut self, offset: f32) -> Equirectangular {
+        self.eye_offset = offset;
+        self
+    }
+}
+
+impl CameraModel for Equirectangular {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        let (sin_phi, cos_phi) = ((s - 0.5) * 2.0 * PI).sin_cos();
+        let (sin_theta, cos_theta) = ((t - 0.5) * PI).sin_cos();
+        let dir = cos_theta * (sin_phi * self.u - cos_phi * self.w) + sin_theta * self.v;
+        let right = cos_phi * self.u + sin_phi * self.w;
+        Some(Ray::new(self.origin + self.eye_offset * right, dir))
+    }
+}
+
+/// How a fisheye lens spreads angles from the view direction over its image circle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FisheyeMapping {
+    /// Distance from the centre is proportional to the angle, as for dome projection.
+    #[default]
+    Equidistant,
+    /// Equal solid angles cover equal areas of the image, as in most real fisheye lenses.
+    Equisolid,
+}
+
+/// A circular image, as wide as the shorter side of the frame, covering `fov` degrees.
+#[derive(Clone, Copy, Debug)]
+pub struct Fisheye {
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    // Scale from (s, t) about the centre to the unit circle.
+    scale: (f32, f32),
+    half_fov: f32,
+    mapping: FisheyeMapping,
+}
+
+impl Fisheye {
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3, fov: f32, mapping: FisheyeMapping, aspect_ratio: f32) -> Fisheye {
+        let (u, v, w) = basis(lookfrom, lookat, vup);
+        let scale = if aspect_ratio >= 1.0 { (2.0 * aspect_ratio, 2.0) } else { (2.0, 2.0 / aspect_ratio) };
+        Fisheye {
+            origin: lookfrom,
+            u,
+            v,
+            w,
+            scale,
+            half_fov: degrees_to_radians(fov) / 2.0,
+            mapping,
+        }
+    }
+}
+
+impl CameraModel for Fisheye {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        let x = (s - 0.5) * self.scale.0;
+        let y = (t - 0.5) * self.scale.1;
+        let r = f32::sqrt(x * x + y * y);
+        if r > 1.0 {
+            return None;
+        }
+        // The angle from the view direction at this distance from the centre.
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * self.half_fov,
+            FisheyeMapping::Equisolid => 2.0 * f32::asin(r * f32::sin(self.half_fov / 2.0)),
+        };
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        let across = if r > 0.0 { (x / r) * self.u + (y / r) * self.v } else { Vec3::zero() };
+        Some(Ray::new(self.origin, cos_theta * -self.w + sin_theta * across))
+    }
+}
+
+/// How the two views of a stereo pair share the image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StereoLayout {
+    /// The left eye's view in the left half of the image.
+    #[default]
+    SideBySide,
+    /// The left eye's view in the top half, as is usual for 360 video.
+    TopBottom,
+}
+
+impl StereoLayout {
+    /// The aspect ratio of each eye's view in an image of `aspect_ratio`.
+    pub fn eye_aspect_ratio(&self, aspect_ratio: f32) -> f32 {
+        match self {
+            StereoLayout::SideBySide => aspect_ratio / 2.0,
+            StereoLayout::TopBottom => aspect_ratio * 2.0,
+        }
+    }
+}
+
+/// A pair of cameras `eye_separation` apart, looking the same way. A perspective pair's views
+/// are shifted to meet `convergence` in front of them, so what is there appears at the depth of
+/// the screen; by default, that is the focus distance. An equirectangular pair is rendered as
+/// omnidirectional stereo, for 360 video.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StereoRig {
+    pub layout: StereoLayout,
+    pub eye_separation: f32,
+    pub convergence: Option<f32>,
+}
+
+impl StereoRig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.eye_separation.is_finite() && self.eye_separation > 0.0) {
+            return Err(format!("camera.eye_separation must be positive, got {}", self.eye_separation));
+        }
+        if let Some(convergence) = self.convergence.filter(|c| !(c.is_finite() && *c > 0.0)) {
+            return Err(format!("camera.convergence must be positive, got {}", convergence));
+        }
+        Ok(())
+    }
+}
+
+/// The two views of a stereo rig, sharing the image as its layout says.
+#[derive(Clone, Debug)]
+pub struct Stereo {
+    left: Box<Camera>,
+    right: Box<Camera>,
+    layout: StereoLayout,
+}
+
+impl Stereo {
+    pub fn new(left: Camera, right: Camera, layout: StereoLayout) -> Stereo {
+        Stereo {
+            left: Box::new(left),
+            right: Box::new(right),
+            layout,
+        }
+    }
+
+    /// The eye that sees (s, t) on the image, and where that is in its own view.
+    pub fn eye(&self, s: f32, t: f32) -> (&Camera, f32, f32) {
+        match self.layout {
+            StereoLayout::SideBySide if s < 0.5 => (&self.left, 2.0 * s, t),
+            StereoLayout::SideBySide => (&self.right, 2.0 * s - 1.0, t),
+            StereoLayout::TopBottom if t >= 0.5 => (&self.left, s, 2.0 * t - 1.0),
+            StereoLayout::TopBottom => (&self.right, s, 2.0 * t),
+        }
+    }
+}
+
+impl CameraModel for Stereo {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        let (eye, s, t) = self.eye(s, t);
+        eye.get_ray(s, t)
+    }
+
+    fn get_weighted_ray(&self, s: f32, t: f32) -> Option<(Ray, f32)> {
+        let (eye, s, t) = self.eye(s, t);
+        eye.get_weighted_ray(s, t)
+    }
+}
+
+/// A camera whose shutter is open for `open` seconds at each row of the image, with the rows
+/// opening one after another over `readout` seconds from the top, as in the rolling shutter of
+/// a CMOS sensor. With no readout, the whole image is exposed at once. Each ray carries the time
+/// it was cast, from when the top row opens, and is cast by `camera` moved to where it is then.
+/// `moves` take it there at even steps over the whole exposure, and are blended in between; the
+/// camera turns and moves, but its lens stays as it was when the shutter opened.
+#[derive(Clone, Debug)]
+pub struct Shutter {
+    camera: Box<Camera>,
+    moves: Vec<Transform>,
+    open: f32,
+    readout: f32,
+}
+
+impl Shutter {
+    pub fn new(camera: Camera, moves: Vec<Transform>, open: f32, readout: f32) -> Shutter {
+        assert!(!moves.is_empty(), "a shutter needs to know where the camera is");
+        Shutter { camera: Box::new(camera), moves, open, readout }
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    /// A moment at random while row `t` of the image is exposed, and how the camera has moved
+    /// by then.
+    pub fn moment(&self, t: f32) -> (Transform, f32) {
+        let time = self.readout * (1.0 - t.clamp(0.0, 1.0)) + random_f32() * self.open;
+        let end = self.readout + self.open;
+        let last = self.moves.len() - 1;
+        let step = if end > 0.0 { (time / end * last as f32).clamp(0.0, last as f32) } else { 0.0 };
+        let i = (step as usize).min(last.saturating_sub(1));
+        let moved = match self.moves.get(i + 1) {
+            Some(next) => self.moves[i].lerp(next, step - i as f32),
+            None => self.moves[i],
+        };
+        (moved, time)
+    }
+}
+
+/// `r` cast from a camera that has been moved by `moved`.
+pub fn move_ray(r: Ray, moved: &Transform) -> Ray {
+    Ray::new(moved.point_to_world(&r.orig), moved.vector_to_world(&r.dir)).with_time(r.time)
+}
+
+impl CameraModel for Shutter {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        let (moved, time) = self.moment(t);
+        self.camera.get_ray(s, t).map(|r| move_ray(r, &moved).with_time(time))
+    }
+
+    fn get_weighted_ray(&self, s: f32, t: f32) -> Option<(Ray, f32)> {
+        let (moved, time) = self.moment(t);
+        self.camera.get_weighted_ray(s, t).map(|(r, weight)| (move_ray(r, &moved).with_time(time), weight))
+    }
+}
+
+/// The settings of a physical camera. Scene radiance is taken to be in candela per square metre,
+/// as for real light, and scaled to pixel values the way a camera's meter would.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Exposure {
+    /// Focal length over aperture diameter. Each stop up (times the square root of 2) halves
+    /// the light and deepens the depth of field.
+    pub f_number: f32,
+    /// How long the shutter is open, in seconds. Moving objects blur over this time.
+    pub shutter: f32,
+    /// The sensor's sensitivity, as a film speed.
+    pub iso: f32,
+}
+
+impl Exposure {
+    /// The factor from radiance to pixel value. This is saturation-based exposure (ISO 12232),
+    /// which maps the brightest luminance the sensor records without clipping to 1.
+    pub fn scale(&self) -> f32 {
+        self.shutter * self.iso / (1.2 * 100.0 * self.f_number * self.f_number)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [("f_number", self.f_number), ("shutter", self.shutter), ("iso", self.iso)] {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("camera.{} must be positive, got {}", name, value));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Brown-Conrady lens distortion, with the coefficients in OpenCV's order: radial `k1`, `k2`,
+/// then tangential `p1`, `p2`, then radial `k3`. Image positions are measured from the centre,
+/// with the top and bottom edges at y = 1 and -1.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Distortion {
+    pub k1: f32,
+    pub k2: f32,
+    pub p1: f32,
+    pub p2: f32,
+    pub k3: f32,
+}
+
+impl Distortion {
+    /// Where the lens puts a point that an ideal lens would put at (x, y).
+    pub fn distort(&self, x: f32, y: f32) -> (f32, f32) {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        let dx = 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x);
+        let dy = self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y;
+        (x * radial + dx, y * radial + dy)
+    }
+
+    /// Where an ideal lens would put what the lens puts at (x, y): the inverse of `distort`,
+    /// found by fixed-point iteration, which converges for the mild distortion of most lenses.
+    /// None if nothing lands at (x, y), as in the corners of strong barrel distortion.
+    pub fn undistort(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        let (mut ux, mut uy) = (x, y);
+        for _ in 0..20 {
+            let (dx, dy) = self.distort(ux, uy);
+            ux += x - dx;
+            uy += y - dy;
+        }
+        let (dx, dy) = self.distort(ux, uy);
+        ((dx - x).abs() < 1e-4 && (dy - y).abs() < 1e-4).then_some((ux, uy))
+    }
+}
+
+/// The imperfections of a real lens, applied to the rays of any camera model.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LensEffects {
+    pub distortion: Option<Distortion>,
+    /// Relative change in focal length for the red, green and blue channels, so each is
+    /// magnified differently and colours fringe towards the edges of the image.
+    pub chromatic_aberration: Option<[f32; 3]>,
+    /// Darken towards the edges by the cosine to the fourth of the angle off the view direction.
+    pub vignetting: bool,
+}
+
+impl LensEffects {
+    /// The ray for (s, t) on the image through `camera`, which looks along `forward`, with the
+    /// weight of each channel it carries.
+    pub fn get_ray(&self, camera: &Camera, s: f32, t: f32, aspect_ratio: f32, forward: Vec3) -> Option<(Ray, Color)> {
+        if let Camera::Shutter(shutter) = camera {
+            // The time goes by the row of the image, wherever the lens bends the ray.
+            let (moved, time) = shutter.moment(t);
+            let ray = self.get_ray(shutter.camera(), s, t, aspect_ratio, forward);
+            return ray.map(|(r, weight)| (move_ray(r, &moved).with_time(time), weight));
+        }
+        if *self == LensEffects::default() {
+            return camera.get_weighted_ray(s, t).map(|(r, weight)| (r, Color::new(weight, weight, weight)));
+        }
+        if let Camera::Stereo(stereo) = camera {
+            // Each eye has a lens of its own.
+            let (eye, s, t) = stereo.eye(s, t);
+            return self.get_ray(eye, s, t, stereo.layout.eye_aspect_ratio(aspect_ratio), forward);
+        }
+        let (mut x, mut y) = ((2.0 * s - 1.0) * aspect_ratio, 2.0 * t - 1.0);
+        if let Some(distortion) = &self.distortion {
+            (x, y) = distortion.undistort(x, y)?;
+        }
+        let mut weight = Color::new(1.0, 1.0, 1.0);
+        if let Some(shifts) = self.chromatic_aberration {
+            // Trace one channel at a time, chosen at random, and count it three times over.
+            let channel = ((random_f32() * 3.0) as usize).min(2);
+            weight = Color::zero();
+            weight[channel] = 3.0;
+            x /= 1.0 + shifts[channel];
+            y /= 1.0 + shifts[channel];
+        }
+        let (r, falloff) = camera.get_weighted_ray((x / aspect_ratio + 1.0) / 2.0, (y + 1.0) / 2.0)?;
+        weight *= falloff;
+        if self.vignetting {
+            let cos = f32::max(Vec3::dot(&Vec3::unit_vector(&r.dir), &forward), 0.0);
+            weight *= cos * cos * cos * cos;
+        }
+        Some((r, weight))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(d) = &self.distortion {
+            if ![d.k1, d.k2, d.p1, d.p2, d.k3].iter().all(|c| c.is_finite()) {
+                return Err(format!("camera.distortion must be finite, got {:?}", d));
+            }
+        }
+        if let Some(shifts) = self.chromatic_aberration {
+            if !shifts.iter().all(|s| s.is_finite() && *s > -1.0) {
+                return Err(format!("camera.chromatic_aberration shifts must be more than -1, got {:?}", shifts));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[enum_dispatch(CameraModel)]
+#[derive(Clone, Debug)]
+pub enum Camera {
+    Perspective,
+    Orthographic,
+    Equirectangular,
+    Fisheye,
+    LensCamera,
+    Calibrated,
+    Stereo,
+    Shutter,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let lookfrom = Point3::new(0.0, 0.0, 10.0);
+        let camera: Camera = Orthographic::new(lookfrom, Point3::zero(), Vec3::new(0.0, 1.0, 0.0), 4.0, 2.0).into();
+        let centre = camera.get_ray(0.5, 0.5).unwrap();
+        let corner = camera.get_ray(1.0, 1.0).unwrap();
+        assert_eq!(centre.orig, lookfrom);
+        assert_eq!(corner.orig, Point3::new(4.0, 2.0, 10.0));
+        assert_eq!(centre.dir, corner.dir);
+        assert_eq!(centre.dir, Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-5
+    }
+
+    #[test]
+    fn panoramic_rays_cover_the_sphere() {
+        let (lookfrom, lookat, vup) = (Point3::zero(), Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0));
+        let camera: Camera = Equirectangular::new(lookfrom, lookat, vup).into();
+        let dir = |s, t| camera.get_ray(s, t).unwrap().dir;
+        assert!(close(dir(0.5, 0.5), Vec3::new(0.0, 0.0, -1.0)));
+        assert!(close(dir(0.75, 0.5), Vec3::new(1.0, 0.0, 0.0)));
+        assert!(close(dir(0.0, 0.5), Vec3::new(0.0, 0.0, 1.0)));
+        assert!(close(dir(0.3, 1.0), Vec3::new(0.0, 1.0, 0.0)));
+
+        // A 180 degree fisheye sees straight sideways at the edge of its circle, and nothing
+        // in the corners.
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let camera: Camera = Fisheye::new(lookfrom, lookat, vup, 180.0, mapping, 2.0).into();
+            assert!(close(camera.get_ray(0.5, 0.5).unwrap().dir, Vec3::new(0.0, 0.0, -1.0)));
+            assert!(close(camera.get_ray(0.75, 0.5).unwrap().dir, Vec3::new(1.0, 0.0, 0.0)));
+            assert!(close(camera.get_ray(0.5, 0.0).unwrap().dir, Vec3::new(0.0, -1.0, 0.0)));
+            assert!(camera.get_ray(0.0, 0.0).is_none());
+        }
+        // Half way out, equisolid bends the view less than equidistant.
+        let equisolid: Camera = Fisheye::new(lookfrom, lookat, vup, 180.0, FisheyeMapping::Equisolid, 1.0).into();
+        assert!(equisolid.get_ray(0.75, 0.5).unwrap().dir.x < f32::sin(PI / 4.0));
+    }
+
+    #[test]
+    fn shifts_and_tilts_the_view() {
+        let (lookfrom, lookat, vup) = (Point3::zero(), Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0));
+        let camera = Perspective::new(lookfrom, lookat, vup, 90.0, 1.0, 0.0, 1.0);
+        // Shifted up by half the image, the centre looks where the top edge did, and the view
+        // direction hasn't turned.
+        let shifted = camera.clone().with_shift(0.0, 0.5);
+        assert!(close(shifted.get_ray(0.5, 0.5).unwrap().dir, camera.get_ray(0.5, 1.0).unwrap().dir));
+
+        // Tilted 45 degrees, the plane of focus through (0, 0, -2) passes through (0, 1, -3):
+        // rays from all over the lens meet there.
+        let tilted = Perspective::new(lookfrom, lookat, vup, 90.0, 1.0, 1.0, 2.0).with_tilt(45.0, 0.0);
+        let t = 0.5 + 0.5 * (1.0 / 3.0);
+        for _ in 0..10 {
+            let r = tilted.get_ray(0.5, t).unwrap();
+            let k = (-3.0 - r.orig.z) / r.dir.z;
+            assert!(close(r.at(k), Point3::new(0.0, 1.0, -3.0)));
+        }
+    }
+
+    #[test]
+    fn lens_effects_bend_and_darken_rays() {
+        let d = Distortion { k1: -0.2, k2: 0.05, p1: 0.01, p2: -0.005, k3: 0.01 };
+        let (x, y) = d.distort(0.8, -0.6);
+        let (ux, uy) = d.undistort(x, y).unwrap();
+        assert!((ux - 0.8).abs() < 1e-5 && (uy + 0.6).abs() < 1e-5);
+
+        // Barrel distortion (negative k1) pulls more of the scene into the corner of the image.
+        let (lookfrom, lookat, vup) = (Point3::zero(), Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0));
+        let camera: Camera = Perspective::new(lookfrom, lookat, vup, 90.0, 1.0, 0.0, 1.0).into();
+        let forward = Vec3::new(0.0, 0.0, -1.0);
+        let barrel = LensEffects { distortion: Some(Distortion { k1: -0.05, ..Default::default() }), ..Default::default() };
+        let (r, _) = barrel.get_ray(&camera, 1.0, 1.0, 1.0, forward).unwrap();
+        assert!(r.dir.x / -r.dir.z > 1.0);
+
+        // At 45 degrees off axis, vignetting leaves a quarter of the light.
+        let vignetting = LensEffects { vignetting: true, ..Default::default() };
+        let (_, weight) = vignetting.get_ray(&camera, 1.0, 0.5, 1.0, forward).unwrap();
+        assert!((weight - Color::new(0.25, 0.25, 0.25)).length() < 1e-5);
+
+        // Each ray carries one channel, counted three times over.
+        let fringed = LensEffects { chromatic_aberration: Some([0.01, 0.0, -0.01]), ..Default::default() };
+        let (_, weight) = fringed.get_ray(&camera, 0.9, 0.9, 1.0, forward).unwrap();
+        assert_eq!(weight.x + weight.y + weight.z, 3.0);
+    }
+
+    #[test]
+    fn stereo_eyes_see_from_either_side() {
+        let (lookfrom, lookat, vup) = (Point3::zero(), Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0));
+        let eye = |offset: f32| -> Camera {
+            let shift = -offset / (4.0 * 2.0);
+            let origin = Point3::new(offset, 0.0, 0.0);
+            Perspective::new(origin, origin + lookat, vup, 90.0, 1.0, 0.0, 1.0).with_shift(shift, 0.0).into()
+        };
+        // Side by side, each eye's view centres on the point 4 ahead of the middle of the rig.
+        let stereo = Stereo::new(eye(-0.5), eye(0.5), StereoLayout::SideBySide);
+        let ahead = Point3::new(0.0, 0.0, -4.0);
+        for (s, origin_x) in [(0.25, -0.5), (0.75, 0.5)] {
+            let r = stereo.get_ray(s, 0.5).unwrap();
+            assert!((r.orig.x - origin_x).abs() < 1e-6);
+            let to = ahead - r.orig;
+            assert!(Vec3::cross(&Vec3::unit_vector(&r.dir), &Vec3::unit_vector(&to)).length() < 1e-5);
+        }
+
+        // Omnidirectional stereo: looking ahead the left eye is on the left, and looking right
+        // it is in front.
+        let left = Equirectangular::new(lookfrom, lookat, vup).with_eye_offset(-0.5);
+        assert!((left.get_ray(0.5, 0.5).unwrap().orig - Point3::new(-0.5, 0.0, 0.0)).length() < 1e-6);
+        assert!((left.get_ray(0.75, 0.5).unwrap().orig - Point3::new(0.0, 0.0, -0.5)).length() < 1e-6);
+        assert_eq!(StereoLayout::TopBottom.eye_aspect_ratio(1.0), 2.0);
+    }
+
+    #[test]
+    fn calibrated_camera_sees_what_opencv_projects() {
+        // Non-square pixels, an off-centre principal point and some distortion.
+        let k = Mat3::from_rows(Vec3::new(800.0, 0.0, 300.0), Vec3::new(0.0, 600.0, 200.0), Vec3::new(0.0, 0.0, 1.0));
+        let rotation = Mat3::rodrigues(Vec3::new(0.1, -0.4, 0.05));
+        let translation = Vec3::new(0.5, -0.2, 6.0);
+        let mut camera = Calibrated::from_opencv(&k, 640.0, 480.0, &rotation, translation);
+        let point = Point3::new(0.7, 0.4, -0.3);
+        for distortion in [None, Some(Distortion { k1: -0.1, k2: 0.02, p1: 0.001, p2: -0.002, k3: 0.0 })] {
+            camera.intrinsics.distortion = distortion;
+            // Project the point as OpenCV does, to a pixel measured from the top left pixel's centre.
+            let p = rotation * point + translation;
+            let (x, y) = (p.x / p.z, p.y / p.z);
+            let (x, y) = distortion.map_or((x, y), |d| d.distort(x, y));
+            let (px, py) = (800.0 * x + 300.0, 600.0 * y + 200.0);
+            let r = camera.get_ray((px + 0.5) / 640.0, 1.0 - (py + 0.5) / 480.0).unwrap();
+            let to = Vec3::unit_vector(&(point - r.orig));
+            assert!(Vec3::cross(&Vec3::unit_vector(&r.dir), &to).length() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn exposure_follows_the_reciprocity_law() {
+        // The sunny 16 rule: at f/16 and a shutter of 1/ISO, a sunlit grey card (about 5000
+        // cd/m^2) comes out in the middle of the range.
+        let sunny = Exposure { f_number: 16.0, shutter: 0.01, iso: 100.0 };
+        assert!((0.1..0.5).contains(&(5000.0 * sunny.scale())));
+        // A stop down with twice the shutter time, or a stop down at twice the ISO, gives the
+        // same exposure.
+        let slower = Exposure { f_number: 16.0 * f32::sqrt(2.0), shutter: 0.02, iso: 100.0 };
+        let faster_film = Exposure { f_number: 16.0 * f32::sqrt(2.0), shutter: 0.01, iso: 200.0 };
+        assert!((slower.scale() - sunny.scale()).abs() < 1e-9);
+        assert!((faster_film.scale() - sunny.scale()).abs() < 1e-9);
+        assert!(Exposure { iso: 0.0, ..sunny }.validate().is_err());
     }
 }
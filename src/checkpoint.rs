@@ -0,0 +1,222 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::film::*;
+use crate::render::RenderSettings;
+use crate::scene::Scene;
+use crate::util::fnv1a;
+use crate::vec3::*;
+
+const MAGIC: &[u8; 8] = b"RTCHECK2";
+
+/// The size of a pixel as `write_pixel` writes it.
+pub(crate) const PIXEL_BYTES: usize = 4 * 12 + 4 + 4 + 4 + 8;
+
+/// Everything needed to carry on a progressive render: the accumulated film (radiance and AOV
+/// sums with their sample counts) and where in the sequence of passes it got to.
+pub struct Checkpoint {
+    pub seed: u64,
+    pub samples_per_pass: u32,
+    /// Passes finished so far, which picks the random streams for the next one.
+    pub passes: u32,
+    /// What the film is a render of, as `Checkpoint::fingerprint` has it.
+    pub fingerprint: u64,
+    pub film: Film,
+}
+
+impl Checkpoint {
+    /// Samples per pixel accumulated so far. Checkpoints are taken between passes, so every
+    /// pixel has the same number.
+    pub fn samples(&self) -> u32 {
+        self.film.pixels.first().map_or(0, |p| p.samples)
+    }
+
+    /// A hash of what decides a render's samples besides the seed and passes: the scene, the
+    /// camera (after any command-line changes) and how deep rays go.
+    pub fn fingerprint(scene: &Scene, settings: &RenderSettings) -> u64 {
+        let camera = toml::to_string(&scene.camera).expect("cameras are made of tables, strings and floats");
+        let text = format!("scene = {}
+max_depth = {}
+{}", scene.fingerprint(), settings.image.max_depth, camera);
+        fnv1a(text.as_bytes())
+    }
+
+    /// Check that the checkpoint can be continued with `scene` and `settings`.
+    pub fn check(&self, scene: &Scene, settings: &RenderSettings) -> Result<(), String> {
+        let (width, height) = (settings.image.width, settings.image.height());
+        if (self.film.width, self.film.height) != (width, height) {
+            return Err(format!(
+                "checkpoint is {}x{} but the render is {}x{}",
+                self.film.width, self.film.height, width, height
+            ));
+        }
+        if self.seed != settings.seed || self.samples_per_pass != settings.samples_per_pass {
+            return Err(format!(
+                "checkpoint was rendered with seed {} and {} samples per pass, not seed {} and {}",
+                self.seed, self.samples_per_pass, settings.seed, settings.samples_per_pass
+            ));
+        }
+        if self.fingerprint != Checkpoint::fingerprint(scene, settings) {
+            return Err("checkpoint was rendered from a different scene, camera or max depth".to_string());
+        }
+        Ok(())
+    }
+
+    /// Write to a temporary file next to `path` and rename it into place, so a render killed
+    /// mid-write leaves the previous checkpoint intact.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".partial");
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&temp, path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Checkpoint> {
+        Checkpoint::read(&fs::read(path)?)
+    }
+
+    // Little-endian: a header, then every pixel's sums from the top row down.
+    fn write(&self, w: &mut dyn Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&(self.film.width as u64).to_le_bytes())?;
+        w.write_all(&(self.film.height as u64).to_le_bytes())?;
+        w.write_all(&self.seed.to_le_bytes())?;
+        w.write_all(&self.samples_per_pass.to_le_bytes())?;
+        w.write_all(&self.passes.to_le_bytes())?;
+        w.write_all(&self.fingerprint.to_le_bytes())?;
+        for p in &self.film.pixels {
+            write_pixel(w, p)?;
+        }
+        Ok(())
+    }
+
+    fn read(bytes: &[u8]) -> io::Result<Checkpoint> {
+        let r = &mut &bytes[..];
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a render checkpoint"));
+        }
+        let width = read_u64(r)? as usize;
+        let height = read_u64(r)? as usize;
+        let seed = read_u64(r)?;
+        let samples_per_pass = read_u32(r)?;
+        let passes = read_u32(r)?;
+        let fingerprint = read_u64(r)?;
+        // Check the header against the rest of the file before making room for the film.
+        if width.checked_mul(height).and_then(|n| n.checked_mul(PIXEL_BYTES)) != Some(r.len()) {
+            let message = format!("checkpoint is cut short or corrupt: {}x{} pixels don't fit in it", width, height);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+
+        let mut film = Film::new(width, height);
+        for p in film.pixels.iter_mut() {
+            *p = read_pixel(r)?;
+        }
+
+        Ok(Checkpoint {
+            seed,
+            samples_per_pass,
+            passes,
+            fingerprint,
+            film,
+        })
+    }
+}
+
+// Pixel sums are stored exactly, so a film read back accumulates just as the original would.
+// Also used for tiles sent between distributed render processes.
+pub(crate) fn write_pixel(w: &mut dyn Write, p: &Pixel) -> io::Result<()> {
+    for v in [p.color, p.albedo, p.normal, p.position] {
+        write_vec3(w, v)?;
+    }
+    w.write_all(&p.depth.to_le_bytes())?;
+    w.write_all(&p.samples.to_le_bytes())?;
+    w.write_all(&p.hits.to_le_bytes())?;
+    w.write_all(&p.object_id.map_or(u64::MAX, |id| id as u64).to_le_bytes())
+}
+
+pub(crate) fn read_pixel(r: &mut dyn Read) -> io::Result<Pixel> {
+    Ok(Pixel {
+        color: read_vec3(r)?,
+        albedo: read_vec3(r)?,
+        normal: read_vec3(r)?,
+        position: read_vec3(r)?,
+        depth: read_f32(r)?,
+        samples: read_u32(r)?,
+        hits: read_u32(r)?,
+        object_id: match read_u64(r)? {
+            u64::MAX => None,
+            id => Some(id as usize),
+        },
+    })
+}
+
+pub(crate) fn write_vec3(w: &mut dyn Write, v: Vec3) -> io::Result<()> {
+    for i in 0..3 {
+        w.write_all(&v[i].to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_u32(r: &mut dyn Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_u64(r: &mut dyn Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_f32(r: &mut dyn Read) -> io::Result<f32> {
+    Ok(f32::from_bits(read_u32(r)?))
+}
+
+pub(crate) fn read_vec3(r: &mut dyn Read) -> io::Result<Vec3> {
+    Ok(Vec3::new(read_f32(r)?, read_f32(r)?, read_f32(r)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let mut film = Film::new(2, 1);
+        film.pixels[0].color = Color::new(1.5, 0.25, 8.0);
+        film.pixels[0].samples = 16;
+        film.pixels[1].object_id = Some(7);
+        film.pixels[1].depth = f32::INFINITY;
+        let checkpoint = Checkpoint {
+            seed: 42,
+            samples_per_pass: 16,
+            passes: 1,
+            fingerprint: 99,
+            film,
+        };
+
+        let mut bytes = vec![];
+        checkpoint.write(&mut bytes).unwrap();
+        let read = Checkpoint::read(&bytes).unwrap();
+        assert_eq!((read.seed, read.samples_per_pass, read.passes, read.fingerprint), (42, 16, 1, 99));
+        assert_eq!(read.film.pixels[0].color, Color::new(1.5, 0.25, 8.0));
+        assert_eq!(read.samples(), 16);
+        assert_eq!(read.film.pixels[1].object_id, Some(7));
+        assert_eq!(read.film.pixels[1].depth, f32::INFINITY);
+
+        assert!(Checkpoint::read(b"P6\n2 1\n255\n").is_err());
+        // A header promising more pixels than follow is refused before the film is allocated.
+        bytes[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(Checkpoint::read(&bytes).is_err());
+        bytes.truncate(bytes.len() - 1);
+        bytes[8..16].copy_from_slice(&2u64.to_le_bytes());
+        assert!(Checkpoint::read(&bytes).is_err());
+    }
+}
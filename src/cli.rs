@@ -0,0 +1,879 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::builder::{PossibleValue, PossibleValuesParser};
+use clap::{Args, Parser, Subcommand};
+
+use raytracing_rust::aperture::*;
+use raytracing_rust::camera::{Distortion, Exposure, FisheyeMapping, StereoLayout, StereoRig};
+use raytracing_rust::denoise::*;
+use raytracing_rust::distributed::DEFAULT_PORT;
+use raytracing_rust::image::*;
+use raytracing_rust::lens::{LensOptions, LensSystem};
+use raytracing_rust::render::*;
+use raytracing_rust::scene::*;
+use raytracing_rust::scenes::*;
+use raytracing_rust::tile::TileOrder;
+use raytracing_rust::util::random_seed;
+use raytracing_rust::vec3::*;
+
+/// A path tracer, following "Ray Tracing in One Weekend".
+///
+/// Renders SCENE (a TOML scene description), a built-in scene, or by default the random-spheres
+/// cover scene.
+/// Settings given on the command line override the ones in the scene file.
+#[derive(Parser, Debug)]
+#[command(version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub render: RenderArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Denoise a saved render using the albedo, normal and depth AOVs written next to it.
+    Denoise {
+        /// Beauty image written with --aovs.
+        input: PathBuf,
+        /// Where to write the result [default: overwrite INPUT].
+        output: Option<PathBuf>,
+    },
+    /// Render tiles for a coordinator started with --listen. Give the same scene as the
+    /// coordinator; the seed and render settings come from it.
+    Worker {
+        /// The coordinator's address, as host or host:port.
+        #[arg(value_parser = parse_address)]
+        coordinator: String,
+
+        #[command(flatten)]
+        source: SceneArgs,
+
+        /// Number of render threads [default: one per CPU].
+        #[arg(short = 'j', long)]
+        threads: Option<usize>,
+    },
+}
+
+/// Which scene to render.
+#[derive(Args, Debug)]
+pub struct SceneArgs {
+    /// Scene description file (TOML).
+    pub scene: Option<PathBuf>,
+
+    /// Render a built-in scene instead of a scene file.
+    #[arg(short, long, value_name = "NAME", conflicts_with = "scene", value_parser = builtin_names())]
+    pub builtin: Option<String>,
+}
+
+impl SceneArgs {
+    /// Load or build the scene. Seed the random generator first, as some scenes are random.
+    pub fn load(&self) -> Result<Scene, String> {
+        match (&self.scene, &self.builtin) {
+            (Some(path), _) => Scene::load(path).map_err(|e| e.to_string()),
+            (None, Some(name)) => Ok((builtin(name).expect("clap checks the name").build)()),
+            (None, None) => Ok(random_spheres()),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct RenderArgs {
+    #[command(flatten)]
+    pub source: SceneArgs,
+
+    /// Output image; the format is chosen by extension (.png, .ppm, .p3, .pfm). With --frames,
+    /// the name for numbered frames, or a .y4m video.
+    #[arg(short, long, default_value = "image.ppm")]
+    pub output: PathBuf,
+
+    /// Output format, overriding the extension: png, png16, ppm (binary P6), p3 (ASCII) or pfm.
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<ImageFormat>,
+
+    /// Image width in pixels.
+    #[arg(short, long)]
+    pub width: Option<usize>,
+
+    /// Image height in pixels. Sets the aspect ratio together with the width.
+    #[arg(long, conflicts_with = "aspect_ratio")]
+    pub height: Option<usize>,
+
+    /// Image aspect ratio (width / height), e.g. 1.5 or 16:9.
+    #[arg(long, value_parser = parse_aspect_ratio)]
+    pub aspect_ratio: Option<f32>,
+
+    /// Samples per pixel.
+    #[arg(short, long = "spp")]
+    pub samples_per_pixel: Option<u32>,
+
+    /// Maximum number of bounces per path.
+    #[arg(short = 'd', long)]
+    pub max_depth: Option<i32>,
+
+    /// Random seed, for reproducible renders [default: random].
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Refine the image in passes of this many samples per pixel.
+    #[arg(long, default_value_t = 16)]
+    pub samples_per_pass: u32,
+
+    /// Save the accumulated samples to PATH between passes, so the render can be resumed.
+    #[arg(long, value_name = "PATH")]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Minimum time between checkpoints, e.g. 90s or 10m; one is always saved at the end.
+    #[arg(long, value_name = "DURATION", default_value = "5m", value_parser = parse_duration)]
+    pub checkpoint_interval: Duration,
+
+    /// Carry on from a checkpoint up to --spp samples, using its seed and pass size. Later
+    /// checkpoints go to the same file unless --checkpoint says otherwise.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["seed", "samples_per_pass"])]
+    pub resume: Option<PathBuf>,
+
+    /// Number of render threads [default: one per CPU].
+    #[arg(short = 'j', long)]
+    pub threads: Option<usize>,
+
+    /// Render in square tiles this many pixels across.
+    #[arg(long, default_value_t = 32)]
+    pub tile_size: usize,
+
+    /// Order to render tiles in: spiral (from the centre out), hilbert or scanline.
+    #[arg(long, default_value = "spiral", value_parser = parse_tile_order)]
+    pub tile_order: TileOrder,
+
+    /// Write the partial image to the output file this often while rendering, e.g. 30s.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub flush_interval: Option<Duration>,
+
+    /// Coordinate a distributed render instead of rendering here: hand out tiles to workers
+    /// (see the worker command) connecting on ADDR, as port, host or host:port.
+    #[arg(long, value_name = "ADDR", value_parser = parse_address, conflicts_with_all = ["resume", "checkpoint"])]
+    pub listen: Option<String>,
+
+    /// Render as many passes as fit in this time, e.g. 10m or 1h30m. Without --spp there is no
+    /// other limit on the number of samples.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub time_limit: Option<Duration>,
+
+    /// Serve a live preview of the render over HTTP, with its progress and a stop button, on
+    /// ADDR as port, host or host:port.
+    #[arg(long, value_name = "ADDR", num_args = 0..=1, default_missing_value = "127.0.0.1:8000", value_parser = parse_preview_address)]
+    pub preview: Option<String>,
+
+    /// Render the scene's animation: every frame, FIRST-LAST, or one frame. Each frame is written
+    /// to the output name numbered in place of its run of #s (or before the extension if
+    /// there are none), or into a single video stream if the output ends in .y4m.
+    #[arg(
+        long,
+        value_name = "RANGE",
+        num_args = 0..=1,
+        default_missing_value = "all",
+        value_parser = parse_frames,
+        conflicts_with_all = ["resume", "checkpoint", "listen", "flush_interval"]
+    )]
+    pub frames: Option<FrameRange>,
+
+    /// Render the scene's named cameras, all of them or those listed, into one output each,
+    /// named after the camera, e.g. shot_front.png for -o shot.png.
+    #[arg(
+        long,
+        value_name = "NAMES",
+        num_args = 0..=1,
+        value_delimiter = ',',
+        conflicts_with_all = ["frames", "resume", "checkpoint", "listen", "flush_interval"]
+    )]
+    pub cameras: Option<Vec<String>>,
+
+    /// Also write the albedo, normal, depth, position and object ID passes next to the output.
+    #[arg(long)]
+    pub aovs: bool,
+
+    /// Denoise the image before writing it, guided by the AOVs.
+    #[arg(long)]
+    pub denoise: bool,
+
+    #[command(flatten)]
+    pub camera: CameraArgs,
+}
+
+/// Frames to render, counting from 0. No `last` means through the end of the animation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameRange {
+    pub first: u32,
+    pub last: Option<u32>,
+}
+
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Camera")]
+pub struct CameraArgs {
+    /// Camera position, as x,y,z.
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub lookfrom: Option<Point3>,
+
+    /// Point the camera looks at, as x,y,z.
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub lookat: Option<Point3>,
+
+    /// Camera up direction, as x,y,z.
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub vup: Option<Vec3>,
+
+    /// Vertical field of view in degrees, switching to a perspective projection if need be.
+    #[arg(long)]
+    pub vfov: Option<f32>,
+
+    /// Use an orthographic projection, showing this many scene units from the bottom of the
+    /// image to the top.
+    #[arg(long, value_name = "VIEW_HEIGHT", conflicts_with = "vfov")]
+    pub orthographic: Option<f32>,
+
+    /// Capture every direction around the camera, as a 2:1 longitude-latitude panorama.
+    #[arg(long, conflicts_with_all = ["vfov", "orthographic"])]
+    pub equirectangular: bool,
+
+    /// Use a fisheye lens covering this many degrees across its image circle.
+    #[arg(long, value_name = "FOV", conflicts_with_all = ["vfov", "orthographic", "equirectangular"])]
+    pub fisheye: Option<f32>,
+
+    /// How the fisheye maps angles to its image: equidistant or equisolid.
+    #[arg(long, value_name = "MAPPING", default_value = "equidistant", value_parser = parse_fisheye_mapping)]
+    pub fisheye_mapping: FisheyeMapping,
+
+    /// Trace rays through the lens described by this prescription file.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["vfov", "orthographic", "equirectangular", "fisheye"])]
+    pub lens: Option<PathBuf>,
+
+    /// Narrow the lens camera's aperture stop to this diameter, in millimetres.
+    #[arg(long, value_name = "MM")]
+    pub lens_aperture: Option<f32>,
+
+    /// Lens aperture diameter; 0 disables depth of field.
+    #[arg(long)]
+    pub aperture: Option<f32>,
+
+    /// Shift the lens across and up by fractions of the image size, as x,y, to move the view
+    /// without turning the camera.
+    #[arg(long, value_name = "X,Y", value_parser = parse_pair, allow_hyphen_values = true)]
+    pub shift: Option<(f32, f32)>,
+
+    /// Tilt the plane of focus this many degrees about the horizontal, top away from the camera.
+    #[arg(long, value_name = "DEGREES", allow_hyphen_values = true)]
+    pub tilt: Option<f32>,
+
+    /// Swing the plane of focus this many degrees about the vertical, right side away.
+    #[arg(long, value_name = "DEGREES", allow_hyphen_values = true)]
+    pub swing: Option<f32>,
+
+    /// Make this a physical camera with this f-number, which sets the aperture and, with
+    /// --shutter and --iso, the exposure. Light is taken to be in candela per square metre.
+    #[arg(long, value_name = "N", conflicts_with = "aperture")]
+    pub f_number: Option<f32>,
+
+    /// A physical camera's shutter time, in seconds or as a fraction such as 1/125. Moving
+    /// objects blur over it.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_shutter)]
+    pub shutter: Option<f32>,
+
+    /// A physical camera's sensitivity.
+    #[arg(long)]
+    pub iso: Option<f32>,
+
+    /// Expose the rows one after another from the top, starting over this many seconds, as a
+    /// CMOS sensor's rolling shutter does. Moving objects and cameras skew.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_shutter)]
+    pub rolling_shutter: Option<f32>,
+
+    /// Shape the aperture as a polygon with this many blades, for polygonal bokeh.
+    #[arg(long, value_name = "BLADES")]
+    pub aperture_blades: Option<u32>,
+
+    /// Turn the polygonal aperture this many degrees anticlockwise.
+    #[arg(long, value_name = "DEGREES", allow_hyphen_values = true)]
+    pub aperture_rotation: Option<f32>,
+
+    /// Shape the aperture as this greyscale image, bright where light passes.
+    #[arg(long, value_name = "PATH", conflicts_with = "aperture_blades")]
+    pub aperture_mask: Option<PathBuf>,
+
+    /// Distance from the camera to the plane of perfect focus.
+    #[arg(long)]
+    pub focus_dist: Option<f32>,
+
+    /// Focus on whatever is seen through the middle of the image, or through the point S,T
+    /// from 0,0 at the bottom left to 1,1 at the top right.
+    #[arg(long, value_name = "S,T", num_args = 0..=1, default_missing_value = "0.5,0.5", value_parser = parse_pair,
+          conflicts_with = "focus_dist")]
+    pub autofocus: Option<(f32, f32)>,
+
+    /// Focus on the middle of the object with this name.
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["focus_dist", "autofocus"])]
+    pub focus_on: Option<String>,
+
+    /// Distort the image like a real lens, with Brown-Conrady coefficients k1,k2,p1,p2[,k3] in
+    /// OpenCV's order, measured with the image's half height as 1 (or as OpenCV measures them,
+    /// for a calibrated camera).
+    #[arg(long, value_name = "K1,K2,P1,P2[,K3]", value_parser = parse_distortion, allow_hyphen_values = true)]
+    pub distortion: Option<Distortion>,
+
+    /// Fringe colours towards the edges by shifting the focal length of the red, green and
+    /// blue channels by these fractions, e.g. 0.003,0,-0.003.
+    #[arg(long, value_name = "R,G,B", value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub chromatic_aberration: Option<Vec3>,
+
+    /// Darken the edges of the image as a real lens does, by cos^4 of the angle off axis.
+    #[arg(long)]
+    pub vignetting: bool,
+
+    /// Render a view for each eye, side-by-side or top-bottom in the one image. With
+    /// --equirectangular, this is omnidirectional stereo for 360 video.
+    #[arg(long, value_name = "LAYOUT", value_parser = parse_stereo_layout)]
+    pub stereo: Option<StereoLayout>,
+
+    /// The distance between the eyes of a stereo camera, in scene units [default: 0.064].
+    #[arg(long, value_name = "DISTANCE")]
+    pub eye_separation: Option<f32>,
+
+    /// The distance at which a perspective stereo camera's views meet, so what is there appears
+    /// at the depth of the screen [default: the focus distance].
+    #[arg(long, value_name = "DISTANCE")]
+    pub convergence: Option<f32>,
+}
+
+impl RenderArgs {
+    /// The scene's render settings with the command-line overrides applied.
+    pub fn settings(&self, scene: &Scene) -> RenderSettings {
+        let mut image = scene.image;
+        // A new width keeps the scene's aspect ratio rather than its height.
+        if let Some(width) = self.width {
+            image.aspect_ratio = image.aspect();
+            image.width = width;
+            image.height = None;
+        }
+        if let Some(height) = self.height {
+            image.aspect_ratio = image.width as f32 / height as f32;
+            image.height = Some(height);
+        }
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            image.aspect_ratio = aspect_ratio;
+            image.height = None;
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            image.samples_per_pixel = Some(samples_per_pixel);
+        } else if self.time_limit.is_some() {
+            image.samples_per_pixel = None;
+        }
+        if let Some(max_depth) = self.max_depth {
+            image.max_depth = max_depth;
+        }
+
+        RenderSettings {
+            image,
+            seed: self.seed.unwrap_or_else(random_seed),
+            samples_per_pass: self.samples_per_pass,
+            tile_size: self.tile_size,
+            tile_order: self.tile_order,
+        }
+    }
+
+    /// Apply the command-line camera overrides to a loaded scene.
+    pub fn apply_camera(&self, camera: &mut CameraSettings) -> Result<(), String> {
+        let args = &self.camera;
+        camera.lookfrom = args.lookfrom.unwrap_or(camera.lookfrom);
+        camera.lookat = args.lookat.unwrap_or(camera.lookat);
+        camera.vup = args.vup.unwrap_or(camera.vup);
+        if let Some(vfov) = args.vfov {
+            camera.projection = Projection::Perspective;
+            camera.vfov = vfov;
+        }
+        if let Some(view_height) = args.orthographic {
+            camera.projection = Projection::Orthographic { view_height };
+        }
+        if args.equirectangular {
+            camera.projection = Projection::Equirectangular;
+        }
+        if let Some(fov) = args.fisheye {
+            camera.projection = Projection::Fisheye { fov, mapping: args.fisheye_mapping };
+        }
+        if let Some(path) = &args.lens {
+            let system = Arc::new(LensSystem::load(path)?);
+            let options = match &camera.projection {
+                Projection::Lens { options, .. } => *options,
+                _ => LensOptions::default(),
+            };
+            camera.projection = Projection::Lens { system, options };
+        }
+        if let Some(diameter) = args.lens_aperture {
+            let Projection::Lens { options, .. } = &mut camera.projection else {
+                return Err("--lens-aperture only applies to a lens camera".to_string());
+            };
+            options.aperture_diameter = Some(diameter);
+        }
+        camera.aperture = args.aperture.unwrap_or(camera.aperture);
+        camera.shift = args.shift.unwrap_or(camera.shift);
+        camera.tilt = (args.tilt.unwrap_or(camera.tilt.0), args.swing.unwrap_or(camera.tilt.1));
+        if args.f_number.is_some() || args.shutter.is_some() || args.iso.is_some() {
+            // Settings not given keep the scene's, if it has a physical camera.
+            let scene = camera.exposure;
+            let setting = |arg: Option<f32>, get: fn(&Exposure) -> f32, flag: &str| {
+                arg.or(scene.as_ref().map(get)).ok_or(format!("{} is needed for a physical camera", flag))
+            };
+            camera.exposure = Some(Exposure {
+                f_number: setting(args.f_number, |e| e.f_number, "--f-number")?,
+                shutter: setting(args.shutter, |e| e.shutter, "--shutter")?,
+                iso: setting(args.iso, |e| e.iso, "--iso")?,
+            });
+        }
+        camera.rolling_shutter = args.rolling_shutter.or(camera.rolling_shutter);
+        if args.aperture.is_some() && camera.exposure.is_some() {
+            return Err("--aperture doesn't apply to a physical camera; use --f-number".to_string());
+        }
+        if let Some(blades) = args.aperture_blades {
+            camera.aperture_shape = ApertureShape::Polygon { blades, rotation: 0.0 };
+        }
+        if let Some(path) = &args.aperture_mask {
+            camera.aperture_shape = ApertureShape::Mask(Arc::new(ApertureMask::load(path)?));
+        }
+        if let Some(degrees) = args.aperture_rotation {
+            let ApertureShape::Polygon { rotation, .. } = &mut camera.aperture_shape else {
+                return Err("--aperture-rotation only applies to a polygonal aperture".to_string());
+            };
+            *rotation = degrees;
+        }
+        if let Some(focus_dist) = args.focus_dist {
+            camera.focus_dist = focus_dist;
+            camera.autofocus = None;
+        }
+        if let Some(distortion) = args.distortion {
+            match &mut camera.projection {
+                Projection::Calibrated(intrinsics) => intrinsics.distortion = Some(distortion),
+                _ => camera.effects.distortion = Some(distortion),
+            }
+        }
+        if let Some(shifts) = args.chromatic_aberration {
+            camera.effects.chromatic_aberration = Some([shifts.x, shifts.y, shifts.z]);
+        }
+        camera.effects.vignetting |= args.vignetting;
+        if let Some(layout) = args.stereo {
+            let rig = camera.stereo.get_or_insert(StereoRig { layout, eye_separation: 0.064, convergence: None });
+            rig.layout = layout;
+        }
+        if let Some(distance) = args.eye_separation {
+            let Some(rig) = &mut camera.stereo else {
+                return Err("--eye-separation only applies to a stereo camera; add --stereo".to_string());
+            };
+            rig.eye_separation = distance;
+        }
+        if let Some(distance) = args.convergence {
+            let Some(rig) = &mut camera.stereo else {
+                return Err("--convergence only applies to a stereo camera; add --stereo".to_string());
+            };
+            rig.convergence = Some(distance);
+        }
+        if let Some((s, t)) = args.autofocus {
+            camera.autofocus = Some(Autofocus::Point(s, t));
+        }
+        if let Some(name) = &args.focus_on {
+            camera.autofocus = Some(Autofocus::Object(name.clone()));
+        }
+        Ok(())
+    }
+
+    /// Where to save checkpoints, if anywhere.
+    pub fn checkpoint_path(&self) -> Option<&PathBuf> {
+        self.checkpoint.as_ref().or(self.resume.as_ref())
+    }
+
+    pub fn format(&self) -> Result<ImageFormat, String> {
+        self.format
+            .or_else(|| ImageFormat::from_path(&self.output))
+            .ok_or_else(|| format!("Unsupported output format: {} (try --format)", self.output.display()))
+    }
+
+    /// Whether frames go into a video stream rather than separate images.
+    pub fn is_video(&self) -> bool {
+        self.output.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("y4m"))
+    }
+
+    /// Where to write one frame of a sequence, e.g. `frame_####.png` becomes `frame_0012.png`
+    /// and `orbit.png` becomes `orbit_0012.png`.
+    pub fn frame_path(&self, frame: u32) -> PathBuf {
+        let name = self.output.file_name().and_then(|name| name.to_str()).unwrap_or("frame");
+        let name = match name.find('#') {
+            Some(start) => {
+                let width = name[start..].chars().take_while(|&c| c == '#').count();
+                format!("{}{:0width$}{}", &name[..start], frame, &name[start + width..], width = width)
+            }
+            None => with_suffix(name, &format!("{:04}", frame)),
+        };
+        self.output.with_file_name(name)
+    }
+
+    /// Where to write the view through a named camera, e.g. `shot.png` becomes `shot_front.png`.
+    pub fn camera_path(&self, camera: &str) -> PathBuf {
+        let name = self.output.file_name().and_then(|name| name.to_str()).unwrap_or("camera");
+        self.output.with_file_name(with_suffix(name, camera))
+    }
+
+    pub fn denoiser(&self) -> Option<Denoiser> {
+        self.denoise.then(Denoiser::default)
+    }
+}
+
+fn builtin_names() -> PossibleValuesParser {
+    PossibleValuesParser::new(BUILTIN_SCENES.iter().map(|scene| PossibleValue::new(scene.name).help(scene.description)))
+}
+
+fn parse_format(s: &str) -> Result<ImageFormat, String> {
+    ImageFormat::from_name(s).ok_or_else(|| format!("unknown format `{}`", s))
+}
+
+// Fill in whatever's missing from host:port. A bare port listens on all interfaces.
+fn parse_address(s: &str) -> Result<String, String> {
+    complete_address(s, "0.0.0.0", DEFAULT_PORT)
+}
+
+// As parse_address, but the preview is only for this machine unless asked otherwise.
+fn parse_preview_address(s: &str) -> Result<String, String> {
+    complete_address(s, "127.0.0.1", 8000)
+}
+
+fn complete_address(s: &str, host: &str, port: u16) -> Result<String, String> {
+    if s.is_empty() {
+        return Err("empty address".to_string());
+    }
+    if s.parse::<u16>().is_ok() {
+        return Ok(format!("{}:{}", host, s));
+    }
+    match s.rsplit_once(':') {
+        Some((_, port)) => port.parse::<u16>().map(|_| s.to_string()).map_err(|_| format!("bad port in `{}`", s)),
+        None => Ok(format!("{}:{}", s, port)),
+    }
+}
+
+// `name` with `_suffix` before its extension.
+fn with_suffix(name: &str, suffix: &str) -> String {
+    let path = Path::new(name);
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or(name);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => format!("{}_{}.{}", stem, suffix, ext),
+        None => format!("{}_{}", stem, suffix),
+    }
+}
+
+// `all`, a single frame, FIRST-LAST or FIRST- (to the end).
+fn parse_frames(s: &str) -> Result<FrameRange, String> {
+    let frame = |f: &str| f.trim().parse::<u32>().map_err(|_| format!("`{}` is not a frame number", f));
+    let range = match s.split_once('-') {
+        _ if s == "all" => FrameRange { first: 0, last: None },
+        Some((first, "")) => FrameRange { first: frame(first)?, last: None },
+        Some((first, last)) => FrameRange { first: frame(first)?, last: Some(frame(last)?) },
+        None => FrameRange { first: frame(s)?, last: Some(frame(s)?) },
+    };
+    if range.last.is_some_and(|last| last < range.first) {
+        return Err(format!("`{}` ends before it starts", s));
+    }
+    Ok(range)
+}
+
+// Seconds, or a fraction of a second such as 1/125.
+fn parse_shutter(s: &str) -> Result<f32, String> {
+    let seconds = match s.split_once('/') {
+        Some((n, d)) => n.trim().parse::<f32>().ok().zip(d.trim().parse::<f32>().ok()).map(|(n, d)| n / d),
+        None => s.parse().ok(),
+    };
+    seconds.ok_or_else(|| format!("`{}` is not a shutter time (use seconds or a fraction such as 1/125)", s))
+}
+
+fn parse_fisheye_mapping(s: &str) -> Result<FisheyeMapping, String> {
+    match s {
+        "equidistant" => Ok(FisheyeMapping::Equidistant),
+        "equisolid" => Ok(FisheyeMapping::Equisolid),
+        _ => Err(format!("unknown fisheye mapping `{}` (use equidistant or equisolid)", s)),
+    }
+}
+
+fn parse_stereo_layout(s: &str) -> Result<StereoLayout, String> {
+    match s {
+        "side-by-side" => Ok(StereoLayout::SideBySide),
+        "top-bottom" => Ok(StereoLayout::TopBottom),
+        _ => Err(format!("unknown stereo layout `{}` (use side-by-side or top-bottom)", s)),
+    }
+}
+
+fn parse_tile_order(s: &str) -> Result<TileOrder, String> {
+    TileOrder::from_name(s).ok_or_else(|| format!("unknown tile order `{}`", s))
+}
+
+// Seconds, or a number with units such as 90s, 10m, 1.5h or 1h30m.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    if let Ok(secs) = s.parse::<f64>() {
+        return Duration::try_from_secs_f64(secs).map_err(|_| format!("`{}` is not a valid duration", s));
+    }
+
+    let mut total = 0.0;
+    let mut rest = s.trim();
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(split);
+        let units = tail.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(units);
+        let number: f64 = number.trim().parse().map_err(|_| format!("`{}` is not a valid duration", s))?;
+        total += number
+            * match unit {
+                "ms" => 0.001,
+                "s" => 1.0,
+                "m" => 60.0,
+                "h" => 3600.0,
+                _ => return Err(format!("unknown unit `{}` in `{}` (use s, m or h)", unit, s)),
+            };
+        rest = tail.trim_start();
+    }
+    Duration::try_from_secs_f64(total).map_err(|_| format!("`{}` is not a valid duration", s))
+}
+
+fn parse_vec3(s: &str) -> Result<Vec3, String> {
+    let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+    if parts.len() != 3 {
+        return Err(format!("expected x,y,z but got `{}`", s));
+    }
+    let mut v = Vec3::zero();
+    for (i, part) in parts.iter().enumerate() {
+        v[i] = part.parse().map_err(|_| format!("`{}` is not a number", part))?;
+    }
+    Ok(v)
+}
+
+fn parse_distortion(s: &str) -> Result<Distortion, String> {
+    let c: Vec<f32> = s
+        .split(',')
+        .map(|part| part.trim().parse().map_err(|_| format!("`{}` is not a number", part.trim())))
+        .collect::<Result<_, _>>()?;
+    match c[..] {
+        [k1, k2, p1, p2] => Ok(Distortion { k1, k2, p1, p2, k3: 0.0 }),
+        [k1, k2, p1, p2, k3] => Ok(Distortion { k1, k2, p1, p2, k3 }),
+        _ => Err(format!("expected k1,k2,p1,p2 or k1,k2,p1,p2,k3 but got `{}`", s)),
+    }
+}
+
+fn parse_pair(s: &str) -> Result<(f32, f32), String> {
+    let (x, y) = s.split_once(',').ok_or_else(|| format!("expected x,y but got `{}`", s))?;
+    let number = |part: &str| part.trim().parse().map_err(|_| format!("`{}` is not a number", part.trim()));
+    Ok((number(x)?, number(y)?))
+}
+
+fn parse_aspect_ratio(s: &str) -> Result<f32, String> {
+    match s.split_once(':') {
+        Some((w, h)) => {
+            let w: f32 = w.trim().parse().map_err(|_| format!("`{}` is not a number", w))?;
+            let h: f32 = h.trim().parse().map_err(|_| format!("`{}` is not a number", h))?;
+            Ok(w / h)
+        }
+        None => s.parse().map_err(|_| format!("`{}` is not a number", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli_definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    // The default scene's camera, changed by the camera flags in `args`.
+    fn camera_with(args: &[&str]) -> Result<CameraSettings, String> {
+        let cli = Cli::parse_from(["raytracing-rust"].iter().chain(args));
+        let mut camera = random_spheres().camera;
+        cli.render.apply_camera(&mut camera)?;
+        Ok(camera)
+    }
+
+    #[test]
+    fn parses_render_overrides() {
+        let cli = Cli::parse_from([
+            "raytracing-rust", "scene.toml", "-o", "out.png", "--spp", "16", "--aspect-ratio", "16:9",
+            "--lookfrom", "-1,2.5,3",
+        ]);
+        assert!(cli.command.is_none());
+        assert_eq!(cli.render.source.scene, Some(PathBuf::from("scene.toml")));
+        assert_eq!(cli.render.samples_per_pixel, Some(16));
+        assert_eq!(cli.render.aspect_ratio, Some(16.0 / 9.0));
+        assert_eq!(cli.render.camera.lookfrom, Some(Vec3::new(-1.0, 2.5, 3.0)));
+        assert!(matches!(cli.render.format(), Ok(ImageFormat::Png(_))));
+
+        let cli = Cli::parse_from(["raytracing-rust", "-w", "100", "--height", "366"]);
+        assert_eq!(cli.render.settings(&random_spheres()).image.height(), 366);
+    }
+
+    #[test]
+    fn parses_tile_settings() {
+        let cli = Cli::parse_from(["raytracing-rust", "--tile-order", "hilbert", "--flush-interval", "2.5"]);
+        assert_eq!(cli.render.tile_order, TileOrder::Hilbert);
+        assert_eq!(cli.render.flush_interval, Some(Duration::from_millis(2500)));
+    }
+
+    #[test]
+    fn parses_time_limit() {
+        // A time limit alone takes as many samples as fit in it.
+        let cli = Cli::parse_from(["raytracing-rust", "--time-limit", "10s"]);
+        assert_eq!(cli.render.time_limit, Some(Duration::from_secs(10)));
+        assert_eq!(cli.render.settings(&random_spheres()).image.samples_per_pixel, None);
+    }
+
+    #[test]
+    fn parses_camera_models() {
+        let camera = camera_with(&["--orthographic", "12"]).unwrap();
+        assert_eq!(camera.projection, Projection::Orthographic { view_height: 12.0 });
+        let camera = camera_with(&["--fisheye", "200", "--fisheye-mapping", "equisolid"]).unwrap();
+        assert_eq!(camera.projection, Projection::Fisheye { fov: 200.0, mapping: FisheyeMapping::Equisolid });
+        assert!(Cli::try_parse_from(["raytracing-rust", "--fisheye", "200", "--equirectangular"]).is_err());
+    }
+
+    #[test]
+    fn parses_lens_camera() {
+        assert!(camera_with(&["--lens-aperture", "10"]).is_err());
+        let camera = camera_with(&["--lens", "scenes/lenses/dgauss50.dat", "--lens-aperture", "10"]).unwrap();
+        assert!(matches!(camera.projection, Projection::Lens { options, .. } if options.aperture_diameter == Some(10.0)));
+    }
+
+    #[test]
+    fn parses_aperture_shapes() {
+        let camera = camera_with(&["--aperture-blades", "6", "--aperture-rotation", "-15"]).unwrap();
+        assert_eq!(camera.aperture_shape, ApertureShape::Polygon { blades: 6, rotation: -15.0 });
+    }
+
+    #[test]
+    fn parses_exposure() {
+        assert_eq!(camera_with(&["--f-number", "8"]).err(), Some("--shutter is needed for a physical camera".to_string()));
+        let camera = camera_with(&["--f-number", "8", "--shutter", "1/125", "--iso", "400"]).unwrap();
+        assert_eq!(camera.exposure, Some(Exposure { f_number: 8.0, shutter: 0.008, iso: 400.0 }));
+    }
+
+    #[test]
+    fn parses_autofocus() {
+        assert_eq!(camera_with(&["--autofocus"]).unwrap().autofocus, Some(Autofocus::Point(0.5, 0.5)));
+        assert!(Cli::try_parse_from(["raytracing-rust", "--focus-on", "glass", "--focus-dist", "3"]).is_err());
+    }
+
+    #[test]
+    fn parses_lens_effects() {
+        let camera = camera_with(&["--distortion", "-0.2,0.05,0,0", "--vignetting"]).unwrap();
+        assert_eq!(camera.effects.distortion, Some(Distortion { k1: -0.2, k2: 0.05, ..Default::default() }));
+        assert!(camera.effects.vignetting);
+    }
+
+    #[test]
+    fn parses_stereo() {
+        let camera = camera_with(&["--stereo", "top-bottom", "--eye-separation", "0.1"]).unwrap();
+        let rig = StereoRig { layout: StereoLayout::TopBottom, eye_separation: 0.1, convergence: None };
+        assert_eq!(camera.stereo, Some(rig));
+    }
+
+    #[test]
+    fn parses_rolling_shutter() {
+        assert_eq!(camera_with(&["--rolling-shutter", "1/50"]).unwrap().rolling_shutter, Some(0.02));
+    }
+
+    #[test]
+    fn parses_builtin_scene() {
+        let cli = Cli::parse_from(["raytracing-rust", "--builtin", "cornell-box"]);
+        assert_eq!(cli.render.source.builtin.as_deref(), Some("cornell-box"));
+        assert!(Cli::try_parse_from(["raytracing-rust", "--builtin", "teapot"]).is_err());
+        assert!(Cli::try_parse_from(["raytracing-rust", "scene.toml", "--builtin", "cornell-box"]).is_err());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert!(parse_duration("10 minutes").is_err());
+        assert!(parse_duration("-1").is_err());
+    }
+
+    #[test]
+    fn resume_checkpoints_to_the_same_file() {
+        let cli = Cli::parse_from(["raytracing-rust", "--resume", "render.ckpt", "--spp", "1000"]);
+        assert_eq!(cli.render.checkpoint_path(), Some(&PathBuf::from("render.ckpt")));
+        assert_eq!(cli.render.checkpoint_interval, Duration::from_secs(300));
+        assert!(Cli::try_parse_from(["raytracing-rust", "--resume", "render.ckpt", "--seed", "1"]).is_err());
+    }
+
+    #[test]
+    fn parses_distributed_render() {
+        let cli = Cli::parse_from(["raytracing-rust", "scene.toml", "--listen", "7000"]);
+        assert_eq!(cli.render.listen.as_deref(), Some("0.0.0.0:7000"));
+
+        let cli = Cli::parse_from(["raytracing-rust", "worker", "render-box", "-b", "cornell-box"]);
+        match cli.command {
+            Some(Command::Worker { coordinator, source, .. }) => {
+                assert_eq!(coordinator, format!("render-box:{}", DEFAULT_PORT));
+                assert_eq!(source.builtin.as_deref(), Some("cornell-box"));
+            }
+            _ => panic!("expected the worker subcommand"),
+        }
+        assert!(Cli::try_parse_from(["raytracing-rust", "worker", "host:port"]).is_err());
+    }
+
+    #[test]
+    fn parses_frame_sequences() {
+        let cli = Cli::parse_from(["raytracing-rust", "orbit.toml", "--frames", "-o", "out/orbit_###.png"]);
+        assert_eq!(cli.render.frames, Some(FrameRange { first: 0, last: None }));
+        assert_eq!(cli.render.frame_path(7), PathBuf::from("out/orbit_007.png"));
+        assert!(!cli.render.is_video());
+
+        let cli = Cli::parse_from(["raytracing-rust", "--frames", "10-20", "-o", "orbit.Y4M"]);
+        assert_eq!(cli.render.frames, Some(FrameRange { first: 10, last: Some(20) }));
+        assert!(cli.render.is_video());
+
+        let cli = Cli::parse_from(["raytracing-rust", "--frames", "5", "-o", "orbit.png"]);
+        assert_eq!(cli.render.frames, Some(FrameRange { first: 5, last: Some(5) }));
+        assert_eq!(cli.render.frame_path(5), PathBuf::from("orbit_0005.png"));
+
+        assert!(Cli::try_parse_from(["raytracing-rust", "--frames", "20-10"]).is_err());
+        assert!(Cli::try_parse_from(["raytracing-rust", "--frames", "--checkpoint", "a.ckpt"]).is_err());
+    }
+
+    #[test]
+    fn parses_named_cameras() {
+        let cli = Cli::parse_from(["raytracing-rust", "product.toml", "--cameras", "-o", "shots/shot.png"]);
+        assert_eq!(cli.render.cameras, Some(vec![]));
+        assert_eq!(cli.render.camera_path("front"), PathBuf::from("shots/shot_front.png"));
+
+        let cli = Cli::parse_from(["raytracing-rust", "--cameras", "front,top", "-o", "shot"]);
+        assert_eq!(cli.render.cameras, Some(vec!["front".to_string(), "top".to_string()]));
+        assert_eq!(cli.render.camera_path("top"), PathBuf::from("shot_top"));
+
+        assert!(Cli::try_parse_from(["raytracing-rust", "--cameras", "--frames"]).is_err());
+    }
+
+    #[test]
+    fn parses_preview_address() {
+        let preview = |args: &[&str]| Cli::parse_from(args).render.preview;
+        assert_eq!(preview(&["raytracing-rust"]), None);
+        assert_eq!(preview(&["raytracing-rust", "--preview"]).as_deref(), Some("127.0.0.1:8000"));
+        assert_eq!(preview(&["raytracing-rust", "--preview", "9000"]).as_deref(), Some("127.0.0.1:9000"));
+        assert_eq!(preview(&["raytracing-rust", "--preview", "0.0.0.0"]).as_deref(), Some("0.0.0.0:8000"));
+    }
+
+    #[test]
+    fn parses_denoise_subcommand() {
+        let cli = Cli::parse_from(["raytracing-rust", "denoise", "noisy.pfm", "clean.png"]);
+        match cli.command {
+            Some(Command::Denoise { input, output }) => {
+                assert_eq!(input, PathBuf::from("noisy.pfm"));
+                assert_eq!(output, Some(PathBuf::from("clean.png")));
+            }
+            _ => panic!("expected the denoise subcommand"),
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use crate::film::*;
+use crate::image::*;
+use crate::vec3::*;
+
+/// Edge-avoiding À-trous wavelet filter (Dammertz et al. 2010).
+///
+/// Each pass is a sparse 5x5 B3-spline blur whose taps are spread twice as far apart as the
+/// previous pass, with each tap weighted down by how much its colour, albedo, normal and depth
+/// differ from the centre pixel. Lighting is filtered separately from albedo so that texture
+/// detail survives.
+#[derive(Clone, Copy, Debug)]
+pub struct Denoiser {
+    pub iterations: u32,
+    pub sigma_color: f32,
+    pub sigma_albedo: f32,
+    pub sigma_normal: f32,
+    pub sigma_depth: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Denoiser {
+        Denoiser {
+            iterations: 5,
+            sigma_color: 0.6,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.3,
+            sigma_depth: 0.05,
+        }
+    }
+}
+
+/// The guide buffers used to find edges.
+pub struct Features {
+    pub albedo: Image,
+    pub normal: Image,
+    pub depth: Image,
+}
+
+impl Features {
+    pub fn from_film(film: &Film) -> Features {
+        Features {
+            albedo: film.aov(Aov::Albedo),
+            normal: film.aov(Aov::Normal),
+            depth: film.aov(Aov::Depth),
+        }
+    }
+
+    /// Load the AOVs written next to a beauty image by `Film::write_aovs`. Each must be
+    /// `width` by `height`, as the beauty image is.
+    pub fn read(beauty_path: &Path, width: usize, height: usize) -> io::Result<Features> {
+        let read = |aov: Aov, gamma_correct| {
+            let path = aov.path_for(beauty_path);
+            let image = read_image(&path, gamma_correct)?;
+            if (image.width, image.height) != (width, height) {
+                let message = format!("{}: is {}x{}, but the beauty image is {}x{}", path.display(), image.width, image.height, width, height);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+            Ok(image)
+        };
+        let albedo = read(Aov::Albedo, true)?;
+        let mut normal = read(Aov::Normal, false)?;
+        let mut depth = read(Aov::Depth, false)?;
+
+        // Undo the [0, 1] display mapping of normals written to integer formats. Misses were black.
+        // Depth misses were white, beyond the farthest hit, and are infinitely far again.
+        if !ImageFormat::from_path(beauty_path).is_some_and(|f| f.is_float()) {
+            for n in normal.pixels.iter_mut() {
+                if !n.near_zero() {
+                    *n = 2.0 * *n - Vec3::new(1.0, 1.0, 1.0);
+                }
+            }
+            for d in depth.pixels.iter_mut() {
+                if d.x > DISPLAY_DEPTH_FAR + 0.01 {
+                    *d = Color::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+                }
+            }
+        }
+
+        Ok(Features { albedo, normal, depth })
+    }
+}
+
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+const MIN_ALBEDO: f32 = 1e-3;
+
+impl Denoiser {
+    pub fn denoise(&self, beauty: &Image, features: &Features) -> Image {
+        let (width, height) = (beauty.width, beauty.height);
+        assert!(
+            [&features.albedo, &features.normal, &features.depth]
+                .iter()
+                .all(|f| f.width == width && f.height == height),
+            "feature buffers must match the beauty image size"
+        );
+
+        // Filter lighting only: divide out the albedo now and multiply it back in at the end.
+        let albedo: Vec<Color> = features.albedo.pixels.iter().map(|a| max_components(*a, MIN_ALBEDO)).collect();
+        let mut irradiance: Vec<Color> = beauty.pixels.iter().zip(&albedo).map(|(c, a)| *c / *a).collect();
+        let depth = normalized_depth(&features.depth);
+
+        for i in 0..self.iterations {
+            let step = 1 << i;
+            // Noise drops with every pass, so tighten the colour tolerance to match.
+            let sigma_color = self.sigma_color / (1 << i) as f32;
+
+            irradiance = (0..height)
+                .into_par_iter()
+                .flat_map_iter(|y| {
+                    let irradiance = &irradiance;
+                    let albedo = &albedo;
+                    let depth = &depth;
+                    (0..width).map(move |x| {
+                        let p = y * width + x;
+                        let mut sum = Color::zero();
+                        let mut weight_sum = 0.0;
+
+                        for (ky, hy) in KERNEL.iter().enumerate() {
+                            let qy = y as isize + (ky as isize - 2) * step;
+                            if qy < 0 || qy >= height as isize {
+                                continue;
+                            }
+                            for (kx, hx) in KERNEL.iter().enumerate() {
+                                let qx = x as isize + (kx as isize - 2) * step;
+                                if qx < 0 || qx >= width as isize {
+                                    continue;
+                                }
+                                let q = qy as usize * width + qx as usize;
+
+                                let color_dist = (irradiance[p] - irradiance[q]).length_squared();
+                                let albedo_dist = (albedo[p] - albedo[q]).length_squared();
+                                let normal_dist = (features.normal.pixels[p] - features.normal.pixels[q]).length_squared();
+                                let depth_dist = (depth[p] - depth[q]) * (depth[p] - depth[q]);
+
+                                let weight = hx * hy
+                                    * f32::exp(
+                                        -color_dist / (sigma_color * sigma_color)
+                                            - albedo_dist / (self.sigma_albedo * self.sigma_albedo)
+                                            - normal_dist / (self.sigma_normal * self.sigma_normal)
+                                            - depth_dist / (self.sigma_depth * self.sigma_depth),
+                                    );
+                                sum += weight * irradiance[q];
+                                weight_sum += weight;
+                            }
+                        }
+
+                        // The centre tap always has weight > 0, so this never divides by zero.
+                        sum / weight_sum
+                    })
+                })
+                .collect();
+        }
+
+        let pixels = irradiance.iter().zip(&albedo).map(|(e, a)| *e * *a).collect();
+        let mut image = Image::from_pixels(width, height, pixels);
+        image.gamma_correct = beauty.gamma_correct;
+        image
+    }
+}
+
+/// Denoise a saved beauty image using the AOVs written next to it, and save the result.
+pub fn denoise_file(input: &Path, output: &Path, denoiser: &Denoiser) -> io::Result<()> {
+    let format = ImageFormat::from_path(output).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("Unsupported output format: {}", output.display()))
+    })?;
+    let beauty = read_image(input, true)?;
+    let features = Features::read(input, beauty.width, beauty.height)?;
+
+    let denoised = denoiser.denoise(&beauty, &features);
+    let mut writer = BufWriter::new(File::create(output)?);
+    format.write_image(&mut writer, &denoised)?;
+    writer.flush()
+}
+
+fn max_components(v: Vec3, min: f32) -> Vec3 {
+    Vec3::new(f32::max(v.x, min), f32::max(v.y, min), f32::max(v.z, min))
+}
+
+// Scale depth to [0, 1] so the tolerance doesn't depend on scene size. Misses sit well past the
+// far end so they never blend with geometry.
+fn normalized_depth(depth: &Image) -> Vec<f32> {
+    let max = depth
+        .pixels
+        .iter()
+        .map(|d| d.x)
+        .filter(|d| d.is_finite())
+        .fold(0.0, f32::max);
+    let scale = if max > 0.0 { 1.0 / max } else { 1.0 };
+    depth
+        .pixels
+        .iter()
+        .map(|d| if d.x.is_finite() { d.x * scale } else { 2.0 })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat(width: usize, height: usize, color: Color) -> Image {
+        Image::from_pixels(width, height, vec![color; width * height])
+    }
+
+    #[test]
+    fn preserves_normal_edges() {
+        // Left half faces +x, right half faces +y; noise-free but differently lit.
+        let (width, height) = (16, 8);
+        let mut beauty = flat(width, height, Color::new(0.2, 0.2, 0.2));
+        let mut normal = flat(width, height, Vec3::new(1.0, 0.0, 0.0));
+        for y in 0..height {
+            for x in width / 2..width {
+                beauty.pixels[y * width + x] = Color::new(0.8, 0.8, 0.8);
+                normal.pixels[y * width + x] = Vec3::new(0.0, 1.0, 0.0);
+            }
+        }
+        let features = Features {
+            albedo: flat(width, height, Color::new(1.0, 1.0, 1.0)),
+            normal,
+            depth: flat(width, height, Color::new(1.0, 1.0, 1.0)),
+        };
+
+        let denoised = Denoiser::default().denoise(&beauty, &features);
+        for (a, b) in denoised.pixels.iter().zip(&beauty.pixels) {
+            assert!((*a - *b).length() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn reads_back_depth_misses() {
+        // A hit at depth 2 beside one at 4 and a miss.
+        let mut film = Film::new(3, 1);
+        for (pixel, depth) in film.pixels.iter_mut().zip([2.0, 4.0]) {
+            let hit = FirstHit {
+                albedo: Color::zero(),
+                normal: Vec3::new(0.0, 0.0, 1.0),
+                position: Point3::zero(),
+                depth,
+                object_id: 0,
+            };
+            pixel.add_sample(Color::zero(), Color::zero(), Some(hit));
+        }
+        film.pixels[2].add_sample(Color::zero(), Color::zero(), None);
+
+        let path = std::env::temp_dir().join("raytracing-depth-misses.png");
+        film.write_aovs(&path, ImageFormat::from_name("png").unwrap()).unwrap();
+        let features = Features::read(&path, 3, 1).unwrap();
+        for aov in Aov::ALL {
+            std::fs::remove_file(aov.path_for(&path)).unwrap();
+        }
+        let depth: Vec<f32> = features.depth.pixels.iter().map(|d| d.x).collect();
+        assert!(depth[0] < depth[1] && depth[1].is_finite() && depth[2].is_infinite(), "{:?}", depth);
+    }
+
+    #[test]
+    fn refuses_stale_aovs() {
+        // The AOVs are left over from a smaller render than the beauty image.
+        let path = std::env::temp_dir().join("raytracing-stale-aovs.png");
+        let format = ImageFormat::from_name("png").unwrap();
+        Film::new(3, 1).write_aovs(&path, format).unwrap();
+        format.write_image(&mut File::create(&path).unwrap(), &flat(4, 1, Color::zero())).unwrap();
+        let error = denoise_file(&path, &path, &Denoiser::default()).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        for aov in Aov::ALL {
+            std::fs::remove_file(aov.path_for(&path)).unwrap();
+        }
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let albedo = Aov::Albedo.path_for(&path);
+        assert_eq!(error.to_string(), format!("{}: is 3x1, but the beauty image is 4x1", albedo.display()));
+    }
+}
@@ -0,0 +1,679 @@
+//! Rendering across processes. A coordinator hands out work items (one tile for one pass) over
+//! TCP to workers that have loaded the same scene, and merges the tiles they send back.
+//!
+//! Each sample's random stream only depends on the seed, the sample's number and the pixel, and the
+//! coordinator merges each tile's passes in order, so the result is the same as rendering in
+//! one process. Workers may join at any time; the work of one that drops out is handed to the
+//! others.
+//!
+//! The protocol is a sequence of little-endian messages, each a tag byte and a fixed layout,
+//! except that a job's image and camera settings go as TOML text after its length:
+//!
+//! ```text
+//! worker                       coordinator
+//!   Hello {version, threads} ->
+//!                           <- Job {settings, camera, time, scene fingerprint}   (or Error)
+//!   Ready                    ->                                                   (or Error)
+//!                           <- Work {id, pass, samples, tile}              (several in flight)
+//!   Result {id, pixels}      ->
+//!   ...
+//!                           <- Done
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::checkpoint::*;
+use crate::film::*;
+use crate::render::*;
+use crate::scene::*;
+use crate::tile::*;
+use crate::util::*;
+
+const VERSION: u32 = 16;
+
+// The most a job's settings may take up, which is mostly an aperture mask's weights.
+const MAX_JOB_BYTES: usize = 64 << 20;
+// The widest or tallest image a worker will take on.
+const MAX_IMAGE_SIDE: usize = 1 << 16;
+// The longest error message either side will read.
+const MAX_ERROR_BYTES: usize = 1 << 16;
+
+/// The port used when an address doesn't give one.
+pub const DEFAULT_PORT: u16 = 7878;
+
+#[derive(Clone, Copy, Debug)]
+struct WorkItem {
+    id: u64,
+    pass: u32,
+    samples: u32,
+    tile: Tile,
+}
+
+// What a worker needs besides the scene itself. The worker poses its scene at `time` once it
+// has the camera, as whether the camera has a shutter changes how the scene is posed.
+#[derive(Clone)]
+struct Job {
+    settings: RenderSettings,
+    camera: CameraSettings,
+    time: f32,
+    fingerprint: u64,
+}
+
+// The settings of a job that go as TOML, so they keep up with the settings themselves.
+#[derive(Serialize, Deserialize)]
+struct JobView {
+    image: ImageSettings,
+    camera: CameraSettings,
+}
+
+enum Message {
+    Hello { version: u32, threads: u32 },
+    Job(Box<Job>),
+    Ready,
+    Work(WorkItem),
+    Result { id: u64, pixels: Vec<Pixel> },
+    Done,
+    Error(String),
+}
+
+impl Message {
+    fn write(&self, w: &mut dyn Write) -> io::Result<()> {
+        match self {
+            Message::Hello { version, threads } => {
+                w.write_all(&[1])?;
+                w.write_all(&version.to_le_bytes())?;
+                w.write_all(&threads.to_le_bytes())
+            }
+            Message::Job(job) => {
+                let view = JobView {
+                    image: job.settings.image,
+                    camera: job.camera.clone(),
+                };
+                let text = toml::to_string(&view).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                w.write_all(&[2])?;
+                w.write_all(&job.settings.seed.to_le_bytes())?;
+                w.write_all(&job.settings.samples_per_pass.to_le_bytes())?;
+                w.write_all(&job.time.to_le_bytes())?;
+                w.write_all(&job.fingerprint.to_le_bytes())?;
+                w.write_all(&(text.len() as u32).to_le_bytes())?;
+                w.write_all(text.as_bytes())
+            }
+            Message::Ready => w.write_all(&[3]),
+            Message::Work(item) => {
+                w.write_all(&[4])?;
+                w.write_all(&item.id.to_le_bytes())?;
+                w.write_all(&item.pass.to_le_bytes())?;
+                w.write_all(&item.samples.to_le_bytes())?;
+                for v in [item.tile.x0, item.tile.y0, item.tile.x1, item.tile.y1] {
+                    w.write_all(&(v as u64).to_le_bytes())?;
+                }
+                Ok(())
+            }
+            Message::Result { id, pixels } => {
+                w.write_all(&[5])?;
+                w.write_all(&id.to_le_bytes())?;
+                w.write_all(&(pixels.len() as u64).to_le_bytes())?;
+                for p in pixels {
+                    write_pixel(w, p)?;
+                }
+                Ok(())
+            }
+            Message::Done => w.write_all(&[6]),
+            Message::Error(message) => {
+                w.write_all(&[7])?;
+                w.write_all(&(message.len() as u32).to_le_bytes())?;
+                w.write_all(message.as_bytes())
+            }
+        }
+    }
+
+    // A result may have at most `max_pixels` pixels.
+    fn read(r: &mut dyn Read, max_pixels: usize) -> io::Result<Message> {
+        Message::read_fields(r, max_pixels).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => io::Error::new(e.kind(), "connection closed"),
+            _ => e,
+        })
+    }
+
+    fn read_fields(r: &mut dyn Read, max_pixels: usize) -> io::Result<Message> {
+        let mut tag = [0];
+        r.read_exact(&mut tag)?;
+        let message = match tag[0] {
+            1 => Message::Hello {
+                version: read_u32(r)?,
+                threads: read_u32(r)?,
+            },
+            2 => {
+                let seed = read_u64(r)?;
+                let samples_per_pass = read_u32(r)?;
+                let time = f32::from_bits(read_u32(r)?);
+                let fingerprint = read_u64(r)?;
+                let length = read_u32(r)? as usize;
+                if length > MAX_JOB_BYTES {
+                    return Err(protocol_error(format!("a job of {} bytes is too big", length)));
+                }
+                let mut text = vec![0; length];
+                r.read_exact(&mut text)?;
+                let text = String::from_utf8(text).map_err(|e| protocol_error(e.to_string()))?;
+                let view: JobView = toml::from_str(&text).map_err(|e| protocol_error(e.to_string()))?;
+                let mut camera = view.camera;
+                if let Projection::Lens { system, .. } = &camera.projection {
+                    system.validate().map_err(protocol_error)?;
+                }
+                // The coordinator has already focused the camera.
+                camera.autofocus = None;
+                let mut settings = RenderSettings::new(view.image, seed);
+                settings.samples_per_pass = samples_per_pass;
+                settings.validate().map_err(protocol_error)?;
+                let (width, height) = (settings.image.width, settings.image.height());
+                if width.max(height) > MAX_IMAGE_SIDE {
+                    return Err(protocol_error(format!("a {}x{} image is too big", width, height)));
+                }
+                if !time.is_finite() {
+                    return Err(protocol_error(format!("a job at {}s can't be posed", time)));
+                }
+                Message::Job(Box::new(Job { settings, camera, time, fingerprint }))
+            }
+            3 => Message::Ready,
+            4 => Message::Work(WorkItem {
+                id: read_u64(r)?,
+                pass: read_u32(r)?,
+                samples: read_u32(r)?,
+                tile: Tile {
+                    x0: read_u64(r)? as usize,
+                    y0: read_u64(r)? as usize,
+                    x1: read_u64(r)? as usize,
+                    y1: read_u64(r)? as usize,
+                },
+            }),
+            5 => {
+                let id = read_u64(r)?;
+                let count = read_u64(r)?;
+                if count > max_pixels as u64 {
+                    return Err(protocol_error(format!("a result of {} pixels is bigger than any tile", count)));
+                }
+                let pixels = (0..count).map(|_| read_pixel(r)).collect::<io::Result<_>>()?;
+                Message::Result { id, pixels }
+            }
+            6 => Message::Done,
+            7 => {
+                let length = read_u32(r)? as usize;
+                if length > MAX_ERROR_BYTES {
+                    return Err(protocol_error(format!("an error message of {} bytes is too long", length)));
+                }
+                let mut bytes = vec![0; length];
+                r.read_exact(&mut bytes)?;
+                Message::Error(String::from_utf8_lossy(&bytes).into_owned())
+            }
+            tag => return Err(protocol_error(format!("unknown message type {}", tag))),
+        };
+        Ok(message)
+    }
+}
+
+fn protocol_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// How `coordinate` reports back to the `Renderer` that called it.
+pub(crate) struct Hooks<'a> {
+    pub progress: &'a dyn Progress,
+    pub stopping: &'a (dyn Fn() -> bool + Sync),
+    /// Called after each tile is merged into the framebuffer.
+    pub merged: &'a (dyn Fn(&Framebuffer) + Sync),
+}
+
+// Work to hand out (items are numbered pass by pass, from `next` on, plus any taken back from
+// workers that dropped out), and finished tiles waiting for an earlier pass of the same tile.
+struct Shared {
+    next: u64,
+    returned: VecDeque<u64>,
+    next_pass: Vec<u32>,
+    waiting: HashMap<(usize, u32), Vec<Pixel>>,
+    done: u64,
+}
+
+impl Shared {
+    fn take(&mut self, total: u64) -> Option<u64> {
+        self.returned.pop_front().or_else(|| {
+            let id = self.next;
+            (id < total).then(|| {
+                self.next += 1;
+                id
+            })
+        })
+    }
+}
+
+struct Coordinator<'a> {
+    job: Job,
+    tiles: Vec<Tile>,
+    total: u64,
+    shared: Mutex<Shared>,
+    changed: Condvar,
+    framebuffer: Framebuffer,
+    hooks: Hooks<'a>,
+}
+
+/// Hand out the render of `scene`, posed at `time`, to workers connecting on `listener` until
+/// it's done (or stopped).
+pub(crate) fn coordinate(listener: &TcpListener, scene: &Scene, time: f32, settings: &RenderSettings, hooks: Hooks) -> io::Result<Film> {
+    let (width, height) = (settings.image.width, settings.image.height());
+    let tiles = tiles(width, height, settings.tile_size, settings.tile_order);
+    let pass_count = settings.image.sample_limit().div_ceil(settings.samples_per_pass);
+
+    let coordinator = Coordinator {
+        job: Job {
+            settings: *settings,
+            camera: scene.camera.clone(),
+            time,
+            fingerprint: scene.fingerprint(),
+        },
+        total: pass_count as u64 * tiles.len() as u64,
+        shared: Mutex::new(Shared {
+            next: 0,
+            returned: VecDeque::new(),
+            next_pass: vec![0; tiles.len()],
+            waiting: HashMap::new(),
+            done: 0,
+        }),
+        tiles,
+        changed: Condvar::new(),
+        framebuffer: Framebuffer::new(width, height),
+        hooks,
+    };
+
+    coordinator.hooks.progress.start(coordinator.total);
+    listener.set_nonblocking(true)?;
+    thread::scope(|s| -> io::Result<()> {
+        loop {
+            match listener.accept() {
+                Ok((stream, addr)) => {
+                    let coordinator = &coordinator;
+                    s.spawn(move || {
+                        coordinator.hooks.progress.message(&format!("worker {} joined", addr));
+                        match coordinator.serve(stream) {
+                            Ok(()) => coordinator.hooks.progress.message(&format!("worker {} finished", addr)),
+                            Err(e) => coordinator.hooks.progress.message(&format!("worker {} dropped out: {}", addr, e)),
+                        }
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    let shared = coordinator.shared.lock().unwrap();
+                    if coordinator.finished(&shared) {
+                        return Ok(());
+                    }
+                    drop(coordinator.changed.wait_timeout(shared, Duration::from_millis(50)).unwrap());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    })?;
+    coordinator.hooks.progress.finish();
+
+    Ok(coordinator.framebuffer.into_film())
+}
+
+impl Coordinator<'_> {
+    fn finished(&self, shared: &Shared) -> bool {
+        shared.done == self.total || (self.hooks.stopping)()
+    }
+
+    fn item(&self, id: u64) -> WorkItem {
+        let settings = &self.job.settings;
+        let pass = (id / self.tiles.len() as u64) as u32;
+        WorkItem {
+            id,
+            pass,
+            samples: u32::min(settings.samples_per_pass, settings.image.sample_limit() - pass * settings.samples_per_pass),
+            tile: self.tiles[(id % self.tiles.len() as u64) as usize],
+        }
+    }
+
+    // Talk to one worker until the render is finished or the worker goes away.
+    fn serve(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+
+        let threads = match Message::read(&mut reader, 0)? {
+            Message::Hello { version, threads } if version == VERSION => threads,
+            Message::Hello { version, .. } => {
+                let message = format!("worker speaks protocol version {}, not {}", version, VERSION);
+                Message::Error(message.clone()).write(&mut writer)?;
+                writer.flush()?;
+                return Err(protocol_error(message));
+            }
+            _ => return Err(protocol_error("expected a hello".to_string())),
+        };
+        Message::Job(Box::new(self.job.clone())).write(&mut writer)?;
+        writer.flush()?;
+        match Message::read(&mut reader, 0)? {
+            Message::Ready => {}
+            Message::Error(message) => return Err(io::Error::other(message)),
+            _ => return Err(protocol_error("expected the worker to be ready".to_string())),
+        }
+
+        // Keep every worker thread busy while the results of earlier items come back.
+        let window = 2 * threads.max(1) as usize;
+        let mut in_flight = vec![];
+        let result = self.exchange(&mut reader, &mut writer, window, &mut in_flight);
+        if !in_flight.is_empty() {
+            // Give the worker's unfinished items to someone else.
+            let mut shared = self.shared.lock().unwrap();
+            for &id in in_flight.iter().rev() {
+                shared.returned.push_front(id);
+            }
+            self.changed.notify_all();
+        }
+        result
+    }
+
+    fn exchange(&self, reader: &mut dyn Read, writer: &mut BufWriter<TcpStream>, window: usize, in_flight: &mut Vec<u64>) -> io::Result<()> {
+        let max_pixels = self.tiles.iter().map(|t| t.width() * t.height()).max().unwrap_or(0);
+        loop {
+            // Take the work under the lock but send it after, so a slow worker doesn't hold up
+            // the others.
+            let first_new = in_flight.len();
+            {
+                let mut shared = self.shared.lock().unwrap();
+                while in_flight.len() < window && !(self.hooks.stopping)() {
+                    let Some(id) = shared.take(self.total) else { break };
+                    in_flight.push(id);
+                }
+                if in_flight.is_empty() && !self.finished(&shared) {
+                    // Other workers have the rest; wait in case one of them drops out.
+                    drop(self.changed.wait_timeout(shared, Duration::from_millis(100)).unwrap());
+                    continue;
+                }
+            }
+            if in_flight.is_empty() {
+                Message::Done.write(writer)?;
+                return writer.flush();
+            }
+            for &id in &in_flight[first_new..] {
+                Message::Work(self.item(id)).write(writer)?;
+            }
+            writer.flush()?;
+
+            let (id, pixels) = match Message::read(reader, max_pixels)? {
+                Message::Result { id, pixels } => (id, pixels),
+                Message::Error(message) => return Err(io::Error::other(message)),
+                _ => return Err(protocol_error("expected a result".to_string())),
+            };
+            let Some(position) = in_flight.iter().position(|&i| i == id) else {
+                return Err(protocol_error(format!("result for item {}, which the worker wasn't given", id)));
+            };
+            let item = self.item(id);
+            if pixels.len() != item.tile.width() * item.tile.height() {
+                return Err(protocol_error(format!("result for item {} has {} pixels", id, pixels.len())));
+            }
+            in_flight.swap_remove(position);
+            self.merge(&item, pixels);
+            self.hooks.progress.advance(1);
+            (self.hooks.merged)(&self.framebuffer);
+        }
+    }
+
+    // Add a finished item to the film, holding it back until the tile's earlier passes are in so
+    // the sums come out exactly as a local render's.
+    fn merge(&self, item: &WorkItem, pixels: Vec<Pixel>) {
+        let mut shared = self.shared.lock().unwrap();
+        let tile = (item.id % self.tiles.len() as u64) as usize;
+        shared.waiting.insert((tile, item.pass), pixels);
+        loop {
+            let next = shared.next_pass[tile];
+            let Some(pixels) = shared.waiting.remove(&(tile, next)) else { break };
+            self.framebuffer.add_tile(&item.tile, &pixels);
+            shared.next_pass[tile] += 1;
+        }
+        shared.done += 1;
+        self.changed.notify_all();
+    }
+}
+
+/// Render work items from the coordinator at the other end of `stream` until it says it's done.
+/// `load_scene` must build the same scene the coordinator has, as loaded; it's called once the
+/// random generator has been seeded to match, and posed with the coordinator's camera at the
+/// coordinator's time. Returns how many items this worker rendered.
+pub fn work<F: FnOnce() -> Result<Scene, String>>(stream: TcpStream, load_scene: F) -> io::Result<u64> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    Message::Hello {
+        version: VERSION,
+        threads: rayon::current_num_threads() as u32,
+    }
+    .write(&mut writer)?;
+    writer.flush()?;
+    let job = match Message::read(&mut reader, 0)? {
+        Message::Job(job) => job,
+        Message::Error(message) => return Err(io::Error::other(message)),
+        _ => return Err(protocol_error("expected a job".to_string())),
+    };
+
+    seed_rng(job.settings.seed, 0);
+    let refuse = |writer: &mut BufWriter<TcpStream>, message: String| {
+        Message::Error(message.clone()).write(writer)?;
+        writer.flush()?;
+        Err(io::Error::other(message))
+    };
+    let mut scene = match load_scene() {
+        Ok(scene) => scene,
+        Err(message) => return refuse(&mut writer, message),
+    };
+    if let Err(message) = job.camera.validate() {
+        return refuse(&mut writer, message);
+    }
+    // The camera is already posed and focused, so posing it again leaves it as it is.
+    scene.camera = job.camera;
+    let scene = match scene.at(job.time) {
+        Ok(scene) => scene,
+        Err(message) => return refuse(&mut writer, message),
+    };
+    if scene.fingerprint() != job.fingerprint {
+        return refuse(&mut writer, "the worker's scene is not the same as the coordinator's".to_string());
+    }
+    let settings = job.settings;
+    let camera = match scene.build_camera(&settings.image) {
+        Ok(camera) => Arc::new(camera),
+        Err(message) => return refuse(&mut writer, message),
+    };
+    Message::Ready.write(&mut writer)?;
+    writer.flush()?;
+
+    // Read work on this thread and render it on the pool, sending each result when it's done.
+    // Rendering tasks are spawned rather than pulled from a channel so they never block a pool
+    // thread, which matters when several workers share one pool.
+    let scene = Arc::new(scene);
+    let writer = Arc::new(Mutex::new(writer));
+    let (finished, results) = mpsc::channel::<io::Result<()>>();
+    let mut handed_out = 0;
+    let read = loop {
+        match Message::read(&mut reader, 0) {
+            Ok(Message::Work(item)) if !item.tile.is_within(settings.image.width, settings.image.height()) => {
+                break Err(protocol_error(format!("item {} is for a tile outside the image", item.id)));
+            }
+            Ok(Message::Work(item)) => {
+                handed_out += 1;
+                let (scene, camera, writer, finished) = (scene.clone(), camera.clone(), writer.clone(), finished.clone());
+                rayon::spawn(move || {
+                    let first_sample = item.pass * settings.samples_per_pass;
+                    let pixels = render_tile(&scene, &camera, &settings, &item.tile, first_sample, item.samples);
+                    let mut writer = writer.lock().unwrap();
+                    let sent = Message::Result { id: item.id, pixels }.write(&mut *writer).and_then(|()| writer.flush());
+                    // Only fails if `work` has already returned.
+                    let _ = finished.send(sent);
+                });
+            }
+            Ok(Message::Done) => break Ok(()),
+            Ok(Message::Error(message)) => break Err(io::Error::other(message)),
+            Ok(_) => break Err(protocol_error("expected work".to_string())),
+            Err(e) => break Err(e),
+        }
+    };
+
+    let mut rendered = 0;
+    let mut sent = Ok(());
+    for result in results.iter().take(handed_out) {
+        match result {
+            Ok(()) => rendered += 1,
+            Err(e) => sent = sent.and(Err(e)),
+        }
+    }
+    read.and(sent).map(|()| rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::aperture::*;
+    use crate::camera::{Exposure, StereoLayout, StereoRig};
+    use crate::lens::*;
+
+    fn load() -> Scene {
+        Scene::parse(include_str!("../scenes/three_spheres.toml")).unwrap()
+    }
+
+    #[test]
+    fn jobs_keep_their_camera() {
+        let mut scene = load();
+        let system = LensSystem::load(Path::new("scenes/lenses/dgauss50.dat")).unwrap();
+        let camera = &mut scene.camera;
+        camera.projection = Projection::Lens { system: Arc::new(system), options: LensOptions::default() };
+        camera.aperture_shape = ApertureShape::Mask(Arc::new(ApertureMask::new(2, 1, vec![0.25, 1.0]).unwrap()));
+        camera.effects.chromatic_aberration = Some([0.003, 0.0, -0.003]);
+        camera.stereo = Some(StereoRig { layout: StereoLayout::TopBottom, eye_separation: 0.065, convergence: None });
+        camera.focus_dist = f32::INFINITY;
+        let mut settings = RenderSettings::for_scene(&scene);
+        settings.seed = u64::MAX;
+        let job = Job { settings, camera: scene.camera.clone(), time: 0.5, fingerprint: 7 };
+
+        let mut bytes = vec![];
+        Message::Job(Box::new(job.clone())).write(&mut bytes).unwrap();
+        let Message::Job(read) = Message::read(&mut &bytes[..], 0).unwrap() else { panic!("expected a job") };
+        assert_eq!((read.settings.seed, read.time, read.fingerprint), (u64::MAX, 0.5, 7));
+        let text = |job: &Job| toml::to_string(&JobView { image: job.settings.image, camera: job.camera.clone() }).unwrap();
+        assert_eq!(text(&read), text(&job));
+        assert!(matches!(&read.camera.aperture_shape, ApertureShape::Mask(mask) if mask.weights == [0.25, 1.0]));
+    }
+
+    #[test]
+    fn refuses_oversized_messages() {
+        let mut bytes = vec![];
+        Message::Result { id: 0, pixels: vec![Pixel::default(); 3] }.write(&mut bytes).unwrap();
+        assert!(Message::read(&mut &bytes[..], 3).is_ok());
+        let error = Message::read(&mut &bytes[..], 2).err().unwrap();
+        assert!(error.to_string().contains("bigger than any tile"), "{}", error);
+
+        // Only the length is there; it's refused before anything is allocated for the rest.
+        let mut bytes = vec![7];
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        let error = Message::read(&mut &bytes[..], 0).err().unwrap();
+        assert!(error.to_string().contains("too long"), "{}", error);
+    }
+
+    #[test]
+    fn workers_render_the_same_image() {
+        let scene = load();
+        let mut settings = RenderSettings::for_scene(&scene);
+        settings.image.width = 24;
+        settings.image.samples_per_pixel = Some(5);
+        settings.samples_per_pass = 2;
+        settings.tile_size = 8;
+        let local = Renderer::new().render_film(&scene, &settings).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let workers: Vec<_> = (0..2)
+            .map(|_| thread::spawn(move || work(TcpStream::connect(addr).unwrap(), || Ok(load()))))
+            .collect();
+        let remote = Renderer::new().render_distributed(&listener, &scene, 0.0, &settings).unwrap();
+
+        let rendered: u64 = workers.into_iter().map(|w| w.join().unwrap().unwrap()).sum();
+        assert_eq!(rendered, 3 * 6);
+        assert_eq!(remote.beauty().pixels, local.beauty().pixels);
+        assert!(remote.pixels.iter().all(|p| p.samples == 5));
+    }
+
+    // Render `scene` posed at 0 seconds here and on a worker that loads orbit.toml as it is.
+    fn render_orbit_both_ways(scene: Scene) -> (Film, Film) {
+        let scene = scene.at(0.0).unwrap();
+        let mut settings = RenderSettings::for_scene(&scene);
+        settings.image.width = 16;
+        settings.image.samples_per_pixel = Some(2);
+        let local = Renderer::new().render_film(&scene, &settings).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let load = || Ok(Scene::parse(include_str!("../scenes/orbit.toml")).unwrap());
+        let worker = thread::spawn(move || work(TcpStream::connect(addr).unwrap(), load));
+        let remote = Renderer::new().render_distributed(&listener, &scene, 0.0, &settings).unwrap();
+
+        assert_eq!(worker.join().unwrap().unwrap(), 1);
+        (local, remote)
+    }
+
+    #[test]
+    fn workers_pose_animations_the_same() {
+        let scene = Scene::parse(include_str!("../scenes/orbit.toml")).unwrap();
+        let (local, remote) = render_orbit_both_ways(scene);
+        assert_eq!(remote.beauty().pixels, local.beauty().pixels);
+
+        // A shutter given on the command line only reaches the worker with the job, but the
+        // objects still move while it's open there too.
+        let mut scene = Scene::parse(include_str!("../scenes/orbit.toml")).unwrap();
+        scene.camera.exposure = Some(Exposure { f_number: 8.0, shutter: 0.25, iso: 100.0 });
+        let (local, remote) = render_orbit_both_ways(scene);
+        assert_eq!(remote.beauty().pixels, local.beauty().pixels);
+    }
+
+    #[test]
+    fn workers_follow_the_camera_down_the_rows() {
+        let still = Scene::parse(include_str!("../scenes/orbit.toml")).unwrap();
+        let (still, _) = render_orbit_both_ways(still);
+
+        // Each row is cast from further along the camera's tracks, on the worker as here.
+        let mut scene = Scene::parse(include_str!("../scenes/orbit.toml")).unwrap();
+        scene.camera.rolling_shutter = Some(0.5);
+        let (local, remote) = render_orbit_both_ways(scene);
+        assert_ne!(local.beauty().pixels, still.beauty().pixels);
+        assert_eq!(remote.beauty().pixels, local.beauty().pixels);
+    }
+
+    #[test]
+    fn refuses_a_different_load() {
+        let scene = load();
+        let mut settings = RenderSettings::for_scene(&scene);
+        settings.image.width = 8;
+        settings.image.samples_per_pixel = Some(1);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let wrong = thread::spawn(move || {
+            let mut other = load();
+            other.world.pop();
+            work(TcpStream::connect(addr).unwrap(), || Ok(other))
+        });
+        let right = thread::spawn(move || {
+            // Join once the first worker has been turned away.
+            thread::sleep(Duration::from_millis(200));
+            work(TcpStream::connect(addr).unwrap(), || Ok(load()))
+        });
+        Renderer::new().render_distributed(&listener, &scene, 0.0, &settings).unwrap();
+
+        let error = wrong.join().unwrap().unwrap_err();
+        assert!(error.to_string().contains("not the same"), "{}", error);
+        assert_eq!(right.join().unwrap().unwrap(), 1);
+    }
+}
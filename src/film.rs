@@ -0,0 +1,252 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::image::*;
+use crate::vec3::*;
+
+/// The displayed depth of the farthest hit. Misses are white, above it, so that depth read back
+/// from an 8/16-bit image still tells the sky from the nearest geometry.
+pub const DISPLAY_DEPTH_FAR: f32 = 0.9;
+
+/// First-hit data recorded for a camera sample, used for the auxiliary output passes.
+#[derive(Clone, Copy, Debug)]
+pub struct FirstHit {
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub position: Point3,
+    pub depth: f32,
+    pub object_id: usize,
+}
+
+/// The auxiliary (non-beauty) passes a `Film` can write out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aov {
+    Albedo,
+    Normal,
+    Depth,
+    Position,
+    ObjectId,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 5] = [Aov::Albedo, Aov::Normal, Aov::Depth, Aov::Position, Aov::ObjectId];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+        }
+    }
+
+    /// `out/image.png` -> `out/image.albedo.png`
+    pub fn path_for(&self, beauty_path: &Path) -> PathBuf {
+        let stem = beauty_path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match beauty_path.extension() {
+            Some(ext) => format!("{}.{}.{}", stem, self.name(), ext.to_string_lossy()),
+            None => format!("{}.{}", stem, self.name()),
+        };
+        beauty_path.with_file_name(name)
+    }
+}
+
+/// Running sums for a single pixel. Misses still count towards beauty and albedo (which takes the
+/// background colour), but depth and position are only averaged over samples that hit something.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Pixel {
+    pub color: Color,
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub position: Point3,
+    pub depth: f32,
+    pub samples: u32,
+    pub hits: u32,
+    pub object_id: Option<usize>,
+}
+
+impl Pixel {
+    pub fn add_sample(&mut self, color: Color, background: Color, first_hit: Option<FirstHit>) {
+        self.color += color;
+        self.samples += 1;
+
+        match first_hit {
+            Some(hit) => {
+                self.albedo += hit.albedo;
+                self.normal += hit.normal;
+                self.position += hit.position;
+                self.depth += hit.depth;
+                self.hits += 1;
+                self.object_id.get_or_insert(hit.object_id);
+            }
+            None => self.albedo += background,
+        }
+    }
+
+    /// Add another accumulator's samples to this one, e.g. a later pass over the same pixel.
+    pub fn merge(&mut self, other: &Pixel) {
+        self.color += other.color;
+        self.albedo += other.albedo;
+        self.normal += other.normal;
+        self.position += other.position;
+        self.depth += other.depth;
+        self.samples += other.samples;
+        self.hits += other.hits;
+        self.object_id = self.object_id.or(other.object_id);
+    }
+}
+
+/// Per-pixel beauty plus first-hit AOVs, stored row-major from the top row down like `Image`.
+#[derive(Clone)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Pixel>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Film {
+        Film {
+            width,
+            height,
+            pixels: vec![Pixel::default(); width * height],
+        }
+    }
+
+    pub fn beauty(&self) -> Image {
+        self.image(|p| p.color / p.samples.max(1) as f32)
+    }
+
+    /// Raw AOV values: linear, unbounded, and with misses left at zero (or infinity for depth).
+    pub fn aov(&self, aov: Aov) -> Image {
+        let mut image = match aov {
+            Aov::Albedo => self.image(|p| p.albedo / p.samples.max(1) as f32),
+            Aov::Normal => self.image(|p| {
+                if p.hits == 0 || p.normal.near_zero() { Vec3::zero() } else { Vec3::unit_vector(&p.normal) }
+            }),
+            Aov::Depth => self.image(|p| {
+                let depth = if p.hits == 0 { f32::INFINITY } else { p.depth / p.hits as f32 };
+                Color::new(depth, depth, depth)
+            }),
+            Aov::Position => self.image(|p| p.position / p.hits.max(1) as f32),
+            Aov::ObjectId => self.image(|p| {
+                let id = p.object_id.map_or(-1.0, |id| id as f32);
+                Color::new(id, id, id)
+            }),
+        };
+        image.gamma_correct = aov == Aov::Albedo;
+        image
+    }
+
+    /// AOV values remapped into [0, 1] so they are viewable in an 8/16-bit image.
+    pub fn aov_display(&self, aov: Aov) -> Image {
+        let mut image = self.aov(aov);
+        match aov {
+            Aov::Albedo => {}
+            Aov::Normal => {
+                for (pixel, p) in image.pixels.iter_mut().zip(&self.pixels) {
+                    if p.hits > 0 {
+                        *pixel = 0.5 * (*pixel + Color::new(1.0, 1.0, 1.0));
+                    }
+                }
+            }
+            Aov::Depth => {
+                normalize(&mut image, &self.pixels);
+                for (pixel, p) in image.pixels.iter_mut().zip(&self.pixels) {
+                    *pixel = if p.hits > 0 { DISPLAY_DEPTH_FAR * *pixel } else { Color::new(1.0, 1.0, 1.0) };
+                }
+            }
+            Aov::Position => normalize(&mut image, &self.pixels),
+            Aov::ObjectId => {
+                for (pixel, p) in image.pixels.iter_mut().zip(&self.pixels) {
+                    *pixel = p.object_id.map_or(Color::zero(), id_color);
+                }
+            }
+        }
+        image
+    }
+
+    /// Write each AOV next to the beauty image at `path`, as `<stem>.<aov>.<ext>`.
+    pub fn write_aovs(&self, path: &Path, format: ImageFormat) -> io::Result<()> {
+        for aov in Aov::ALL {
+            let image = if format.is_float() { self.aov(aov) } else { self.aov_display(aov) };
+            let mut writer = BufWriter::new(File::create(aov.path_for(path))?);
+            format.write_image(&mut writer, &image)?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn image<F: Fn(&Pixel) -> Color>(&self, f: F) -> Image {
+        Image::from_pixels(self.width, self.height, self.pixels.iter().map(f).collect())
+    }
+}
+
+// Rescale each channel to [0, 1] over the pixels that hit something.
+fn normalize(image: &mut Image, pixels: &[Pixel]) {
+    let mut min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
+    let mut max = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
+    for (pixel, p) in image.pixels.iter().zip(pixels) {
+        if p.hits > 0 {
+            for i in 0..3 {
+                min[i] = f32::min(min[i], pixel[i]);
+                max[i] = f32::max(max[i], pixel[i]);
+            }
+        }
+    }
+
+    for (pixel, p) in image.pixels.iter_mut().zip(pixels) {
+        if p.hits == 0 {
+            *pixel = Color::zero();
+            continue;
+        }
+        for i in 0..3 {
+            let range = max[i] - min[i];
+            pixel[i] = if range > 0.0 { (pixel[i] - min[i]) / range } else { 0.0 };
+        }
+    }
+}
+
+// A stable, well-spread colour per object index.
+fn id_color(id: usize) -> Color {
+    let mut h = (id as u32).wrapping_add(1).wrapping_mul(0x9E37_79B9);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 13;
+    Color::new(
+        (h & 0xFF) as f32 / 255.0,
+        ((h >> 8) & 0xFF) as f32 / 255.0,
+        ((h >> 16) & 0xFF) as f32 / 255.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aov_paths_sit_next_to_beauty() {
+        assert_eq!(Aov::Normal.path_for(Path::new("out/render.png")), PathBuf::from("out/render.normal.png"));
+        assert_eq!(Aov::ObjectId.path_for(Path::new("render")), PathBuf::from("render.object_id"));
+    }
+
+    #[test]
+    fn depth_averages_only_hits() {
+        let mut film = Film::new(1, 1);
+        let hit = FirstHit {
+            albedo: Color::new(1.0, 0.0, 0.0),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            position: Point3::new(0.0, 0.0, -2.0),
+            depth: 2.0,
+            object_id: 3,
+        };
+        film.pixels[0].add_sample(Color::zero(), Color::new(0.0, 0.0, 1.0), Some(hit));
+        film.pixels[0].add_sample(Color::zero(), Color::new(0.0, 0.0, 1.0), None);
+
+        assert_eq!(film.aov(Aov::Depth).pixels[0], Color::new(2.0, 2.0, 2.0));
+        assert_eq!(film.aov(Aov::Albedo).pixels[0], Color::new(0.5, 0.0, 0.5));
+        assert_eq!(film.aov(Aov::ObjectId).pixels[0], Color::new(3.0, 3.0, 3.0));
+    }
+}
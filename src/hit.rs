@@ -1,21 +1,39 @@
+use enum_dispatch::enum_dispatch;
+use serde::Serialize;
+
+use crate::animation::Moving;
 use crate::material::*;
 use crate::ray::*;
+use crate::transform::*;
+use crate::util::PI;
 use crate::vec3::*;
 
 // #[derive(Debug, PartialEq)]
-pub struct HitRecord {
+pub struct HitRecord<'a> {
     pub p: Point3,        // Point3 where the ray hit the hittable
-    pub normal: Vec3,     // Normal pointing outwards from the object at p
-    pub t: f32,           // ?? not used yet
-    pub front_face: bool, // ?? not used yet
-    pub material: Material,
+    pub normal: Vec3,     // Normal at p, flipped to face against the ray
+    pub t: f32,           // Ray parameter at the hit
+    pub front_face: bool, // Whether the ray hit the outside of the surface
+    pub u: f32,           // Surface coordinates at p, for textures
+    pub v: f32,
+    pub material: &'a Material,
+}
+
+impl HitRecord<'_> {
+    // Orient the normal against the ray, remembering which side was hit.
+    fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3) {
+        self.front_face = Vec3::dot(&r.dir, &outward_normal) < 0.0;
+        self.normal = if self.front_face { outward_normal } else { -outward_normal };
+    }
 }
 
+#[enum_dispatch]
 pub trait Hittable {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
 }
 
 // #[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Sphere {
     pub center: Point3,
     pub radius: f32,
@@ -23,7 +41,7 @@ pub struct Sphere {
 }
 
 impl Hittable for Sphere{
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         // Calculate the discriminant (the part under the sqrt) of the quadratic equation.
         let oc = r.orig - self.center;
         let a = r.dir.length_squared();
@@ -52,15 +70,206 @@ impl Hittable for Sphere{
 
         // Check whether the ray is moving the same direction as the outward normal.
         let outward_normal = (p - self.center) / self.radius;
-        let front_face = Vec3::dot(&r.dir, &outward_normal) < 0.0;
-        let normal = if front_face { outward_normal } else { -outward_normal };
-
-        return Option::Some(HitRecord {
+        let (u, v) = sphere_uv(&outward_normal);
+        let mut rec = HitRecord {
             t: root,
             p,
-            normal,
-            front_face,
-            material: self.material.into(),
-        });
+            normal: outward_normal,
+            front_face: true,
+            u,
+            v,
+            material: &self.material,
+        };
+        rec.set_face_normal(r, outward_normal);
+        Option::Some(rec)
+    }
+}
+
+// u runs around the y axis from -x, v from the south pole (v = 0) to the north pole (v = 1).
+fn sphere_uv(p: &Point3) -> (f32, f32) {
+    let theta = f32::acos(-p.y);
+    let phi = f32::atan2(-p.z, p.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+/// The axis an `AxisRect` faces along.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    fn index(self) -> usize {
+        match self {
+            Axis::X => 0,
+            Axis::Y => 1,
+            Axis::Z => 2,
+        }
+    }
+
+    // The two axes spanning the plane: YZ, XZ and XY respectively.
+    fn plane_axes(self) -> (usize, usize) {
+        match self {
+            Axis::X => (1, 2),
+            Axis::Y => (0, 2),
+            Axis::Z => (0, 1),
+        }
+    }
+}
+
+/// An axis-aligned rectangle at `axis = k`, spanning `min` to `max` in the other two axes (in
+/// x, y, z order, e.g. (x, z) for `Axis::Y`). Its outward normal points along +axis.
+#[derive(Clone, Debug, Serialize)]
+pub struct AxisRect {
+    pub axis: Axis,
+    pub min: (f32, f32),
+    pub max: (f32, f32),
+    pub k: f32,
+    pub material: Material,
+}
+
+impl Hittable for AxisRect {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let n = self.axis.index();
+        let (a, b) = self.axis.plane_axes();
+
+        let t = (self.k - r.orig[n]) / r.dir[n];
+        if !(t >= t_min && t <= t_max) {
+            return Option::None;
+        }
+        let p = r.at(t);
+        if p[a] < self.min.0 || p[a] > self.max.0 || p[b] < self.min.1 || p[b] > self.max.1 {
+            return Option::None;
+        }
+
+        let mut outward_normal = Vec3::zero();
+        outward_normal[n] = 1.0;
+        let mut rec = HitRecord {
+            t,
+            p,
+            normal: outward_normal,
+            front_face: true,
+            u: (p[a] - self.min.0) / (self.max.0 - self.min.0),
+            v: (p[b] - self.min.1) / (self.max.1 - self.min.1),
+            material: &self.material,
+        };
+        rec.set_face_normal(r, outward_normal);
+        Option::Some(rec)
+    }
+}
+
+/// An axis-aligned box made of six rectangles.
+#[derive(Clone, Debug, Serialize)]
+pub struct Cuboid {
+    sides: Vec<AxisRect>,
+}
+
+impl Cuboid {
+    pub fn new(min: Point3, max: Point3, material: Material) -> Cuboid {
+        let mut sides = vec![];
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            let (a, b) = axis.plane_axes();
+            for k in [min[axis.index()], max[axis.index()]] {
+                sides.push(AxisRect {
+                    axis,
+                    min: (min[a], min[b]),
+                    max: (max[a], max[b]),
+                    k,
+                    material: material.clone(),
+                });
+            }
+        }
+        Cuboid { sides }
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut closest: Option<(usize, HitRecord)> = None;
+        for (i, side) in self.sides.iter().enumerate() {
+            let t_far = closest.as_ref().map_or(t_max, |(_, rec)| rec.t);
+            if let Some(rec) = side.hit(r, t_min, t_far) {
+                closest = Some((i, rec));
+            }
+        }
+
+        // Sides go in min, max order for each axis, and the min sides face along -axis.
+        closest.map(|(i, mut rec)| {
+            let mut outward_normal = Vec3::zero();
+            outward_normal[self.sides[i].axis.index()] = if i % 2 == 0 { -1.0 } else { 1.0 };
+            rec.set_face_normal(r, outward_normal);
+            rec
+        })
+    }
+}
+
+/// Another object, moved into place by a rigid transform.
+#[derive(Clone, Debug, Serialize)]
+pub struct Instance {
+    pub object: Box<Object>,
+    pub transform: Transform,
+}
+
+impl Hittable for Instance {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        hit_transformed(&self.object, &self.transform, r, t_min, t_max)
+    }
+}
+
+// Hit `object` moved into place by `transform`.
+pub(crate) fn hit_transformed<'a>(object: &'a Object, transform: &Transform, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'a>> {
+    // Intersect in object space. Rigid transforms keep the ray parameter t the same.
+    let local = Ray::new(transform.point_to_object(&r.orig), transform.vector_to_object(&r.dir)).with_time(r.time);
+    let mut rec = object.hit(&local, t_min, t_max)?;
+    rec.p = transform.point_to_world(&rec.p);
+    rec.normal = transform.vector_to_world(&rec.normal);
+    Option::Some(rec)
+}
+
+#[enum_dispatch(Hittable)]
+#[derive(Clone, Debug, Serialize)]
+pub enum Object {
+    Sphere,
+    AxisRect,
+    Cuboid,
+    Instance,
+    Moving,
+}
+
+impl Object {
+    /// The middle of the object, where focusing on it aims.
+    pub fn center(&self) -> Point3 {
+        match self {
+            Object::Sphere(sphere) => sphere.center,
+            Object::AxisRect(rect) => {
+                let (a, b) = rect.axis.plane_axes();
+                let mut center = Point3::zero();
+                center[rect.axis.index()] = rect.k;
+                center[a] = (rect.min.0 + rect.max.0) / 2.0;
+                center[b] = (rect.min.1 + rect.max.1) / 2.0;
+                center
+            }
+            // The sides' planes are the min and max of x, then y, then z.
+            Object::Cuboid(cuboid) => {
+                let middle = |axis: usize| (cuboid.sides[2 * axis].k + cuboid.sides[2 * axis + 1].k) / 2.0;
+                Point3::new(middle(0), middle(1), middle(2))
+            }
+            Object::Instance(instance) => instance.transform.point_to_world(&instance.object.center()),
+            Object::Moving(moving) => moving.at(0.0).center(),
+        }
+    }
+
+    /// Wrap the object in a transform (identity transforms are skipped).
+    pub fn transformed(self, transform: Transform) -> Object {
+        if transform == Transform::identity() {
+            return self;
+        }
+        Instance {
+            object: Box::new(self),
+            transform,
+        }
+        .into()
     }
 }
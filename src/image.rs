@@ -0,0 +1,390 @@
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::Path;
+
+use enum_dispatch::enum_dispatch;
+
+use crate::util::clamp;
+use crate::vec3::*;
+
+/// A rendered image: linear values per pixel, stored row-major from the top row down.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+    /// Apply gamma 2 when quantizing to an integer format. Off for data passes such as normals.
+    pub gamma_correct: bool,
+}
+
+impl Image {
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> Image {
+        assert_eq!(pixels.len(), width * height, "pixel count does not match image size");
+        Image {
+            width,
+            height,
+            pixels,
+            gamma_correct: true,
+        }
+    }
+
+    // Gamma-correct and quantize every pixel to `max` (255 for 8-bit, 65535 for 16-bit output).
+    fn quantized(&self, max: f32) -> impl Iterator<Item = u16> + '_ {
+        self.pixels.iter().flat_map(move |color| {
+            // sqrt: gamma correction is raise to the power of 1/gamma, and we're using gamma=2, so pow(1/2) -> sqrt
+            let encode = |c: f32| if self.gamma_correct { f32::sqrt(c) } else { c };
+            [color.x, color.y, color.z].map(|c| (clamp(encode(c), 0.0, 1.0) * max).round() as u16)
+        })
+    }
+}
+
+#[enum_dispatch]
+pub trait ImageWriter {
+    fn write_image(&self, w: &mut dyn Write, image: &Image) -> io::Result<()>;
+}
+
+/// Plain-text P3 PPM. Large and slow, but readable by anything.
+#[derive(Clone, Copy, Debug)]
+pub struct PpmAscii;
+
+impl ImageWriter for PpmAscii {
+    fn write_image(&self, w: &mut dyn Write, image: &Image) -> io::Result<()> {
+        writeln!(w, "P3\n{} {}\n255", image.width, image.height)?;
+        let mut values = image.quantized(255.0);
+        while let (Some(r), Some(g), Some(b)) = (values.next(), values.next(), values.next()) {
+            writeln!(w, "{} {} {}", r, g, b)?;
+        }
+        Ok(())
+    }
+}
+
+/// Binary P6 PPM.
+#[derive(Clone, Copy, Debug)]
+pub struct PpmBinary;
+
+impl ImageWriter for PpmBinary {
+    fn write_image(&self, w: &mut dyn Write, image: &Image) -> io::Result<()> {
+        write!(w, "P6\n{} {}\n255\n", image.width, image.height)?;
+        let data: Vec<u8> = image.quantized(255.0).map(|v| v as u8).collect();
+        w.write_all(&data)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Png {
+    pub sixteen_bit: bool,
+}
+
+impl ImageWriter for Png {
+    fn write_image(&self, w: &mut dyn Write, image: &Image) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(w, image.width as u32, image.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+
+        let data: Vec<u8> = if self.sixteen_bit {
+            encoder.set_depth(png::BitDepth::Sixteen);
+            // PNG stores 16-bit samples big-endian.
+            image.quantized(65535.0).flat_map(u16::to_be_bytes).collect()
+        } else {
+            encoder.set_depth(png::BitDepth::Eight);
+            image.quantized(255.0).map(|v| v as u8).collect()
+        };
+
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(&data).map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)
+    }
+}
+
+/// Portable float map: uncompressed linear 32-bit floats, for data passes and further processing.
+#[derive(Clone, Copy, Debug)]
+pub struct Pfm;
+
+impl ImageWriter for Pfm {
+    fn write_image(&self, w: &mut dyn Write, image: &Image) -> io::Result<()> {
+        // A negative scale marks the data as little-endian. Rows are stored bottom to top.
+        write!(w, "PF\n{} {}\n-1.0\n", image.width, image.height)?;
+        let mut data = Vec::with_capacity(image.pixels.len() * 12);
+        for row in image.pixels.chunks(image.width).rev() {
+            for color in row {
+                for c in [color.x, color.y, color.z] {
+                    data.extend_from_slice(&c.to_le_bytes());
+                }
+            }
+        }
+        w.write_all(&data)
+    }
+}
+
+#[enum_dispatch(ImageWriter)]
+#[derive(Clone, Copy, Debug)]
+pub enum ImageFormat {
+    PpmAscii,
+    PpmBinary,
+    Png,
+    Pfm,
+}
+
+impl ImageFormat {
+    /// Pick a format by name: `p3`, `ppm`/`p6`, `png`, `png16` or `pfm`.
+    pub fn from_name(name: &str) -> Option<ImageFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "p3" => Some(PpmAscii.into()),
+            "ppm" | "p6" => Some(PpmBinary.into()),
+            "png" => Some(Png { sixteen_bit: false }.into()),
+            "png16" => Some(Png { sixteen_bit: true }.into()),
+            "pfm" => Some(Pfm.into()),
+            _ => None,
+        }
+    }
+
+    /// Pick a format from a file extension; `.ppm` is written as binary P6.
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(ImageFormat::from_name)
+    }
+
+    /// Whether the format stores linear floats rather than quantized, display-referred values.
+    pub fn is_float(&self) -> bool {
+        matches!(self, ImageFormat::Pfm(_))
+    }
+}
+
+/// A YUV4MPEG2 video stream, one image per frame, for piping into an encoder such as ffmpeg.
+/// Frames are stored as full-resolution 4:4:4 Y'CbCr (BT.601, studio range).
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    width: usize,
+    height: usize,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(mut writer: W, width: usize, height: usize, fps: f32) -> io::Result<Y4mWriter<W>> {
+        // The frame rate is a ratio; millihertz is precise enough for rates such as 29.97.
+        let (num, den) = if fps.fract() == 0.0 { (fps as u32, 1) } else { ((fps * 1000.0).round() as u32, 1000) };
+        writeln!(writer, "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444", width, height, num, den)?;
+        Ok(Y4mWriter { writer, width, height })
+    }
+
+    pub fn write_frame(&mut self, image: &Image) -> io::Result<()> {
+        if (image.width, image.height) != (self.width, self.height) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame size does not match the stream"));
+        }
+        let count = self.width * self.height;
+        let mut planes = vec![0u8; 3 * count];
+        let rgb: Vec<u16> = image.quantized(255.0).collect();
+        for (i, c) in rgb.chunks_exact(3).enumerate() {
+            let (r, g, b) = (c[0] as f32 / 255.0, c[1] as f32 / 255.0, c[2] as f32 / 255.0);
+            planes[i] = (16.0 + 65.481 * r + 128.553 * g + 24.966 * b).round() as u8;
+            planes[count + i] = (128.0 - 37.797 * r - 74.203 * g + 112.0 * b).round() as u8;
+            planes[2 * count + i] = (128.0 + 112.0 * r - 93.786 * g - 18.214 * b).round() as u8;
+        }
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&planes)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Read a P3/P6 PPM, PNG or PFM image back into linear values. Integer formats are scaled to
+/// [0, 1] and, if `gamma_correct` is set, decoded from gamma 2 the same way they were written.
+pub fn read_image(path: &Path, gamma_correct: bool) -> io::Result<Image> {
+    let bytes = fs::read(path)?;
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), msg));
+
+    let (width, height, values, max) = match bytes.get(..2) {
+        Some(b"P3") | Some(b"P6") | Some(b"PF") => read_netpbm(&bytes).map_err(|msg| invalid(&msg))?,
+        Some([0x89, b'P']) => read_png(&bytes).map_err(|e| invalid(&e.to_string()))?,
+        _ => return Err(invalid("unrecognised image format")),
+    };
+
+    let decode = |v: f32| match max {
+        None => v,
+        Some(max) if gamma_correct => (v / max) * (v / max),
+        Some(max) => v / max,
+    };
+    let pixels = values
+        .chunks_exact(3)
+        .map(|c| Color::new(decode(c[0]), decode(c[1]), decode(c[2])))
+        .collect();
+
+    let mut image = Image::from_pixels(width, height, pixels);
+    image.gamma_correct = gamma_correct;
+    Ok(image)
+}
+
+// Returns width, height, RGB values top row first, and the maximum integer value (None for floats).
+type RawImage = (usize, usize, Vec<f32>, Option<f32>);
+
+fn read_netpbm(bytes: &[u8]) -> Result<RawImage, String> {
+    // Header tokens are whitespace separated, with `#` comments running to the end of the line.
+    let mut pos = 0;
+    let mut token = || -> Result<String, String> {
+        loop {
+            while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if pos < bytes.len() && bytes[pos] == b'#' {
+                while pos < bytes.len() && bytes[pos] != b'\n' {
+                    pos += 1;
+                }
+                continue;
+            }
+            break;
+        }
+        let start = pos;
+        while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err("unexpected end of header".to_string());
+        }
+        Ok(String::from_utf8_lossy(&bytes[start..pos]).into_owned())
+    };
+
+    let magic = token()?;
+    let width: usize = token()?.parse().map_err(|_| "invalid width")?;
+    let height: usize = token()?.parse().map_err(|_| "invalid height")?;
+    let scale: f32 = token()?.parse().map_err(|_| "invalid maximum value")?;
+    let count = width * height * 3;
+
+    let values = match magic.as_str() {
+        "P3" => {
+            let values: Result<Vec<f32>, String> = (0..count)
+                .map(|_| token()?.parse::<f32>().map_err(|_| "invalid pixel value".to_string()))
+                .collect();
+            values?
+        }
+        "P6" => {
+            // Exactly one whitespace byte separates the header from the binary data.
+            let data = bytes.get(pos + 1..).unwrap_or_default();
+            if scale > 255.0 {
+                data.chunks_exact(2).take(count).map(|b| u16::from_be_bytes([b[0], b[1]]) as f32).collect()
+            } else {
+                data.iter().take(count).map(|&b| b as f32).collect()
+            }
+        }
+        _ => {
+            let data = bytes.get(pos + 1..).unwrap_or_default();
+            let little_endian = scale < 0.0;
+            let floats: Vec<f32> = data
+                .chunks_exact(4)
+                .take(count)
+                .map(|b| {
+                    let b = [b[0], b[1], b[2], b[3]];
+                    if little_endian { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) }
+                })
+                .collect();
+            if floats.len() < count {
+                return Err("truncated pixel data".to_string());
+            }
+            // PFM rows run bottom to top.
+            let rows: Vec<&[f32]> = floats.chunks(width * 3).rev().collect();
+            return Ok((width, height, rows.concat(), None));
+        }
+    };
+
+    if values.len() < count {
+        return Err("truncated pixel data".to_string());
+    }
+    Ok((width, height, values, Some(scale)))
+}
+
+fn read_png(bytes: &[u8]) -> Result<RawImage, png::DecodingError> {
+    let mut decoder = png::Decoder::new(Cursor::new(bytes));
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info()?;
+    let mut data = vec![0; reader.output_buffer_size().unwrap_or_default()];
+    let info = reader.next_frame(&mut data)?;
+    let data = &data[..info.buffer_size()];
+
+    let samples: Vec<f32> = match info.bit_depth {
+        png::BitDepth::Sixteen => data.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as f32).collect(),
+        _ => data.iter().map(|&b| b as f32).collect(),
+    };
+    let max = match info.bit_depth {
+        png::BitDepth::Sixteen => 65535.0,
+        _ => 255.0,
+    };
+
+    // Drop alpha and expand greyscale so every pixel is RGB.
+    let values = match info.color_type {
+        png::ColorType::Rgb => samples,
+        png::ColorType::Rgba => samples.chunks_exact(4).flat_map(|c| [c[0], c[1], c[2]]).collect(),
+        png::ColorType::Grayscale => samples.iter().flat_map(|&c| [c, c, c]).collect(),
+        png::ColorType::GrayscaleAlpha => samples.chunks_exact(2).flat_map(|c| [c[0], c[0], c[0]]).collect(),
+        png::ColorType::Indexed => unreachable!("EXPAND converts indexed images to RGB"),
+    };
+    Ok((info.width as usize, info.height as usize, values, Some(max)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker() -> Image {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let grey = Color::new(0.25, 0.25, 0.25);
+        Image::from_pixels(2, 2, vec![white, Color::zero(), Color::zero(), grey])
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert!(matches!(ImageFormat::from_path(Path::new("out.ppm")), Some(ImageFormat::PpmBinary(_))));
+        assert!(matches!(ImageFormat::from_path(Path::new("out.PNG")), Some(ImageFormat::Png(Png { sixteen_bit: false }))));
+        assert!(matches!(ImageFormat::from_path(Path::new("out.p3")), Some(ImageFormat::PpmAscii(_))));
+        assert!(ImageFormat::from_path(Path::new("out.jpg")).is_none());
+    }
+
+    #[test]
+    fn binary_ppm_matches_ascii() {
+        let image = checker();
+        let mut ascii = vec![];
+        PpmAscii.write_image(&mut ascii, &image).unwrap();
+        let mut binary = vec![];
+        PpmBinary.write_image(&mut binary, &image).unwrap();
+
+        let ascii = String::from_utf8(ascii).unwrap();
+        let ascii_values: Vec<u8> = ascii.split_whitespace().skip(4).map(|v| v.parse().unwrap()).collect();
+        assert_eq!(&binary[binary.len() - 12..], &ascii_values[..]);
+        assert_eq!(&ascii_values[..3], &[255, 255, 255]);
+        assert_eq!(&ascii_values[9..], &[128, 128, 128]);
+        // Full scale is the largest value at either depth.
+        assert_eq!(image.quantized(65535.0).take(3).collect::<Vec<_>>(), [65535; 3]);
+    }
+
+    #[test]
+    fn writes_y4m_frames() {
+        let mut video = Y4mWriter::new(vec![], 2, 2, 29.97).unwrap();
+        video.write_frame(&checker()).unwrap();
+        video.write_frame(&checker()).unwrap();
+        assert!(video.write_frame(&Image::from_pixels(1, 1, vec![Color::zero()])).is_err());
+
+        let bytes = video.into_inner();
+        let header = b"YUV4MPEG2 W2 H2 F29970:1000 Ip A1:1 C444\n";
+        assert!(bytes.starts_with(header));
+        assert_eq!(bytes.len(), header.len() + 2 * (6 + 12));
+        // White and black at the ends of studio range, with neutral chroma.
+        let frame = &bytes[header.len() + 6..header.len() + 18];
+        assert_eq!((frame[0], frame[1], frame[4], frame[8]), (235, 16, 128, 128));
+    }
+
+    #[test]
+    fn read_back_written_images() {
+        let image = checker();
+        let dir = std::env::temp_dir();
+        for name in ["p3", "ppm", "png", "png16", "pfm"] {
+            let path = dir.join(format!("raytracing-read-back.{}", name));
+            let mut file = fs::File::create(&path).unwrap();
+            ImageFormat::from_name(name).unwrap().write_image(&mut file, &image).unwrap();
+
+            let read = read_image(&path, true).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!((read.width, read.height), (2, 2));
+            for (a, b) in read.pixels.iter().zip(&image.pixels) {
+                assert!((*a - *b).length() < 0.02, "{}: {} != {}", name, a, b);
+            }
+        }
+    }
+}
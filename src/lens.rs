@@ -0,0 +1,544 @@
+//! A camera that traces rays through a real lens design, for the focus falloff, bokeh and
+//! distortion of actual lenses, and the light they lose towards the edges.
+//!
+//! A prescription lists the lens's spherical surfaces from the front (the scene side) to the
+//! back, one per line, in millimetres:
+//!
+//! ```text
+//! # radius  thickness  ior    aperture
+//!   29.475  3.76       1.67   25.2
+//!   84.83   0.12       1      25.2
+//!   0       4.5        0      17.1     <- radius 0 marks the aperture stop
+//! ```
+//!
+//! `radius` is the surface's radius of curvature (positive when its centre is behind it),
+//! `thickness` the distance along the axis to the next surface (or, for the last, to the
+//! film), `ior` the index of refraction behind the surface (1 or 0 for air) and `aperture` the
+//! diameter of the surface.
+//!
+//! The optics follow the realistic camera of Physically Based Rendering (Pharr, Jakob and
+//! Humphreys, section 6.4). Lens space has the film at z = 0 and the lens in front of it, along
+//! +z; the image forms upside down and is turned the right way up when rays are generated.
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::camera::CameraModel;
+use crate::ray::*;
+use crate::util::random_f32;
+use crate::vec3::*;
+
+/// One spherical surface of a lens, or the aperture stop if `radius` is 0. Sizes in millimetres.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LensElement {
+    pub radius: f32,
+    pub thickness: f32,
+    pub ior: f32,
+    pub aperture_radius: f32,
+}
+
+impl LensElement {
+    fn is_stop(&self) -> bool {
+        self.radius == 0.0
+    }
+}
+
+/// A lens prescription: its surfaces from front to back.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LensSystem {
+    pub elements: Vec<LensElement>,
+}
+
+impl LensSystem {
+    pub fn load(path: &Path) -> Result<LensSystem, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("could not read lens {}: {}", path.display(), e))?;
+        LensSystem::parse(&source).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Read a prescription in the format described in the module documentation.
+    pub fn parse(source: &str) -> Result<LensSystem, String> {
+        let mut elements = vec![];
+        for (i, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values: Vec<f32> = line
+                .split_whitespace()
+                .map(|v| v.parse().map_err(|_| format!("line {}: `{}` is not a number", i + 1, v)))
+                .collect::<Result<_, _>>()?;
+            let [radius, thickness, ior, aperture] = values[..] else {
+                return Err(format!("line {}: expected radius, thickness, ior and aperture", i + 1));
+            };
+            elements.push(LensElement {
+                radius,
+                thickness,
+                ior,
+                aperture_radius: aperture / 2.0,
+            });
+        }
+        let system = LensSystem { elements };
+        system.validate()?;
+        Ok(system)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.elements.is_empty() {
+            return Err("a lens needs at least one surface".to_string());
+        }
+        for (i, e) in self.elements.iter().enumerate() {
+            let values = [e.radius, e.thickness, e.ior, e.aperture_radius];
+            if !values.iter().all(|v| v.is_finite()) || e.thickness < 0.0 || e.aperture_radius <= 0.0 {
+                return Err(format!("surface {}: bad radius, thickness or aperture", i + 1));
+            }
+            if !(e.ior >= 1.0 || (e.is_stop() && e.ior == 0.0)) {
+                return Err(format!("surface {}: ior must be at least 1, got {}", i + 1, e.ior));
+            }
+        }
+        if self.elements.iter().filter(|e| e.is_stop()).count() > 1 {
+            return Err("a lens can only have one aperture stop".to_string());
+        }
+        Ok(())
+    }
+
+    /// The diameter of the aperture stop, if there is one.
+    pub fn stop_diameter(&self) -> Option<f32> {
+        self.elements.iter().find(|e| e.is_stop()).map(|e| 2.0 * e.aperture_radius)
+    }
+
+    fn rear_z(&self) -> f32 {
+        self.elements[self.elements.len() - 1].thickness
+    }
+
+    fn front_z(&self) -> f32 {
+        self.elements.iter().map(|e| e.thickness).sum()
+    }
+
+    fn rear_radius(&self) -> f32 {
+        self.elements[self.elements.len() - 1].aperture_radius
+    }
+
+    // The ray out of the front of the lens for a ray from the film, or None if it's blocked.
+    fn trace_from_film(&self, r: &Ray) -> Option<Ray> {
+        // Work with the lens along -z, as the surfaces are laid out from the film forwards.
+        let mut o = Point3::new(r.orig.x, r.orig.y, -r.orig.z);
+        let mut d = Vec3::new(r.dir.x, r.dir.y, -r.dir.z);
+        let mut element_z = 0.0;
+        for i in (0..self.elements.len()).rev() {
+            let element = &self.elements[i];
+            element_z -= element.thickness;
+            let (t, normal) = if element.is_stop() {
+                if d.z >= 0.0 {
+                    return None;
+                }
+                ((element_z - o.z) / d.z, None)
+            } else {
+                let (t, n) = intersect_surface(element.radius, element_z + element.radius, &o, &d)?;
+                (t, Some(n))
+            };
+            let hit = o + t * d;
+            if hit.x * hit.x + hit.y * hit.y > element.aperture_radius * element.aperture_radius {
+                return None;
+            }
+            o = hit;
+            if let Some(n) = normal {
+                let eta_i = element.ior;
+                let eta_t = if i > 0 && self.elements[i - 1].ior != 0.0 { self.elements[i - 1].ior } else { 1.0 };
+                d = refract(&Vec3::unit_vector(&-d), &n, eta_i / eta_t)?;
+            }
+        }
+        Some(Ray::new(Point3::new(o.x, o.y, -o.z), Vec3::new(d.x, d.y, -d.z)))
+    }
+
+    // The ray onto the film for a ray from the scene, or None if it's blocked.
+    fn trace_from_scene(&self, r: &Ray) -> Option<Ray> {
+        let mut o = Point3::new(r.orig.x, r.orig.y, -r.orig.z);
+        let mut d = Vec3::new(r.dir.x, r.dir.y, -r.dir.z);
+        let mut element_z = -self.front_z();
+        for (i, element) in self.elements.iter().enumerate() {
+            let (t, normal) = if element.is_stop() {
+                ((element_z - o.z) / d.z, None)
+            } else {
+                let (t, n) = intersect_surface(element.radius, element_z + element.radius, &o, &d)?;
+                (t, Some(n))
+            };
+            let hit = o + t * d;
+            if hit.x * hit.x + hit.y * hit.y > element.aperture_radius * element.aperture_radius {
+                return None;
+            }
+            o = hit;
+            if let Some(n) = normal {
+                let eta_i = if i == 0 || self.elements[i - 1].ior == 0.0 { 1.0 } else { self.elements[i - 1].ior };
+                let eta_t = if element.ior != 0.0 { element.ior } else { 1.0 };
+                d = refract(&Vec3::unit_vector(&-d), &n, eta_i / eta_t)?;
+            }
+            element_z += element.thickness;
+        }
+        Some(Ray::new(Point3::new(o.x, o.y, -o.z), Vec3::new(d.x, d.y, -d.z)))
+    }
+
+    // The principal plane and focal point of the thick-lens approximation, found by tracing a
+    // ray parallel to the axis through the lens from each side.
+    fn thick_lens(&self, film_diagonal: f32) -> Option<[(f32, f32); 2]> {
+        let x = 0.001 * film_diagonal;
+        let from_scene = Ray::new(Point3::new(x, 0.0, self.front_z() + 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let to_film = self.trace_from_scene(&from_scene)?;
+        let from_film = Ray::new(Point3::new(x, 0.0, self.rear_z() - 1.0), Vec3::new(0.0, 0.0, 1.0));
+        let to_scene = self.trace_from_film(&from_film)?;
+        Some([cardinal_points(&from_scene, &to_film), cardinal_points(&from_film, &to_scene)])
+    }
+
+    /// The distance from the rear surface to the film that brings `focus_dist` (from the
+    /// film, in millimetres) into focus.
+    pub fn focus(&self, focus_dist: f32, film_diagonal: f32) -> Result<f32, String> {
+        let [(pz0, fz0), (pz1, _)] = self.thick_lens(film_diagonal).ok_or("rays don't make it through the lens")?;
+        let f = fz0 - pz0;
+        let z = -focus_dist;
+        let c = (pz1 - z - pz0) * (pz1 - z - 4.0 * f - pz0);
+        if c.is_nan() || c <= 0.0 {
+            return Err(format!("the lens can't focus as close as {} mm", focus_dist));
+        }
+        let delta = 0.5 * (pz1 - z + pz0 - c.sqrt());
+        Ok(self.rear_z() + delta)
+    }
+
+    /// A copy with the aperture narrowed as `options` say, and focused `focus_dist` scene
+    /// units away.
+    pub fn focused(&self, options: &LensOptions, focus_dist: f32) -> Result<LensSystem, String> {
+        let mut system = self.clone();
+        if let Some(diameter) = options.aperture_diameter {
+            let stop = system.elements.iter_mut().find(|e| e.is_stop()).ok_or("the lens has no aperture stop to narrow")?;
+            if diameter > 2.0 * stop.aperture_radius {
+                return Err(format!(
+                    "the aperture can open to at most {} mm, not {} mm",
+                    2.0 * stop.aperture_radius,
+                    diameter
+                ));
+            }
+            stop.aperture_radius = diameter / 2.0;
+        }
+        let rear = system.focus(focus_dist * options.scale, options.film_diagonal)?;
+        let last = system.elements.len() - 1;
+        system.elements[last].thickness = rear;
+        Ok(system)
+    }
+
+    /// The effective focal length, in millimetres.
+    pub fn focal_length(&self, film_diagonal: f32) -> Option<f32> {
+        self.thick_lens(film_diagonal).map(|[(pz, fz), _]| fz - pz)
+    }
+}
+
+// Where a ray meets a spherical surface centred on the axis at `z_center`, and the surface
+// normal facing back along the ray.
+fn intersect_surface(radius: f32, z_center: f32, o: &Point3, d: &Vec3) -> Option<(f32, Vec3)> {
+    let oc = *o - Vec3::new(0.0, 0.0, z_center);
+    let a = d.length_squared();
+    let half_b = Vec3::dot(&oc, d);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    let (t0, t1) = ((-half_b - sqrtd) / a, (-half_b + sqrtd) / a);
+    // Which of the two intersections is on the lens surface depends on the direction of the
+    // ray and which way the surface curves.
+    let t = if (d.z > 0.0) ^ (radius < 0.0) { t0 } else { t1 };
+    if t < 0.0 {
+        return None;
+    }
+    let n = Vec3::unit_vector(&(oc + t * *d));
+    Some((t, if Vec3::dot(&n, d) > 0.0 { -n } else { n }))
+}
+
+// Snell's law for `wi` pointing away from the surface, with `eta` the ratio of the indices of
+// refraction on the incoming and outgoing sides. None for total internal reflection.
+fn refract(wi: &Vec3, n: &Vec3, eta: f32) -> Option<Vec3> {
+    let cos_i = Vec3::dot(n, wi);
+    let sin2_i = f32::max(0.0, 1.0 - cos_i * cos_i);
+    let sin2_t = eta * eta * sin2_i;
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(eta * -*wi + (eta * cos_i - cos_t) * *n)
+}
+
+// The z of the principal plane and of the focal point, from a ray parallel to the axis and
+// the same ray after the lens.
+fn cardinal_points(r_in: &Ray, r_out: &Ray) -> (f32, f32) {
+    let tf = -r_out.orig.x / r_out.dir.x;
+    let fz = -r_out.at(tf).z;
+    let tp = (r_in.orig.x - r_out.orig.x) / r_out.dir.x;
+    let pz = -r_out.at(tp).z;
+    (pz, fz)
+}
+
+// The number of rings, out from the centre of the film, with their own exit pupil bounds.
+const PUPIL_RINGS: usize = 64;
+// Rays traced to find each ring's bounds.
+const PUPIL_SAMPLES: usize = 4096;
+
+/// A rectangle on the rear surface of the lens.
+#[derive(Clone, Copy, Debug)]
+struct Bounds {
+    min: (f32, f32),
+    max: (f32, f32),
+}
+
+impl Bounds {
+    fn empty() -> Bounds {
+        Bounds {
+            min: (f32::INFINITY, f32::INFINITY),
+            max: (f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.min.0 && x <= self.max.0 && y >= self.min.1 && y <= self.max.1
+    }
+
+    fn add(&mut self, x: f32, y: f32) {
+        self.min = (self.min.0.min(x), self.min.1.min(y));
+        self.max = (self.max.0.max(x), self.max.1.max(y));
+    }
+
+    fn area(&self) -> f32 {
+        (self.max.0 - self.min.0) * (self.max.1 - self.min.1)
+    }
+
+    fn lerp(&self, s: f32, t: f32) -> (f32, f32) {
+        (self.min.0 + s * (self.max.0 - self.min.0), self.min.1 + t * (self.max.1 - self.min.1))
+    }
+}
+
+/// A camera looking through a `LensSystem`, focused by moving the lens towards or away from
+/// the film.
+#[derive(Clone, Debug)]
+pub struct LensCamera {
+    system: LensSystem,
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    // Film width and height, in millimetres.
+    film: (f32, f32),
+    // Scene units per millimetre.
+    scale: f32,
+    // Bounds on the rear surface through which light from each ring of the film leaves the
+    // lens, from the centre out.
+    pupils: Vec<Bounds>,
+    // The light reaching the middle of the film, which the weights of rays are relative to.
+    centre_weight: f32,
+}
+
+/// How a `LensCamera` is set up, besides where it is and what it looks at.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LensOptions {
+    /// Diagonal of the film, in millimetres: 43.3 for 35 mm full frame.
+    pub film_diagonal: f32,
+    /// Narrow the aperture stop to this diameter, in millimetres.
+    pub aperture_diameter: Option<f32>,
+    /// Millimetres per scene unit, e.g. 1000 if the scene is in metres.
+    pub scale: f32,
+}
+
+impl Default for LensOptions {
+    /// Full frame film, the lens wide open, and a scene in metres.
+    fn default() -> LensOptions {
+        LensOptions {
+            film_diagonal: 43.3,
+            aperture_diameter: None,
+            scale: 1000.0,
+        }
+    }
+}
+
+impl LensCamera {
+    /// Set up `system` to focus `focus_dist` scene units away. `basis` is the camera's right, up
+    /// and backward directions.
+    pub fn new(
+        system: &LensSystem,
+        options: &LensOptions,
+        origin: Point3,
+        basis: (Vec3, Vec3, Vec3),
+        focus_dist: f32,
+        aspect_ratio: f32,
+    ) -> Result<LensCamera, String> {
+        let system = system.focused(options, focus_dist)?;
+        let width = options.film_diagonal / f32::sqrt(1.0 + 1.0 / (aspect_ratio * aspect_ratio));
+        let (u, v, w) = basis;
+        let mut camera = LensCamera {
+            system,
+            origin,
+            u,
+            v,
+            w,
+            film: (width, width / aspect_ratio),
+            scale: 1.0 / options.scale,
+            pupils: vec![],
+            centre_weight: 1.0,
+        };
+        let radius = options.film_diagonal / 2.0;
+        camera.pupils = (0..PUPIL_RINGS)
+            .map(|i| {
+                let x0 = i as f32 / PUPIL_RINGS as f32 * radius;
+                let x1 = (i + 1) as f32 / PUPIL_RINGS as f32 * radius;
+                camera.bound_exit_pupil(x0, x1)
+            })
+            .collect();
+        // The middle of the film sees the clear part of its pupil bounds.
+        let (rear_z, centre) = (camera.system.rear_z(), camera.pupils[0]);
+        let clear: f32 = (0..PUPIL_SAMPLES)
+            .filter_map(|i| {
+                let (x, y) = centre.lerp(radical_inverse(2, i), radical_inverse(3, i));
+                let dir = Vec3::new(x, y, rear_z);
+                camera.system.trace_from_film(&Ray::new(Point3::zero(), dir))?;
+                let cos = Vec3::unit_vector(&dir).z;
+                Some(cos * cos * cos * cos)
+            })
+            .sum();
+        camera.centre_weight = centre.area() * clear.max(1.0) / PUPIL_SAMPLES as f32 / (rear_z * rear_z);
+        Ok(camera)
+    }
+
+    // Bound where rays from film points between x0 and x1 along the x axis get through the rear
+    // of the lens, by tracing a spread of rays aimed at a square over the rear surface.
+    fn bound_exit_pupil(&self, x0: f32, x1: f32) -> Bounds {
+        let system = &self.system;
+        let extent = 1.5 * system.rear_radius();
+        let square = Bounds {
+            min: (-extent, -extent),
+            max: (extent, extent),
+        };
+        let mut bounds = Bounds::empty();
+        let mut exiting = 0;
+        for i in 0..PUPIL_SAMPLES {
+            let film = Point3::new(x0 + (i as f32 + 0.5) / PUPIL_SAMPLES as f32 * (x1 - x0), 0.0, 0.0);
+            let (x, y) = square.lerp(radical_inverse(2, i), radical_inverse(3, i));
+            let rear = Point3::new(x, y, system.rear_z());
+            if bounds.contains(x, y) || system.trace_from_film(&Ray::new(film, rear - film)).is_some() {
+                bounds.add(x, y);
+                exiting += 1;
+            }
+        }
+        if exiting == 0 {
+            return square;
+        }
+        // Allow for the gaps between samples.
+        let margin = 2.0 * (2.0 * extent * std::f32::consts::SQRT_2) / (PUPIL_SAMPLES as f32).sqrt();
+        Bounds {
+            min: (bounds.min.0 - margin, bounds.min.1 - margin),
+            max: (bounds.max.0 + margin, bounds.max.1 + margin),
+        }
+    }
+
+    // A point on the rear of the lens for a ray from `(x, y)` on the film, in the pupil bounds
+    // for its ring turned to face it, and the area of those bounds.
+    fn sample_exit_pupil(&self, x: f32, y: f32) -> (Point3, f32) {
+        let r = f32::sqrt(x * x + y * y);
+        let ring = ((r / (0.5 * f32::hypot(self.film.0, self.film.1))) * PUPIL_RINGS as f32) as usize;
+        let bounds = &self.pupils[ring.min(PUPIL_RINGS - 1)];
+        let (px, py) = bounds.lerp(random_f32(), random_f32());
+        let (sin, cos) = if r > 0.0 { (y / r, x / r) } else { (0.0, 1.0) };
+        (Point3::new(cos * px - sin * py, sin * px + cos * py, self.system.rear_z()), bounds.area())
+    }
+}
+
+impl CameraModel for LensCamera {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        self.get_weighted_ray(s, t).map(|(r, _)| r)
+    }
+
+    fn get_weighted_ray(&self, s: f32, t: f32) -> Option<(Ray, f32)> {
+        // The lens forms an inverted image, so turn the film around.
+        let film = Point3::new(-(s - 0.5) * self.film.0, -(t - 0.5) * self.film.1, 0.0);
+        // Rays the lens blocks leave the sample black, which darkens the edges of the image
+        // where less of the lens is clear.
+        let (rear, area) = self.sample_exit_pupil(film.x, film.y);
+        let out = self.system.trace_from_film(&Ray::new(film, rear - film))?;
+        // The light through a point of the pupil falls off as cos^4 of the ray's angle off the
+        // axis, and the point stands for the whole of the bounds it was drawn from, seen from
+        // the rear of the lens. Exposure already accounts for the f-number, so the weights are
+        // relative to the middle of the film, which comes out as bright as through a thin lens.
+        let cos = Vec3::unit_vector(&(rear - film)).z;
+        let rear_z = self.system.rear_z();
+        let weight = cos * cos * cos * cos * area / (rear_z * rear_z) / self.centre_weight;
+        // Lens space looks along +z, the camera along -w.
+        let to_world = |v: Vec3| v.x * self.u + v.y * self.v - v.z * self.w;
+        Some((Ray::new(self.origin + self.scale * to_world(out.orig), to_world(out.dir)), weight))
+    }
+}
+
+// The digits of `i` in base `base`, mirrored about the point: a well-spread sequence in [0, 1).
+fn radical_inverse(base: usize, mut i: usize) -> f32 {
+    let (mut result, mut scale) = (0.0, 1.0 / base as f32);
+    while i > 0 {
+        result += (i % base) as f32 * scale;
+        i /= base;
+        scale /= base as f32;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOUBLE_GAUSS: &str = include_str!("../scenes/lenses/dgauss50.dat");
+
+    #[test]
+    fn parses_prescriptions() {
+        let lens = LensSystem::parse(DOUBLE_GAUSS).unwrap();
+        assert_eq!(lens.elements.len(), 11);
+        assert_eq!(lens.stop_diameter(), Some(17.1));
+        // It's a 50 mm lens.
+        let focal_length = lens.focal_length(43.3).unwrap();
+        assert!((focal_length - 50.0).abs() < 1.0, "{}", focal_length);
+
+        assert!(LensSystem::parse("29.475 3.76 1.67\n").unwrap_err().contains("line 1"));
+        assert!(LensSystem::parse("0 1 0 10\n0 1 0 10\n").is_err());
+    }
+
+    #[test]
+    fn focuses_by_moving_the_lens() {
+        let lens = LensSystem::parse(DOUBLE_GAUSS).unwrap();
+        let far = lens.focus(1e6, 43.3).unwrap();
+        let near = lens.focus(1000.0, 43.3).unwrap();
+        // Focusing closer moves the lens out, by about f^2 / (d - f) for a thin lens.
+        assert!(near > far);
+        assert!(((near - far) - 50.0 * 50.0 / 950.0).abs() < 0.5, "{}", near - far);
+        assert!(lens.focus(10.0, 43.3).is_err());
+
+        // A ray from the centre of the film that gets through leaves the front of the lens heading
+        // for the scene.
+        let options = LensOptions { film_diagonal: 43.3, aperture_diameter: None, scale: 1000.0 };
+        let basis = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let camera = LensCamera::new(&lens, &options, Point3::zero(), basis, 1.0, 1.5).unwrap();
+        let ray = (0..100).find_map(|_| camera.get_ray(0.5, 0.5)).unwrap();
+        assert!(ray.orig.z < 0.0 && ray.dir.z < 0.0);
+        // And it meets the plane of focus close to the axis.
+        let t = (-1.0 - ray.orig.z) / ray.dir.z;
+        let p = ray.at(t);
+        assert!(p.x.abs() < 0.01 && p.y.abs() < 0.01, "{:?}", p);
+    }
+
+    #[test]
+    fn darkens_towards_the_corners() {
+        let lens = LensSystem::parse(DOUBLE_GAUSS).unwrap();
+        let options = LensOptions::default();
+        let basis = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let camera = LensCamera::new(&lens, &options, Point3::zero(), basis, 10.0, 1.5).unwrap();
+        // The light reaching a point of the film, with blocked rays counting as none.
+        let light = |s, t| {
+            let n = 20000;
+            (0..n).filter_map(|_| camera.get_weighted_ray(s, t)).map(|(_, weight)| weight).sum::<f32>() / n as f32
+        };
+        let (centre, corner) = (light(0.5, 0.5), light(1.0, 1.0));
+        assert!((centre - 1.0).abs() < 0.05, "{}", centre);
+        assert!(corner < 0.5 * centre, "{} vs {}", corner, centre);
+    }
+}
@@ -0,0 +1,70 @@
+//! A path tracer following "Ray Tracing in One Weekend".
+//!
+//! Build a [`Scene`](scene::Scene) in code, load one from a TOML file or pick one of the
+//! [built-in scenes](scenes::BUILTIN_SCENES), then hand it to a
+//! [`Renderer`](render::Renderer):
+//!
+//! ```
+//! use raytracing_rust::hit::Sphere;
+//! use raytracing_rust::material::Lambertian;
+//! use raytracing_rust::render::{RenderSettings, Renderer};
+//! use raytracing_rust::scene::{Background, CameraSettings, ImageSettings, Projection, Scene};
+//! use raytracing_rust::vec3::{Color, Point3, Vec3};
+//!
+//! let scene = Scene {
+//!     image: ImageSettings { width: 32, height: None, aspect_ratio: 1.0, samples_per_pixel: Some(4), max_depth: 8 },
+//!     camera: CameraSettings {
+//!         projection: Projection::Perspective,
+//!         lookfrom: Point3::new(0.0, 0.0, 3.0),
+//!         lookat: Point3::new(0.0, 0.0, 0.0),
+//!         vup: Vec3::new(0.0, 1.0, 0.0),
+//!         vfov: 40.0,
+//!         aperture: 0.0,
+//!         aperture_shape: Default::default(),
+//!         exposure: None,
+//!         shift: (0.0, 0.0),
+//!         tilt: (0.0, 0.0),
+//!         autofocus: None,
+//!         effects: Default::default(),
+//!         stereo: None,
+//!         rolling_shutter: None,
+//!         focus_dist: 3.0,
+//!     },
+//!     background: Background::Sky,
+//!     world: vec![Sphere {
+//!         center: Point3::new(0.0, 0.0, 0.0),
+//!         radius: 1.0,
+//!         material: Lambertian { albedo: Color::new(0.8, 0.3, 0.3).into() }.into(),
+//!     }
+//!     .into()],
+//!     animation: Default::default(),
+//!     names: Default::default(),
+//!     cameras: Default::default(),
+//! };
+//!
+//! let image = Renderer::new().render(&scene, &RenderSettings::for_scene(&scene)).unwrap();
+//! assert_eq!((image.width, image.height), (32, 32));
+//! ```
+
+pub mod animation;
+pub mod aperture;
+pub mod camera;
+pub mod checkpoint;
+pub mod denoise;
+pub mod distributed;
+pub mod film;
+pub mod hit;
+pub mod image;
+pub mod lens;
+pub mod material;
+pub mod perlin;
+pub mod preview;
+pub mod ray;
+pub mod render;
+pub mod scene;
+pub mod scenes;
+pub mod texture;
+pub mod tile;
+pub mod transform;
+pub mod util;
+pub mod vec3;
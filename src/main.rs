@@ -1,232 +1,241 @@
-mod camera;
-mod hit;
-mod material;
-mod ray;
-mod util;
-mod vec3;
-
-use std::fs::File;
+mod cli;
+
+use std::fs::{self, File};
 use std::io::{self, BufWriter, Seek, Write};
-use std::time::Instant;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use clap::Parser;
 use console::style;
-use indicatif::{HumanBytes, ParallelProgressIterator, ProgressBar};
-use rayon::iter::ParallelIterator;
-use rayon::prelude::*;
-
-use camera::*;
-use hit::*;
-use material::*;
-use ray::*;
-use util::*;
-use vec3::*;
-
-fn hit_list<H: Hittable>(hittables: &Vec<H>, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-    let mut hit_rec = Option::None;
-    let mut closest_so_far = t_max;
-
-    for hittable in hittables {
-        match hittable.hit(r, t_min, closest_so_far) {
-            Some(rec) => {
-                closest_so_far = rec.t;
-                hit_rec = Option::Some(rec);
-            },
-            None => {},
-        }
+use indicatif::{HumanBytes, ProgressBar};
+
+use raytracing_rust::checkpoint::*;
+use raytracing_rust::denoise::*;
+use raytracing_rust::distributed::work;
+use raytracing_rust::film::Film;
+use raytracing_rust::image::*;
+use raytracing_rust::preview::{self, Preview};
+use raytracing_rust::render::*;
+use raytracing_rust::scene::{CameraSettings, Scene};
+use raytracing_rust::util::*;
+
+use cli::*;
+
+// Shows render progress as a bar of tiles.
+struct BarProgress(ProgressBar);
+
+impl Progress for BarProgress {
+    fn start(&self, total: u64) {
+        self.0.set_length(total);
     }
 
-    return hit_rec;
-}
+    fn advance(&self, n: u64) {
+        self.0.inc(n);
+    }
 
-fn ray_color<H: Hittable>(r: &Ray, hittables: &Vec<H>, depth: i32) -> Color {
-    if depth <= 0 {
-        return Color::zero();
+    fn finish(&self) {
+        self.0.finish();
     }
 
-    match hit_list(hittables, r, 0.001, INFINITY) {
-        Some(rec) => {
-            let m = rec.material;
-            return match m.scatter(&r, &rec) {
-                (Some(scattered_ray), attenuation) => {
-                    attenuation * ray_color(&scattered_ray, hittables, depth - 1)
-                }
-                (None, _) => Color::zero(),
-            }
-        },
-        None => {},
-    };
+    fn message(&self, message: &str) {
+        self.0.println(message);
+    }
+}
 
-    // Background gradient
-    let unit_direction = Vec3::unit_vector(&r.dir);
-    let t = 0.5 * (unit_direction.y + 1.0);
-    (1.0 - t) * Color::new(1.0, 1.0, 1.0) + (t * Color::new(0.5, 0.7, 1.0))
+// Shows how much of a time-limited render's budget has been used.
+struct TimeProgress {
+    bar: ProgressBar,
+    start: Instant,
 }
 
-fn write_color(
-    w: &mut BufWriter<&mut File>,
-    color: Color,
-    samples_per_pixel: i32,
-) -> io::Result<()> {
-    // sqrt: gamma correction is raise to the power of 1/gamma, and we're using gamma=2, so pow(1/2) -> sqrt
-    let scale = 1.0 / samples_per_pixel as f32;
-    let r = f32::sqrt(color.x * scale);
-    let b = f32::sqrt(color.y * scale);
-    let g = f32::sqrt(color.z * scale);
-
-    write!(
-        w,
-        "{} {} {}\n",
-        (256.0 * clamp(r, 0.0, 0.999)) as u32,
-        (256.0 * clamp(b, 0.0, 0.999)) as u32,
-        (256.0 * clamp(g, 0.0, 0.999)) as u32
-    )
+impl Progress for TimeProgress {
+    fn advance(&self, _n: u64) {
+        self.bar.set_position(self.start.elapsed().as_secs());
+    }
+
+    fn finish(&self) {
+        self.bar.finish();
+    }
+
+    fn message(&self, message: &str) {
+        self.bar.println(message);
+    }
 }
 
 fn main() -> io::Result<()> {
-    println!("{} Setup...", style("[1/3]").bold().dim());
-    // Image parameters
-    let aspect_ratio = 3.0 / 2.0;
-    let image_width = 1200;
-    let image_height = (image_width as f32 / aspect_ratio) as i32;
-    let samples_per_pixel = 500;
-    let max_depth = 50;
-
-    // Camera
-    let lookfrom = Point3::new(13.0, 2.0, 3.0);
-    // let lookfrom = Point3::new(0.0, 0.0, 0.0);
-    let lookat = Point3::new(0.0, 0.0, 0.0);
-    let vup = Point3::new(0.0, 1.0, 0.0);
-    let fov = 20.0;
-    let dist_to_focus = 10.0;
-    let aperture = 0.1;
-
-    let camera = Camera::new(lookfrom, lookat, vup, fov, aspect_ratio, aperture, dist_to_focus);
-
-    // Scene
-    let mut world = vec![];
-    world.push(Sphere {
-        center: Point3::new(0.0, -1000.0, 0.0),
-        radius: 1000.0,
-        material: Lambertian {
-            albedo: Color::new(0.5, 0.5, 0.5),
-        }.into(),
-    });
-    
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random_f32();
-
-            let center = Point3::new(
-                a as f32 + 0.9 * random_f32(),
-                0.2,
-                b as f32 + 0.9 + random_f32(),
-            );
-            let radius = 0.2;
-
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let albedo = Color::random() * Color::random();
-                    let material = Lambertian {
-                        albedo,
-                    };
-                    world.push(Sphere {
-                        center,
-                        radius,
-                        material: material.into(),
-                    });
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = Color::random_range(0.5, 1.0);
-                    let fuzz = random_f32_range(0.0, 0.5);
-                    let material = Metal {
-                        albedo,
-                        fuzz,
-                    };
-                    world.push(Sphere {
-                        center,
-                        radius,
-                        material: material.into(),
-                    });
-                } else {
-                    // glass
-                    let material = Dialectric {
-                        index_of_refraction: 1.5,
-                    };
-                    world.push(Sphere {
-                        center,
-                        radius,
-                        material: material.into(),
-                    });
-                }
-            }
+    let cli = Cli::parse();
+    match &cli.command {
+        Some(Command::Denoise { input, output }) => {
+            return denoise_file(input, output.as_ref().unwrap_or(input), &Denoiser::default());
         }
+        Some(Command::Worker { coordinator, source, threads }) => {
+            set_threads(*threads);
+            run_worker(coordinator, source);
+            return Ok(());
+        }
+        None => {}
     }
-
-    world.push(Sphere {
-        center: Point3::new(0.0, 1.0, 0.0),
-        radius: 1.0,
-        material: Dialectric {
-            index_of_refraction: 1.5,
-        }.into(),
-    });
-    world.push(Sphere {
-        center: Point3::new(-4.0, 1.0, 0.0),
-        radius: 1.0,
-        material: Lambertian {
-            albedo: Color::new(0.4, 0.2, 0.1),
-        }.into(),
-    });
-    world.push(Sphere {
-        center: Point3::new(4.0, 1.0, 0.0),
-        radius: 1.0,
-        material: Metal {
-            albedo: Color::new(0.7, 0.6, 0.5),
-            fuzz: 0.0,
-        }.into(),
+    let mut args = cli.render;
+    // A resumed render must carry on with the same random streams.
+    let resume = args.resume.as_ref().map(|path| {
+        let checkpoint = Checkpoint::load(path)
+            .unwrap_or_else(|e| fail(&format!("could not read checkpoint {}: {}", path.display(), e)));
+        args.seed = Some(checkpoint.seed);
+        args.samples_per_pass = checkpoint.samples_per_pass;
+        checkpoint
     });
+    let seed = *args.seed.get_or_insert_with(random_seed);
+
+    // Check the output before spending time on the render. Video has no image format.
+    let video = args.frames.is_some() && args.is_video();
+    let format = if video { None } else { Some(args.format().unwrap_or_else(|e| fail(&e))) };
+    if video && args.aovs {
+        fail("AOVs can't be written into a video; give numbered frames as the output instead");
+    }
+    set_threads(args.threads);
+
+    println!("{} Setup...", style("[1/3]").bold().dim());
+    // Scenes may be random (the cover scene, Perlin textures), so build them from the render seed.
+    seed_rng(seed, 0);
+    let mut scene = args.source.load().unwrap_or_else(|e| fail(&e));
+    args.apply_camera(&mut scene.camera).unwrap_or_else(|e| fail(&e));
+    scene.focus_camera().unwrap_or_else(|e| fail(&e));
+    if args.frames.is_none() {
+        // A still of an animated scene shows its first frame.
+        scene = scene.at(0.0).unwrap_or_else(|e| fail(&e));
+    }
+    // Check every view before rendering any of them.
+    let views = args.cameras.as_ref().map(|names| camera_views(&args, &mut scene, names).unwrap_or_else(|e| fail(&e)));
+    let settings = args.settings(&scene);
+    settings.validate().unwrap_or_else(|e| fail(&e));
+    scene.camera.validate().unwrap_or_else(|e| fail(&e));
+    let samples = match (args.time_limit, settings.image.samples_per_pixel) {
+        (_, Some(samples)) => format!("{} samples per pixel", samples),
+        (Some(limit), None) => format!("as many samples per pixel as fit in {:?}", limit),
+        (None, None) => "samples per pixel until stopped".to_string(),
+    };
+    println!("{}x{}, {}, seed {}", settings.image.width, settings.image.height(), samples, settings.seed);
+    if let Some(checkpoint) = &resume {
+        println!("Resuming from {} samples per pixel", checkpoint.samples());
+    }
 
     // Render
     println!("{} Render...", style("[2/3]").bold().dim());
-    let pb = ProgressBar::new(image_height as u64);
     let before_render = Instant::now();
-    let range: Vec<i32> = (0..image_height).rev().collect();
-    let rows: Vec<Vec<Vec3>> = range
-        .into_par_iter() // Use Rayon to parallelize this iterator for basically no effort
-        .progress_with(pb) // Show a progress bar of rows
-        .map(|j| {
-            // For each row..
-            (0..image_width)
-                .into_iter()
-                .map(|i| {
-                    // For each column..
-                    // Run $samples_per_pixel rays through the pixel, at random positions within the pixel
-                    (0..samples_per_pixel).fold(Color::new(0.0, 0.0, 0.0), |a, _| {
-                        let u = (i as f32 + random_f32()) / (image_width as f32 - 1.0);
-                        let v = (j as f32 + random_f32()) / (image_height as f32 - 1.0);
-
-                        let r = camera.get_ray(u, v); // Get a vector representing the ray out of the camera.
-                        a + ray_color(&r, &world, max_depth) // Determine the color of the ray reflected back at the camera
-                    })
-                })
-                .collect()
-        })
-        .collect();
+    let mut renderer = Renderer::new();
+    let bar = match args.time_limit {
+        Some(limit) => {
+            let bar = ProgressBar::new(limit.as_secs());
+            renderer = renderer.with_time_limit(limit).with_progress(TimeProgress {
+                bar: bar.clone(),
+                start: Instant::now(),
+            });
+            bar
+        }
+        None => {
+            let bar = ProgressBar::new(0);
+            renderer = renderer.with_progress(BarProgress(bar.clone()));
+            bar
+        }
+    };
 
-    let render_elapsed = before_render.elapsed();
+    // The first Ctrl-C finishes the tiles in hand and writes what there is; a second one quits.
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = stop.clone();
+    let handler_bar = bar.clone();
+    ctrlc::set_handler(move || {
+        if handler_stop.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        handler_bar.println("Stopping; press Ctrl-C again to quit without writing");
+    })
+    .unwrap_or_else(|e| fail(&e.to_string()));
+    renderer = renderer.with_stop(stop.clone());
 
-    println!("{} Write to disk...", style("[3/3]").bold().dim());
-    let before_write = Instant::now();
-    let mut f = File::create("./image.ppm").unwrap();
-    let mut writer = BufWriter::new(&mut f);
+    let preview = args.preview.as_ref().map(|addr| {
+        let preview = Arc::new(Preview::new(settings.image.samples_per_pixel, stop.clone()));
+        let local = preview::serve(addr, preview.clone())
+            .unwrap_or_else(|e| fail(&format!("could not serve the preview on {}: {}", addr, e)));
+        bar.println(format!("Preview at http://{}/", local));
+        preview
+    });
+    if let Some(preview) = &preview {
+        let preview = preview.clone();
+        renderer = renderer.with_flush(Duration::from_secs(1), move |film| preview.update(film));
+    }
+
+    if let (Some(interval), Some(format)) = (args.flush_interval, format) {
+        let output = args.output.clone();
+        let bar = bar.clone();
+        renderer = renderer.with_flush(interval, move |film| {
+            if let Err(e) = write_partial(&output, format, &film.beauty()) {
+                bar.println(format!("{} could not write partial image: {}", style("warning:").yellow().bold(), e));
+            }
+        });
+    }
+    if let Some(path) = args.checkpoint_path() {
+        let path = path.clone();
+        let bar = bar.clone();
+        renderer = renderer.with_checkpoint(args.checkpoint_interval, move |checkpoint| {
+            if let Err(e) = checkpoint.save(&path) {
+                bar.println(format!("{} could not save checkpoint: {}", style("warning:").yellow().bold(), e));
+            }
+        });
+    }
+    if let Some(frames) = args.frames {
+        let film = render_sequence(&args, &scene, &settings, &renderer, frames, format, &stop, &bar)?;
+        if let (Some(preview), Some(film)) = (&preview, &film) {
+            preview.finish(film);
+        }
+        println!("Complete!");
+        println!("Render time: {:?}", style(before_render.elapsed()).bold());
+        return Ok(());
+    }
+    let format = format.expect("only sequences are written as video");
+    if let Some(views) = views {
+        let film = render_views(&args, &mut scene, &settings, &renderer, views, format, &stop, &bar)?;
+        if let (Some(preview), Some(film)) = (&preview, &film) {
+            preview.finish(film);
+        }
+        println!("Complete!");
+        println!("Render time: {:?}", style(before_render.elapsed()).bold());
+        return Ok(());
+    }
 
-    write!(&mut writer, "P3\n{} {}\n255\n", image_width, image_height)?;
-    for row in rows {
-        for color in row {
-            write_color(&mut writer, color, samples_per_pixel)?;
+    let film = match (resume, &args.listen) {
+        (Some(checkpoint), _) => renderer.resume_film(&scene, &settings, checkpoint).unwrap_or_else(|e| fail(&e)),
+        (None, Some(addr)) => {
+            let listener = TcpListener::bind(addr)
+                .unwrap_or_else(|e| fail(&format!("could not listen on {}: {}", addr, e)));
+            bar.println(format!("Waiting for workers on {}", listener.local_addr()?));
+            renderer.render_distributed(&listener, &scene, 0.0, &settings)?
         }
+        (None, None) => renderer.render_film(&scene, &settings).unwrap_or_else(|e| fail(&e)),
+    };
+    let render_elapsed = before_render.elapsed();
+    if let Some(preview) = &preview {
+        preview.finish(&film);
     }
+    let taken = film.pixels.iter().map(|p| p.samples);
+    let (fewest, most) = (taken.clone().min().unwrap_or(0), taken.max().unwrap_or(0));
+    if settings.image.samples_per_pixel.is_some_and(|target| fewest < target) {
+        println!("Stopped early with {} to {} samples per pixel", fewest, most);
+    } else if args.time_limit.is_some() {
+        println!("Took {} to {} samples per pixel", fewest, most);
+    }
+
+    println!("{} Write to disk...", style("[3/3]").bold().dim());
+    let before_write = Instant::now();
+    let mut writer = BufWriter::new(File::create(&args.output)?);
+    format.write_image(&mut writer, &final_image(&args, &film))?;
     writer.flush()?;
+    if args.aovs {
+        film.write_aovs(&args.output, format)?;
+    }
     let write_elapsed = before_write.elapsed();
 
     println!("Complete!");
@@ -239,3 +248,180 @@ fn main() -> io::Result<()> {
 
     Ok(())
 }
+
+// Render and write each frame in `frames`, returning the film of the last one.
+#[allow(clippy::too_many_arguments)]
+fn render_sequence(
+    args: &RenderArgs,
+    scene: &Scene,
+    settings: &RenderSettings,
+    renderer: &Renderer,
+    frames: FrameRange,
+    format: Option<ImageFormat>,
+    stop: &AtomicBool,
+    bar: &ProgressBar,
+) -> io::Result<Option<Film>> {
+    let animation = &scene.animation;
+    let last = frames.last.unwrap_or(animation.frames.saturating_sub(1));
+    if frames.first > last || last >= animation.frames {
+        fail(&format!("the animation has frames 0 to {}", animation.frames - 1));
+    }
+    let mut video = match format {
+        Some(_) => None,
+        None => {
+            let writer = BufWriter::new(File::create(&args.output)?);
+            Some(Y4mWriter::new(writer, settings.image.width, settings.image.height(), animation.fps)?)
+        }
+    };
+
+    let mut film = None;
+    for frame in frames.first..=last {
+        let time = animation.time(frame);
+        bar.reset();
+        bar.println(format!("Frame {} of {} at {:.3}s", frame, last, time));
+        // Each frame gets its own noise; the same noise in every frame would stay put on screen
+        // while the scene moves under it.
+        let settings = RenderSettings {
+            seed: derive_seed(settings.seed, frame as u64),
+            ..*settings
+        };
+        let rendered = scene.at(time).and_then(|posed| renderer.render_film(&posed, &settings)).unwrap_or_else(|e| fail(&e));
+        let image = final_image(args, &rendered);
+        match (&mut video, format) {
+            (Some(video), _) => video.write_frame(&image)?,
+            (None, Some(format)) => {
+                let path = args.frame_path(frame);
+                let mut writer = BufWriter::new(File::create(&path)?);
+                format.write_image(&mut writer, &image)?;
+                writer.flush()?;
+                if args.aovs {
+                    rendered.write_aovs(&path, format)?;
+                }
+            }
+            (None, None) => unreachable!(),
+        }
+        film = Some(rendered);
+        if stop.load(Ordering::SeqCst) {
+            bar.println(format!("Stopped after frame {}", frame));
+            break;
+        }
+    }
+    if let Some(video) = video {
+        video.into_inner().flush()?;
+    }
+    Ok(film)
+}
+
+// The named cameras to render, all of the scene's if none are listed, each with the command-line
+// overrides applied and focused.
+fn camera_views(args: &RenderArgs, scene: &mut Scene, names: &[String]) -> Result<Vec<(String, CameraSettings)>, String> {
+    if scene.cameras.is_empty() {
+        return Err("the scene has no named cameras; give them as [cameras.NAME]".to_string());
+    }
+    let names = match names.is_empty() {
+        true => scene.cameras.keys().cloned().collect(),
+        false => names.to_vec(),
+    };
+    let mut views = vec![];
+    for name in names {
+        scene.use_camera(&name)?;
+        let in_camera = |e| format!("cameras.{}: {}", name, e);
+        args.apply_camera(&mut scene.camera).map_err(in_camera)?;
+        scene.focus_camera().map_err(in_camera)?;
+        scene.camera.validate().map_err(in_camera)?;
+        views.push((name, scene.camera.clone()));
+    }
+    Ok(views)
+}
+
+// Render and write the view through each camera in `views`, returning the film of the last one.
+#[allow(clippy::too_many_arguments)]
+fn render_views(
+    args: &RenderArgs,
+    scene: &mut Scene,
+    settings: &RenderSettings,
+    renderer: &Renderer,
+    views: Vec<(String, CameraSettings)>,
+    format: ImageFormat,
+    stop: &AtomicBool,
+    bar: &ProgressBar,
+) -> io::Result<Option<Film>> {
+    let count = views.len();
+    let mut film = None;
+    for (i, (name, camera)) in views.into_iter().enumerate() {
+        scene.camera = camera;
+        bar.reset();
+        bar.println(format!("Camera {} ({} of {})", name, i + 1, count));
+        let rendered = renderer.render_film(scene, settings).unwrap_or_else(|e| fail(&e));
+        let path = args.camera_path(&name);
+        let mut writer = BufWriter::new(File::create(&path)?);
+        format.write_image(&mut writer, &final_image(args, &rendered))?;
+        writer.flush()?;
+        if args.aovs {
+            rendered.write_aovs(&path, format)?;
+        }
+        film = Some(rendered);
+        if stop.load(Ordering::SeqCst) {
+            bar.println(format!("Stopped after camera {}", name));
+            break;
+        }
+    }
+    Ok(film)
+}
+
+// The beauty pass, denoised if asked.
+fn final_image(args: &RenderArgs, film: &Film) -> Image {
+    match args.denoiser() {
+        Some(denoiser) => denoiser.denoise(&film.beauty(), &Features::from_film(film)),
+        None => film.beauty(),
+    }
+}
+
+fn set_threads(threads: Option<usize>) {
+    if let Some(threads) = threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .unwrap_or_else(|e| fail(&e.to_string()));
+    }
+}
+
+// Connect to a coordinator, waiting a while in case it hasn't started yet, and render for it.
+fn run_worker(coordinator: &str, source: &SceneArgs) {
+    let mut attempts = 0;
+    let stream = loop {
+        match TcpStream::connect(coordinator) {
+            Ok(stream) => break stream,
+            Err(e) if attempts < 60 => {
+                if attempts == 0 {
+                    println!("Waiting for {} ({})", coordinator, e);
+                }
+                attempts += 1;
+                thread::sleep(Duration::from_millis(500));
+            }
+            Err(e) => fail(&format!("could not connect to {}: {}", coordinator, e)),
+        }
+    };
+    println!("Rendering for {}", coordinator);
+
+    let before = Instant::now();
+    // The scene is posed as the coordinator's is once its camera arrives.
+    let rendered = work(stream, || source.load()).unwrap_or_else(|e| fail(&e.to_string()));
+    println!("Rendered {} tiles in {:?}", rendered, style(before.elapsed()).bold());
+}
+
+// Write to a temporary file and rename it over `path`, so viewers never see a half-written image.
+fn write_partial(path: &Path, format: ImageFormat, image: &Image) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".partial");
+    let mut writer = BufWriter::new(File::create(&temp)?);
+    format.write_image(&mut writer, image)?;
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&temp, path)
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{} {}", style("error:").red().bold(), message);
+    std::process::exit(1);
+}
@@ -1,7 +1,9 @@
 use enum_dispatch::enum_dispatch;
+use serde::Serialize;
 
 use crate::hit::*;
 use crate::ray::*;
+use crate::texture::*;
 use crate::vec3::*;
 use crate::util::random_f32;
 
@@ -9,27 +11,39 @@ use crate::util::random_f32;
 #[enum_dispatch]
 pub trait MaterialBehavior: Sized {
     fn scatter(&self, ray: &Ray, rec: &HitRecord) -> (Option<Ray>, Color);
+
+    // Surface colour independent of lighting and viewing angle, for the albedo AOV.
+    fn albedo(&self, rec: &HitRecord) -> Color;
+
+    // Light given off by the surface itself.
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::zero()
+    }
 }
 
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Texture,
 }
 
 impl MaterialBehavior for Lambertian {
-    fn scatter(&self, _: &Ray, rec: &HitRecord) -> (Option<Ray>, Color) {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord) -> (Option<Ray>, Color) {
         let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
 
-        let scattered = Ray::new(rec.p, scatter_direction);
-        (Option::Some(scattered), self.albedo)
+        let scattered = Ray::new(rec.p, scatter_direction).with_time(ray.time);
+        (Option::Some(scattered), self.albedo.value(rec.u, rec.v, &rec.p))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p)
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize)]
 pub struct Metal {
     pub albedo: Color,
     pub fuzz: f32,
@@ -38,7 +52,7 @@ pub struct Metal {
 impl MaterialBehavior for Metal {
     fn scatter(&self, ray: &Ray, rec: &HitRecord) -> (Option<Ray>, Color) {
         let reflected = Vec3::reflect(&Vec3::unit_vector(&ray.dir), &rec.normal);
-        let scattered = Ray::new(rec.p, reflected + (self.fuzz * Vec3::random_in_unit_sphere()));
+        let scattered = Ray::new(rec.p, reflected + (self.fuzz * Vec3::random_in_unit_sphere())).with_time(ray.time);
 
         if Vec3::dot(&scattered.dir, &rec.normal) > 0.0 {
             (Option::Some(scattered), self.albedo)
@@ -46,10 +60,14 @@ impl MaterialBehavior for Metal {
             (Option::None, self.albedo)
         }
     }
+
+    fn albedo(&self, _: &HitRecord) -> Color {
+        self.albedo
+    }
 }
 
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize)]
 pub struct Dialectric {
     pub index_of_refraction: f32,
 }
@@ -58,7 +76,7 @@ fn reflectance(cosine: f32, ref_idx: f32) -> f32 {
     // Use Schlick's approximation for reflectance.
     let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
     r0 = r0 * r0;
-    return r0 + (1.0 - r0) * f32::powf(1.0 - cosine, 5.0);
+    r0 + (1.0 - r0) * f32::powf(1.0 - cosine, 5.0)
 }
 
 impl MaterialBehavior for Dialectric {
@@ -79,17 +97,42 @@ impl MaterialBehavior for Dialectric {
         };
 
         let refracted = Vec3::refract(&direction, &rec.normal, refraction_ratio);
-        let scattered = Ray::new(rec.p, refracted);
+        let scattered = Ray::new(rec.p, refracted).with_time(ray.time);
 
         (Option::Some(scattered), attenuation)
     }
+
+    fn albedo(&self, _: &HitRecord) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+}
+
+/// An emitter: gives off `emit` and absorbs everything that hits it.
+#[derive(Clone, Debug, Serialize)]
+pub struct DiffuseLight {
+    pub emit: Texture,
+}
+
+impl MaterialBehavior for DiffuseLight {
+    fn scatter(&self, _: &Ray, _: &HitRecord) -> (Option<Ray>, Color) {
+        (Option::None, Color::zero())
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.emit.value(rec.u, rec.v, &rec.p)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.emit.value(rec.u, rec.v, &rec.p)
+    }
 }
 
 // #[derive(Debug, PartialEq)]
-#[derive(Clone, Copy)]
 #[enum_dispatch(MaterialBehavior)]
+#[derive(Clone, Debug, Serialize)]
 pub enum Material {
     Lambertian,
     Metal,
     Dialectric,
+    DiffuseLight,
 }
@@ -0,0 +1,103 @@
+use serde::Serialize;
+
+use crate::util::*;
+use crate::vec3::*;
+
+const POINT_COUNT: usize = 256;
+
+/// Gradient noise with random unit vectors at the lattice points, as in "The Next Week".
+#[derive(Debug, Serialize)]
+pub struct Perlin {
+    ranvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Default for Perlin {
+    fn default() -> Perlin {
+        Perlin::new()
+    }
+}
+
+impl Perlin {
+    /// Draws its tables from the thread's generator, so seed it first for a repeatable pattern.
+    pub fn new() -> Perlin {
+        Perlin {
+            ranvec: (0..POINT_COUNT).map(|_| Vec3::unit_vector(&Vec3::random_range(-1.0, 1.0))).collect(),
+            perm_x: generate_perm(),
+            perm_y: generate_perm(),
+            perm_z: generate_perm(),
+        }
+    }
+
+    /// Smooth noise in [-1, 1].
+    pub fn noise(&self, p: &Point3) -> f32 {
+        let u = p.x - p.x.floor();
+        let v = p.y - p.y.floor();
+        let w = p.z - p.z.floor();
+        let i = p.x.floor() as i32;
+        let j = p.y.floor() as i32;
+        let k = p.z.floor() as i32;
+
+        let mut c = [[[Vec3::zero(); 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    *corner = self.ranvec[self.perm_x[((i + di as i32) & 255) as usize]
+                        ^ self.perm_y[((j + dj as i32) & 255) as usize]
+                        ^ self.perm_z[((k + dk as i32) & 255) as usize]];
+                }
+            }
+        }
+
+        trilinear_interp(&c, u, v, w)
+    }
+
+    /// Sum of `depth` octaves of noise, each at double the frequency and half the weight.
+    pub fn turb(&self, p: &Point3, depth: u32) -> f32 {
+        let mut accum = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+
+        for _ in 0..depth {
+            accum += weight * self.noise(&temp_p);
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+
+        accum.abs()
+    }
+}
+
+fn generate_perm() -> Vec<usize> {
+    let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+    // Fisher-Yates shuffle.
+    for i in (1..POINT_COUNT).rev() {
+        let target = random_f32_range(0.0, (i + 1) as f32) as usize;
+        p.swap(i, target.min(i));
+    }
+    p
+}
+
+fn trilinear_interp(c: &[[[Vec3; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32 {
+    // Hermite smoothing removes the grid artifacts of plain linear interpolation.
+    let uu = u * u * (3.0 - 2.0 * u);
+    let vv = v * v * (3.0 - 2.0 * v);
+    let ww = w * w * (3.0 - 2.0 * w);
+
+    let mut accum = 0.0;
+    for (i, plane) in c.iter().enumerate() {
+        for (j, row) in plane.iter().enumerate() {
+            for (k, corner) in row.iter().enumerate() {
+                let (fi, fj, fk) = (i as f32, j as f32, k as f32);
+                let weight_v = Vec3::new(u - fi, v - fj, w - fk);
+                accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                    * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                    * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                    * Vec3::dot(corner, &weight_v);
+            }
+        }
+    }
+    accum
+}
@@ -0,0 +1,276 @@
+//! A small HTTP server for watching a render from a browser.
+//!
+//! - `GET /` is a page showing the image and statistics, with a stop button.
+//! - `GET /image.png` is the latest snapshot of the film.
+//! - `GET /stats.json` is the render's progress.
+//! - `POST /stop` asks the render to stop, as Ctrl-C does. It must carry the page's token in an
+//!   `X-Stop-Token` header, so other sites open in the browser can't stop the render.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::film::*;
+use crate::image::*;
+
+/// What the server shows: the latest film snapshot, and a flag to stop the render.
+pub struct Preview {
+    film: Mutex<Option<Film>>,
+    /// Samples per pixel the render is aiming for, if it has a fixed number.
+    target_samples: Option<u32>,
+    started: Instant,
+    finished: AtomicBool,
+    stop: Arc<AtomicBool>,
+    /// A secret made up for this run, which the page sends back to stop the render.
+    token: String,
+}
+
+impl Preview {
+    pub fn new(target_samples: Option<u32>, stop: Arc<AtomicBool>) -> Preview {
+        Preview {
+            film: Mutex::new(None),
+            target_samples,
+            started: Instant::now(),
+            finished: AtomicBool::new(false),
+            stop,
+            token: format!("{:032x}", rand::random::<u128>()),
+        }
+    }
+
+    /// Show a newer snapshot, e.g. from `Renderer::with_flush`.
+    pub fn update(&self, film: &Film) {
+        *self.film.lock().unwrap() = Some(film.clone());
+    }
+
+    /// Show the final film.
+    pub fn finish(&self, film: &Film) {
+        self.update(film);
+        self.finished.store(true, Ordering::SeqCst);
+    }
+
+    fn png(&self) -> Option<Vec<u8>> {
+        let beauty = self.film.lock().unwrap().as_ref()?.beauty();
+        let mut bytes = vec![];
+        Png { sixteen_bit: false }.write_image(&mut bytes, &beauty).ok()?;
+        Some(bytes)
+    }
+
+    fn stats_json(&self) -> String {
+        let state = if self.finished.load(Ordering::SeqCst) {
+            "finished"
+        } else if self.stop.load(Ordering::SeqCst) {
+            "stopping"
+        } else {
+            "rendering"
+        };
+
+        let film = self.film.lock().unwrap();
+        let (width, height, min, max, total) = match film.as_ref() {
+            Some(film) => {
+                let samples = film.pixels.iter().map(|p| p.samples);
+                (
+                    film.width,
+                    film.height,
+                    samples.clone().min().unwrap_or(0),
+                    samples.clone().max().unwrap_or(0),
+                    samples.map(u64::from).sum::<u64>(),
+                )
+            }
+            None => (0, 0, 0, 0, 0),
+        };
+        let pixels = (width * height).max(1) as u64;
+        let or_null = |v: Option<String>| v.unwrap_or_else(|| "null".to_string());
+
+        format!(
+            "{{\"state\": \"{}\", \"elapsed_seconds\": {:.3}, \"width\": {}, \"height\": {}, \
+             \"target_samples_per_pixel\": {}, \"min_samples_per_pixel\": {}, \"max_samples_per_pixel\": {}, \
+             \"mean_samples_per_pixel\": {:.3}, \"progress\": {}}}",
+            state,
+            self.started.elapsed().as_secs_f64(),
+            width,
+            height,
+            or_null(self.target_samples.map(|t| t.to_string())),
+            min,
+            max,
+            total as f64 / pixels as f64,
+            or_null(self.target_samples.map(|t| format!("{:.4}", total as f64 / (pixels * t as u64) as f64))),
+        )
+    }
+}
+
+/// Serve `preview` over HTTP on `addr` from a background thread, until the program exits.
+/// Returns the address actually bound, e.g. to find the port when asked for port 0.
+pub fn serve(addr: &str, preview: Arc<Preview>) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let preview = preview.clone();
+            // A client that goes away mid-request is no concern of the render's.
+            thread::spawn(move || drop(respond(stream, &preview)));
+        }
+    });
+    Ok(local)
+}
+
+fn respond(stream: TcpStream, preview: &Preview) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Skip the headers, noting any body so it can be drained and the token if there is one.
+    let mut content_length = 0;
+    let mut token = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            } else if name.trim().eq_ignore_ascii_case("x-stop-token") {
+                token = Some(value.trim().to_string());
+            }
+        }
+    }
+    io::copy(&mut reader.take(content_length), &mut io::sink())?;
+
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let path = path.split('?').next().unwrap_or("");
+    let mut stream = stream;
+    match (method, path) {
+        ("GET", "/") => {
+            let page = PAGE.replace("STOP_TOKEN", &preview.token);
+            send(&mut stream, "200 OK", "text/html; charset=utf-8", page.as_bytes())
+        }
+        ("GET", "/image.png") => match preview.png() {
+            Some(png) => send(&mut stream, "200 OK", "image/png", &png),
+            None => send(&mut stream, "503 Service Unavailable", "text/plain", b"no image yet\n"),
+        },
+        ("GET", "/stats.json") => send(&mut stream, "200 OK", "application/json", preview.stats_json().as_bytes()),
+        ("POST", "/stop") if token.as_deref() != Some(preview.token.as_str()) => {
+            send(&mut stream, "403 Forbidden", "text/plain", b"stopping needs the token from the page\n")
+        }
+        ("POST", "/stop") => {
+            preview.stop.store(true, Ordering::SeqCst);
+            send(&mut stream, "202 Accepted", "application/json", b"{\"state\": \"stopping\"}")
+        }
+        (_, "/" | "/image.png" | "/stats.json" | "/stop") => {
+            send(&mut stream, "405 Method Not Allowed", "text/plain", b"method not allowed\n")
+        }
+        _ => send(&mut stream, "404 Not Found", "text/plain", b"not found\n"),
+    }
+}
+
+fn send(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Render preview</title>
+<style>
+  body { background: #222; color: #ddd; font-family: sans-serif; margin: 2em; }
+  img { max-width: 100%; image-rendering: pixelated; background: #000; }
+  button { font-size: 1em; margin-top: 1em; }
+</style>
+</head>
+<body>
+<img id="image" alt="No image yet">
+<p id="stats">Waiting for the first snapshot&hellip;</p>
+<button id="stop">Stop render</button>
+<script>
+  const image = document.getElementById("image");
+  const stats = document.getElementById("stats");
+  async function refresh() {
+    try {
+      const s = await (await fetch("/stats.json")).json();
+      const progress = s.progress === null ? "" : ` (${(100 * s.progress).toFixed(1)}%)`;
+      stats.textContent = `${s.state}: ${s.width}x${s.height}, ` +
+        `${s.mean_samples_per_pixel.toFixed(1)} samples per pixel${progress} after ${s.elapsed_seconds.toFixed(0)} s`;
+      image.src = "/image.png?" + Date.now();
+      if (s.state === "finished") return;
+    } catch (e) {
+      stats.textContent = "The render has ended.";
+      return;
+    }
+    setTimeout(refresh, 2000);
+  }
+  document.getElementById("stop").onclick = () =>
+    fetch("/stop", { method: "POST", headers: { "X-Stop-Token": "STOP_TOKEN" } });
+  refresh();
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(addr: SocketAddr, request: &str) -> (String, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = vec![];
+        stream.read_to_end(&mut response).unwrap();
+        let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8_lossy(&response[..split]).into_owned();
+        (head, response[split + 4..].to_vec())
+    }
+
+    #[test]
+    fn serves_image_stats_and_stop() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let preview = Arc::new(Preview::new(Some(4), stop.clone()));
+        let addr = serve("127.0.0.1:0", preview.clone()).unwrap();
+
+        let (head, _) = request(addr, "GET /image.png HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(head.starts_with("HTTP/1.1 503"), "{}", head);
+
+        let mut film = Film::new(4, 2);
+        for pixel in &mut film.pixels[..4] {
+            pixel.samples = 4;
+        }
+        preview.update(&film);
+        let (head, body) = request(addr, "GET /image.png HTTP/1.1\r\n\r\n");
+        assert!(head.contains("Content-Type: image/png"), "{}", head);
+        assert_eq!(&body[1..4], b"PNG");
+
+        let (_, body) = request(addr, "GET /stats.json HTTP/1.1\r\n\r\n");
+        let stats = String::from_utf8(body).unwrap();
+        assert!(stats.contains("\"state\": \"rendering\""), "{}", stats);
+        assert!(stats.contains("\"progress\": 0.5000"), "{}", stats);
+
+        let (head, _) = request(addr, "GET /stop HTTP/1.1\r\n\r\n");
+        assert!(head.starts_with("HTTP/1.1 405"), "{}", head);
+        assert!(!stop.load(Ordering::SeqCst));
+        let (head, _) = request(addr, "POST /stop HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}");
+        assert!(head.starts_with("HTTP/1.1 403"), "{}", head);
+        assert!(!stop.load(Ordering::SeqCst));
+        let (_, page) = request(addr, "GET / HTTP/1.1\r\n\r\n");
+        assert!(String::from_utf8(page).unwrap().contains(&preview.token));
+        let stop_request = format!("POST /stop HTTP/1.1\r\nX-Stop-Token: {}\r\nContent-Length: 2\r\n\r\n{{}}", preview.token);
+        let (head, _) = request(addr, &stop_request);
+        assert!(head.starts_with("HTTP/1.1 202"), "{}", head);
+        assert!(stop.load(Ordering::SeqCst));
+
+        let (head, _) = request(addr, "GET /nothing HTTP/1.1\r\n\r\n");
+        assert!(head.starts_with("HTTP/1.1 404"), "{}", head);
+    }
+}
@@ -3,11 +3,18 @@ use crate::vec3::*;
 pub struct Ray {
     pub orig: Point3,
     pub dir: Vec3,
+    /// When the ray was cast, in seconds after the shutter opened, for motion blur.
+    pub time: f32,
 }
 
 impl Ray {
     pub fn new(orig: Point3, dir: Vec3) -> Ray {
-        Ray { orig, dir }
+        Ray { orig, dir, time: 0.0 }
+    }
+
+    pub fn with_time(mut self, time: f32) -> Ray {
+        self.time = time;
+        self
     }
 
     // Calculate P for P(t) = A + (b * t) where A is the origin of the ray, and b is the direction.
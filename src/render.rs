@@ -0,0 +1,569 @@
+use std::io;
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+
+use crate::camera::*;
+use crate::checkpoint::*;
+use crate::distributed::{coordinate, Hooks};
+use crate::film::*;
+use crate::hit::*;
+use crate::image::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::scene::*;
+use crate::tile::*;
+use crate::util::*;
+use crate::vec3::*;
+
+/// Per-render settings, separate from the scene so one scene can be rendered many ways.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderSettings {
+    pub image: ImageSettings,
+    /// Renders with the same seed and image settings take the same samples, whatever the thread
+    /// count, tile size, tile order or pass size, and whether or not they were resumed. Only the
+    /// rounding of the sums can differ where passes split the samples differently.
+    pub seed: u64,
+    /// The image is refined in passes of this many samples per pixel, up to the image's
+    /// `samples_per_pixel`.
+    pub samples_per_pass: u32,
+    /// Tiles are at most this many pixels across.
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+}
+
+impl RenderSettings {
+    /// The scene's own image settings, with a random seed.
+    pub fn for_scene(scene: &Scene) -> RenderSettings {
+        RenderSettings::new(scene.image, random_seed())
+    }
+
+    /// Default passes and tiles for the given image and seed.
+    pub fn new(image: ImageSettings, seed: u64) -> RenderSettings {
+        RenderSettings {
+            image,
+            seed,
+            samples_per_pass: 16,
+            tile_size: 32,
+            tile_order: TileOrder::default(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tile_size == 0 {
+            return Err("tile size must be at least 1".to_string());
+        }
+        if self.samples_per_pass == 0 {
+            return Err("samples per pass must be at least 1".to_string());
+        }
+        self.image.validate()
+    }
+}
+
+/// Receives progress reports from a render. Called from the render threads, hence `Sync`.
+pub trait Progress: Sync {
+    /// The render is starting and will report `total` units of work.
+    fn start(&self, _total: u64) {}
+    /// `n` more units of work have finished.
+    fn advance(&self, _n: u64) {}
+    fn finish(&self) {}
+    /// Something worth telling the user, e.g. a worker joining a distributed render.
+    fn message(&self, _message: &str) {}
+}
+
+/// Ignores all progress reports.
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
+
+pub struct Renderer {
+    progress: Box<dyn Progress>,
+    flushes: Vec<Periodic<Film>>,
+    checkpoint: Option<Periodic<Checkpoint>>,
+    stop: Option<Arc<AtomicBool>>,
+    time_limit: Option<Duration>,
+}
+
+// A callback run at most once per interval.
+struct Periodic<T> {
+    interval: Duration,
+    write: Box<dyn Fn(&T) + Sync>,
+    last: Mutex<Instant>,
+}
+
+impl<T> Periodic<T> {
+    fn new<F: Fn(&T) + Sync + 'static>(interval: Duration, write: F) -> Periodic<T> {
+        Periodic {
+            interval,
+            write: Box::new(write),
+            last: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn restart(&self) {
+        *self.last.lock().unwrap() = Instant::now();
+    }
+
+    // Run `write` on the value from `make` if the interval is up (or `force`). Skips rather than
+    // waits if another thread is already writing.
+    fn maybe_write(&self, force: bool, make: impl FnOnce() -> T) {
+        let Ok(mut last) = self.last.try_lock() else { return };
+        if force || last.elapsed() >= self.interval {
+            (self.write)(&make());
+            *last = Instant::now();
+        }
+    }
+}
+
+impl Default for Renderer {
+    fn default() -> Renderer {
+        Renderer::new()
+    }
+}
+
+impl Renderer {
+    pub fn new() -> Renderer {
+        Renderer {
+            progress: Box::new(NoProgress),
+            flushes: vec![],
+            checkpoint: None,
+            stop: None,
+            time_limit: None,
+        }
+    }
+
+    pub fn with_progress<P: Progress + 'static>(mut self, progress: P) -> Renderer {
+        self.progress = Box::new(progress);
+        self
+    }
+
+    /// Hand the partial film to `write` at most every `interval` while rendering, e.g. to save
+    /// it to disk. It is called from a render thread after a tile finishes. Can be given more
+    /// than once, each with its own interval.
+    pub fn with_flush<F: Fn(&Film) + Sync + 'static>(mut self, interval: Duration, write: F) -> Renderer {
+        self.flushes.push(Periodic::new(interval, write));
+        self
+    }
+
+    /// Hand a checkpoint to `save` after a pass once `interval` has passed since the last one,
+    /// and always after the final pass, so the render can be resumed or extended later.
+    pub fn with_checkpoint<F: Fn(&Checkpoint) + Sync + 'static>(mut self, interval: Duration, save: F) -> Renderer {
+        self.checkpoint = Some(Periodic::new(interval, save));
+        self
+    }
+
+    /// Stop early once `stop` is set, e.g. from a Ctrl-C handler. Tiles already started are
+    /// finished, and the film holds whatever samples were taken.
+    pub fn with_stop(mut self, stop: Arc<AtomicBool>) -> Renderer {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Spend at most `limit` rendering. A pass isn't started if the previous one suggests it won't
+    /// finish in time, and one that overruns is cut short.
+    pub fn with_time_limit(mut self, limit: Duration) -> Renderer {
+        self.time_limit = Some(limit);
+        self
+    }
+
+    /// Render the beauty pass.
+    pub fn render(&self, scene: &Scene, settings: &RenderSettings) -> Result<Image, String> {
+        self.render_film(scene, settings).map(|film| film.beauty())
+    }
+
+    /// Render the beauty pass along with the first-hit AOVs. Progress is reported per tile per
+    /// pass. Fails if the settings don't validate.
+    pub fn render_film(&self, scene: &Scene, settings: &RenderSettings) -> Result<Film, String> {
+        settings.validate()?;
+        let camera = scene.build_camera(&settings.image)?;
+        let film = Film::new(settings.image.width, settings.image.height());
+        Ok(self.render_passes(scene, &camera, settings, film, 0))
+    }
+
+    /// Carry on from a checkpoint until the film has `settings.image.samples_per_pixel` samples.
+    pub fn resume_film(&self, scene: &Scene, settings: &RenderSettings, checkpoint: Checkpoint) -> Result<Film, String> {
+        settings.validate()?;
+        checkpoint.check(scene, settings)?;
+        let camera = scene.build_camera(&settings.image)?;
+        Ok(self.render_passes(scene, &camera, settings, checkpoint.film, checkpoint.passes))
+    }
+
+    fn render_passes(&self, scene: &Scene, camera: &Camera, settings: &RenderSettings, film: Film, first_pass: u32) -> Film {
+        let image_width = settings.image.width;
+        let image_height = settings.image.height();
+
+        // Every pass but perhaps the last has `samples_per_pass` samples.
+        let done = film.pixels.first().map_or(0, |p| p.samples);
+        let remaining = settings.image.sample_limit().saturating_sub(done);
+        let pass_count = remaining.div_ceil(settings.samples_per_pass);
+        let last_pass = first_pass + pass_count;
+        let passes = (first_pass..last_pass).map(|pass| {
+            let start = (pass - first_pass) * settings.samples_per_pass;
+            (pass, done + start, u32::min(settings.samples_per_pass, remaining - start))
+        });
+
+        let tiles = tiles(image_width, image_height, settings.tile_size, settings.tile_order);
+        let framebuffer = Framebuffer::from_film(film);
+        for flush in &self.flushes {
+            flush.restart();
+        }
+        if let Some(checkpoint) = &self.checkpoint {
+            checkpoint.restart();
+        }
+        let deadline = self.time_limit.map(|limit| Instant::now() + limit);
+        let fingerprint = Checkpoint::fingerprint(scene, settings);
+        let checkpoint = |passes, film| Checkpoint {
+            seed: settings.seed,
+            samples_per_pass: settings.samples_per_pass,
+            passes,
+            fingerprint,
+            film,
+        };
+
+        self.progress.start(tiles.len() as u64 * pass_count as u64);
+        let mut passes_done = first_pass;
+        let mut last_pass_time = None;
+        // The film as of the last complete pass, if a pass was cut short.
+        let mut interrupted = None;
+        for (pass, first_sample, samples) in passes {
+            let too_long = deadline.zip(last_pass_time).is_some_and(|(d, t)| Instant::now() + t > d);
+            if too_long || self.stopping(deadline) {
+                break;
+            }
+            let pass_start = Instant::now();
+            let before = self.checkpoint.as_ref().map(|_| framebuffer.snapshot());
+            let cut_short = AtomicBool::new(false);
+
+            tiles.par_iter().for_each(|tile| {
+                // For each tile..
+                if self.stopping(deadline) {
+                    cut_short.store(true, Ordering::Relaxed);
+                    return;
+                }
+                let pixels = render_tile(scene, camera, settings, tile, first_sample, samples);
+                framebuffer.add_tile(tile, &pixels);
+                self.progress.advance(1);
+                for flush in &self.flushes {
+                    flush.maybe_write(false, || framebuffer.snapshot());
+                }
+            });
+
+            if cut_short.into_inner() {
+                interrupted = before;
+                break;
+            }
+            passes_done = pass + 1;
+            last_pass_time = Some(pass_start.elapsed());
+            if let (Some(periodic), true) = (&self.checkpoint, passes_done < last_pass) {
+                periodic.maybe_write(false, || checkpoint(passes_done, framebuffer.snapshot()));
+            }
+        }
+        self.progress.finish();
+
+        // Always leave a checkpoint at the end. Samples from a pass that was cut short can't be
+        // resumed, so it holds only the complete passes.
+        if let (Some(periodic), true) = (&self.checkpoint, passes_done > first_pass) {
+            let film = interrupted.unwrap_or_else(|| framebuffer.snapshot());
+            periodic.maybe_write(true, || checkpoint(passes_done, film));
+        }
+
+        framebuffer.into_film()
+    }
+
+    /// Render on other processes instead: workers that connect to `listener` (see
+    /// `distributed::work`) are handed tiles until the film is done, or the render is stopped.
+    /// The film is the same as `render_film` would give. `scene` is posed at `time`, which the
+    /// workers pose theirs at too. No checkpoints are taken.
+    pub fn render_distributed(&self, listener: &TcpListener, scene: &Scene, time: f32, settings: &RenderSettings) -> io::Result<Film> {
+        settings.validate().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        for flush in &self.flushes {
+            flush.restart();
+        }
+        let deadline = self.time_limit.map(|limit| Instant::now() + limit);
+        let hooks = Hooks {
+            progress: &*self.progress,
+            stopping: &|| self.stopping(deadline),
+            merged: &|framebuffer| {
+                for flush in &self.flushes {
+                    flush.maybe_write(false, || framebuffer.snapshot());
+                }
+            },
+        };
+        coordinate(listener, scene, time, settings, hooks)
+    }
+
+    fn stopping(&self, deadline: Option<Instant>) -> bool {
+        self.stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed))
+            || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// Take `samples` samples for each pixel of `tile`, numbered on from `first_sample`. Each sample's
+/// random stream is picked by its number and the pixel's position, so the image doesn't depend on
+/// how it was cut up into tiles and passes or where the tile was rendered.
+pub fn render_tile(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    tile: &Tile,
+    first_sample: u32,
+    samples: u32,
+) -> Vec<Pixel> {
+    let image_width = settings.image.width;
+    let image_height = settings.image.height();
+    let max_depth = settings.image.max_depth;
+    let exposure = scene.camera.exposure;
+    let effects = &scene.camera.effects;
+    let forward = Vec3::unit_vector(&(scene.camera.lookat - scene.camera.lookfrom));
+    // Stream 0 is left for building scenes.
+    let stream = |sample: u32, pixel: usize| sample as u64 * (image_width * image_height) as u64 + pixel as u64 + 1;
+
+    tile.pixels()
+        .map(|(x, y)| {
+            let j = image_height - 1 - y; // The camera counts rows from the bottom.
+
+            // Run $samples rays through the pixel, at random positions within the pixel
+            (first_sample..first_sample + samples).fold(Pixel::default(), |mut pixel, sample| {
+                seed_rng(settings.seed, stream(sample, y * image_width + x));
+                let u = (x as f32 + random_f32()) / (image_width as f32 - 1.0);
+                let v = (j as f32 + random_f32()) / (image_height as f32 - 1.0);
+
+                // Get a vector representing the ray out of the camera. Parts of the image that the
+                // camera doesn't cover stay black.
+                match effects.get_ray(camera, u, v, settings.image.aspect(), forward) {
+                    Some((r, weight)) => {
+                        // A physical camera scales the light its rays bring back by its exposure.
+                        let (color, first_hit) = camera_sample(&r, scene, max_depth); // Determine the color of the ray reflected back at the camera
+                        let color = exposure.map_or(color, |e| e.scale() * color) * weight;
+                        pixel.add_sample(color, scene.background.color(&r), first_hit);
+                    }
+                    None => pixel.add_sample(Color::zero(), Color::zero(), None),
+                }
+                pixel
+            })
+        })
+        .collect()
+}
+
+fn hit_list<'a, H: Hittable>(hittables: &'a [H], r: &Ray, t_min: f32, t_max: f32) -> Option<(usize, HitRecord<'a>)> {
+    let mut hit_rec = Option::None;
+    let mut closest_so_far = t_max;
+
+    for (i, hittable) in hittables.iter().enumerate() {
+        if let Some(rec) = hittable.hit(r, t_min, closest_so_far) {
+            closest_so_far = rec.t;
+            hit_rec = Option::Some((i, rec));
+        }
+    }
+
+    hit_rec
+}
+
+fn ray_color(r: &Ray, scene: &Scene, depth: i32) -> Color {
+    if depth <= 0 {
+        return Color::zero();
+    }
+
+    match hit_list(&scene.world, r, 0.001, INFINITY) {
+        Some((_, rec)) => shade(r, &rec, scene, depth),
+        None => scene.background.color(r),
+    }
+}
+
+// Trace a camera ray, also returning what it hit first for the AOVs.
+fn camera_sample(r: &Ray, scene: &Scene, depth: i32) -> (Color, Option<FirstHit>) {
+    match hit_list(&scene.world, r, 0.001, INFINITY) {
+        Some((object_id, rec)) => {
+            let first_hit = FirstHit {
+                albedo: rec.material.albedo(&rec),
+                normal: rec.normal,
+                position: rec.p,
+                depth: rec.t * r.dir.length(),
+                object_id,
+            };
+            (shade(r, &rec, scene, depth), Some(first_hit))
+        }
+        None => (scene.background.color(r), None),
+    }
+}
+
+fn shade(r: &Ray, rec: &HitRecord, scene: &Scene, depth: i32) -> Color {
+    let emitted = rec.material.emitted(rec);
+    match rec.material.scatter(r, rec) {
+        (Some(scattered_ray), attenuation) => {
+            emitted + attenuation * ray_color(&scattered_ray, scene, depth - 1)
+        }
+        (None, _) => emitted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct Counter {
+        total: AtomicU64,
+        done: AtomicU64,
+    }
+
+    impl Progress for Arc<Counter> {
+        fn start(&self, total: u64) {
+            self.total.store(total, Ordering::SeqCst);
+        }
+
+        fn advance(&self, n: u64) {
+            self.done.fetch_add(n, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn reports_progress_and_is_repeatable() {
+        let scene = Scene::parse(include_str!("../scenes/three_spheres.toml")).unwrap();
+        let mut settings = RenderSettings::for_scene(&scene);
+        settings.image.width = 12;
+        settings.image.samples_per_pixel = Some(2);
+        settings.tile_size = 4;
+
+        let counter = Arc::new(Counter::default());
+        let first = Renderer::new().with_progress(counter.clone()).render(&scene, &settings).unwrap();
+        let second = Renderer::new().render(&scene, &settings).unwrap();
+
+        // 12 x 8 pixels in 4 x 4 tiles.
+        assert_eq!(counter.total.load(Ordering::SeqCst), 6);
+        assert_eq!(counter.done.load(Ordering::SeqCst), 6);
+        assert_eq!(first.pixels, second.pixels);
+
+        // Nor does it depend on how the image is cut into tiles.
+        settings.tile_size = 5;
+        settings.tile_order = TileOrder::Hilbert;
+        assert_eq!(Renderer::new().render(&scene, &settings).unwrap().pixels, first.pixels);
+
+        // An empty image is refused rather than cut into tiles.
+        settings.image.width = 0;
+        settings.tile_order = TileOrder::Spiral;
+        assert!(Renderer::new().render(&scene, &settings).is_err());
+    }
+
+    #[test]
+    fn resumed_render_matches_uninterrupted() {
+        let scene = Scene::parse(include_str!("../scenes/three_spheres.toml")).unwrap();
+        let mut settings = RenderSettings::for_scene(&scene);
+        settings.image.width = 12;
+        settings.image.samples_per_pixel = Some(6);
+        settings.samples_per_pass = 2;
+        let whole = Renderer::new().render(&scene, &settings).unwrap();
+
+        // Stop after a short last pass, as if killed, then carry on.
+        let saved = Arc::new(Mutex::new(vec![]));
+        let sink = saved.clone();
+        settings.image.samples_per_pixel = Some(3);
+        Renderer::new()
+            .with_checkpoint(Duration::ZERO, move |checkpoint| {
+                sink.lock().unwrap().push((checkpoint.passes, checkpoint.film.clone()))
+            })
+            .render(&scene, &settings)
+            .unwrap();
+        let (passes, film) = saved.lock().unwrap().pop().unwrap();
+        assert_eq!(passes, 2);
+
+        settings.image.samples_per_pixel = Some(6);
+        let checkpoint = |film| Checkpoint {
+            seed: settings.seed,
+            samples_per_pass: 2,
+            passes,
+            fingerprint: Checkpoint::fingerprint(&scene, &settings),
+            film,
+        };
+        let resumed = Renderer::new().resume_film(&scene, &settings, checkpoint(film.clone())).unwrap();
+        assert!(resumed.pixels.iter().all(|p| p.samples == 6));
+        for (a, b) in resumed.beauty().pixels.iter().zip(&whole.pixels) {
+            assert!((*a - *b).length() < 1e-4);
+        }
+
+        // Not against a different scene, camera or depth, though.
+        let refused = "checkpoint was rendered from a different scene, camera or max depth".to_string();
+        let mut other = Scene::parse(include_str!("../scenes/three_spheres.toml")).unwrap();
+        other.world.pop();
+        assert_eq!(Renderer::new().resume_film(&other, &settings, checkpoint(film.clone())).err(), Some(refused.clone()));
+        let mut other = Scene::parse(include_str!("../scenes/three_spheres.toml")).unwrap();
+        other.camera.vfov += 1.0;
+        assert_eq!(Renderer::new().resume_film(&other, &settings, checkpoint(film.clone())).err(), Some(refused.clone()));
+        let mut deeper = settings;
+        deeper.image.max_depth += 1;
+        assert_eq!(Renderer::new().resume_film(&scene, &deeper, checkpoint(film)).err(), Some(refused));
+    }
+
+    // Sets the stop flag once `after` tiles are done.
+    struct StopAfter {
+        after: u64,
+        done: AtomicU64,
+        stop: Arc<AtomicBool>,
+    }
+
+    impl Progress for StopAfter {
+        fn advance(&self, n: u64) {
+            if self.done.fetch_add(n, Ordering::SeqCst) + n >= self.after {
+                self.stop.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    #[test]
+    fn stops_early_and_checkpoints_complete_passes() {
+        let scene = Scene::parse(include_str!("../scenes/three_spheres.toml")).unwrap();
+        let mut settings = RenderSettings::for_scene(&scene);
+        settings.image.width = 12;
+        settings.image.samples_per_pixel = Some(8);
+        settings.samples_per_pass = 2;
+        settings.tile_size = 4;
+
+        // Stop one tile into the second pass (of six tiles each).
+        let stop = Arc::new(AtomicBool::new(false));
+        let saved = Arc::new(Mutex::new(vec![]));
+        let sink = saved.clone();
+        let film = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap().install(|| {
+            Renderer::new()
+                .with_progress(StopAfter { after: 7, done: AtomicU64::new(0), stop: stop.clone() })
+                .with_stop(stop.clone())
+                .with_checkpoint(Duration::from_secs(3600), move |checkpoint| {
+                    sink.lock().unwrap().push((checkpoint.passes, checkpoint.samples()))
+                })
+                .render_film(&scene, &settings)
+                .unwrap()
+        });
+
+        let samples: Vec<u32> = film.pixels.iter().map(|p| p.samples).collect();
+        assert!(samples.contains(&2) && samples.contains(&4) && !samples.contains(&6));
+        assert_eq!(*saved.lock().unwrap(), vec![(1, 2)]);
+
+        // Out of time before starting: nothing rendered.
+        let film = Renderer::new().with_time_limit(Duration::ZERO).render_film(&scene, &settings).unwrap();
+        assert!(film.pixels.iter().all(|p| p.samples == 0));
+    }
+
+    #[test]
+    fn flushes_partial_film() {
+        let scene = Scene::parse(include_str!("../scenes/three_spheres.toml")).unwrap();
+        let mut settings = RenderSettings::for_scene(&scene);
+        settings.image.width = 12;
+        settings.image.samples_per_pixel = Some(1);
+        settings.tile_size = 4;
+
+        let flushes = Arc::new(AtomicU64::new(0));
+        let count = flushes.clone();
+        Renderer::new()
+            .with_flush(Duration::ZERO, move |film| {
+                assert_eq!((film.width, film.height), (12, 8));
+                count.fetch_add(1, Ordering::SeqCst);
+            })
+            .render(&scene, &settings)
+            .unwrap();
+        assert!(flushes.load(Ordering::SeqCst) >= 1);
+    }
+}
@@ -0,0 +1,1699 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::animation::*;
+use crate::aperture::*;
+use crate::camera::*;
+use crate::hit::*;
+use crate::lens::*;
+use crate::material::*;
+use crate::perlin::*;
+use crate::ray::*;
+use crate::texture::*;
+use crate::transform::*;
+use crate::util::{degrees_to_radians, fnv1a};
+use crate::vec3::*;
+
+/// Output resolution and sampling.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ImageSettings {
+    pub width: usize,
+    /// An exact height in pixels, which sets the aspect ratio; otherwise the height follows from
+    /// `aspect_ratio`.
+    pub height: Option<usize>,
+    pub aspect_ratio: f32,
+    /// `None` keeps sampling until the render is stopped or runs out of time.
+    pub samples_per_pixel: Option<u32>,
+    pub max_depth: i32,
+}
+
+impl ImageSettings {
+    pub fn height(&self) -> usize {
+        self.height.unwrap_or((self.width as f32 / self.aspect_ratio) as usize)
+    }
+
+    /// The most samples per pixel a render takes: as many as a count holds, if unlimited.
+    pub fn sample_limit(&self) -> u32 {
+        self.samples_per_pixel.unwrap_or(u32::MAX)
+    }
+
+    /// The width over the height of the image as rendered.
+    pub fn aspect(&self) -> f32 {
+        match self.height {
+            Some(height) => self.width as f32 / height as f32,
+            None => self.aspect_ratio,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width == 0 {
+            return Err("image.width must be at least 1".to_string());
+        }
+        if self.height == Some(0) {
+            return Err("image.height must be at least 1".to_string());
+        }
+        if !is_positive(self.aspect_ratio) {
+            return Err(format!("image.aspect_ratio must be positive, got {}", self.aspect_ratio));
+        }
+        if self.height() == 0 {
+            return Err(format!("image is less than one pixel high at aspect ratio {}", self.aspect_ratio));
+        }
+        if self.samples_per_pixel == Some(0) {
+            return Err("image.samples_per_pixel must be at least 1".to_string());
+        }
+        if self.max_depth < 1 {
+            return Err(format!("image.max_depth must be at least 1, got {}", self.max_depth));
+        }
+        Ok(())
+    }
+}
+
+impl Default for ImageSettings {
+    fn default() -> ImageSettings {
+        ImageSettings {
+            width: 1200,
+            height: None,
+            aspect_ratio: 3.0 / 2.0,
+            samples_per_pixel: Some(500),
+            max_depth: 50,
+        }
+    }
+}
+
+/// How the camera maps the scene onto the image.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Projection {
+    /// Things shrink with distance; the camera's `vfov` sets how much is seen.
+    #[default]
+    Perspective,
+    /// Parallel rays, showing `view_height` world units from the bottom of the image to the top.
+    /// There is no depth of field.
+    Orthographic { view_height: f32 },
+    /// Every direction, mapped by longitude and latitude onto an image twice as wide as high.
+    Equirectangular,
+    /// A circular image covering `fov` degrees, which may be more than 180.
+    Fisheye { fov: f32, mapping: FisheyeMapping },
+    /// Rays traced through a real lens design, focused on `focus_dist`. The aperture is the
+    /// lens's own, set in `options`.
+    Lens { system: Arc<LensSystem>, options: LensOptions },
+    /// A perspective camera matched to a real one's calibration. Its `vfov` follows from the
+    /// intrinsics, and the image should have their aspect ratio.
+    Calibrated(Intrinsics),
+}
+
+/// What a camera keeps in focus, in place of a fixed `focus_dist`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Autofocus {
+    /// Whatever is seen through this point of the image, from (0, 0) at the bottom left to
+    /// (1, 1) at the top right.
+    Point(f32, f32),
+    /// The centre of the object with this name.
+    Object(String),
+}
+
+/// Everything needed to build a `Camera` once the image aspect ratio is known.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CameraSettings {
+    pub projection: Projection,
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    pub vfov: f32,
+    pub aperture: f32,
+    /// The shape of a perspective camera's aperture, and so of its bokeh.
+    pub aperture_shape: ApertureShape,
+    /// Makes this a physical camera, whose f-number sets the aperture in place of `aperture`
+    /// (or a lens's own). A perspective camera is taken to have full frame film in a scene in
+    /// metres, like a lens camera by default.
+    pub exposure: Option<Exposure>,
+    /// Lens shift of a perspective camera, across and up, in fractions of the image size.
+    pub shift: (f32, f32),
+    /// Tilt and swing of a perspective camera's plane of focus, in degrees.
+    pub tilt: (f32, f32),
+    /// Set `focus_dist` from the scene when it's posed; see `Scene::focus_camera`.
+    pub autofocus: Option<Autofocus>,
+    /// Distortion, chromatic aberration and vignetting, for any projection.
+    pub effects: LensEffects,
+    /// Render a view for each eye, together in one image.
+    pub stereo: Option<StereoRig>,
+    /// The seconds a rolling shutter takes to start exposing the rows of the image one after
+    /// another, from the top to the bottom. Each row is then exposed for the shutter time of
+    /// `exposure`, if there is one, or an instant.
+    pub rolling_shutter: Option<f32>,
+    pub focus_dist: f32,
+}
+
+impl CameraSettings {
+    /// Whether the camera takes time to expose its image, through an exposure or a rolling
+    /// shutter.
+    pub fn has_shutter(&self) -> bool {
+        self.exposure.is_some() || self.rolling_shutter.is_some()
+    }
+
+    /// Fails if a lens camera can't focus on `focus_dist`, which may have been set by autofocus
+    /// since the settings were validated.
+    pub fn build(&self, aspect_ratio: f32) -> Result<Camera, String> {
+        let Some(rig) = self.stereo else {
+            return self.build_eye(aspect_ratio, 0.0, f32::INFINITY);
+        };
+        let aspect_ratio = rig.layout.eye_aspect_ratio(aspect_ratio);
+        let convergence = rig.convergence.unwrap_or(self.focus_dist);
+        let left = self.build_eye(aspect_ratio, -rig.eye_separation / 2.0, convergence)?;
+        let right = self.build_eye(aspect_ratio, rig.eye_separation / 2.0, convergence)?;
+        Ok(Stereo::new(left, right, rig.layout).into())
+    }
+
+    // The camera of an eye `offset` to the right of `lookfrom`, whose view meets the middle of
+    // the other eye's at `convergence`.
+    fn build_eye(&self, aspect_ratio: f32, offset: f32, convergence: f32) -> Result<Camera, String> {
+        let (u, _, _) = basis(self.lookfrom, self.lookat, self.vup);
+        let (lookfrom, lookat) = (self.lookfrom + offset * u, self.lookat + offset * u);
+        let camera = match &self.projection {
+            Projection::Perspective => {
+                // Shift the view rather than turning the camera in, so the eyes' images stay
+                // level with each other.
+                let view_width = aspect_ratio * 2.0 * f32::tan(degrees_to_radians(self.vfov) / 2.0);
+                let converge = -offset / (convergence * view_width);
+                Perspective::new(
+                    lookfrom,
+                    lookat,
+                    self.vup,
+                    self.vfov,
+                    aspect_ratio,
+                    self.thin_lens_aperture(aspect_ratio),
+                    self.focus_dist,
+                )
+                .with_aperture_shape(self.aperture_shape.clone())
+                .with_shift(self.shift.0 + converge, self.shift.1)
+                .with_tilt(self.tilt.0, self.tilt.1)
+                .into()
+            }
+            Projection::Orthographic { view_height } => {
+                Orthographic::new(lookfrom, lookat, self.vup, *view_height, aspect_ratio).into()
+            }
+            Projection::Equirectangular => {
+                Equirectangular::new(self.lookfrom, self.lookat, self.vup).with_eye_offset(offset).into()
+            }
+            Projection::Fisheye { fov, mapping } => {
+                Fisheye::new(lookfrom, lookat, self.vup, *fov, *mapping, aspect_ratio).into()
+            }
+            Projection::Calibrated(intrinsics) => {
+                // Move the principal point, as a shift does for a perspective camera.
+                let mut intrinsics = *intrinsics;
+                intrinsics.cx += offset * intrinsics.fx / convergence;
+                let aperture = self.thin_lens_aperture(aspect_ratio);
+                Calibrated::new(lookfrom, lookat, self.vup, intrinsics, aperture, self.focus_dist).into()
+            }
+            Projection::Lens { system, options } => {
+                let basis = basis(lookfrom, lookat, self.vup);
+                let options = self.lens_options(system, options);
+                LensCamera::new(system, &options, lookfrom, basis, self.focus_dist, aspect_ratio)
+                    .map_err(|e| format!("camera.lens: {}", e))?
+                    .into()
+            }
+        };
+        Ok(camera)
+    }
+
+    // The diameter of a perspective camera's lens: focal length over f-number for a physical
+    // camera, with the focal length that gives `vfov` on full frame film.
+    fn thin_lens_aperture(&self, aspect_ratio: f32) -> f32 {
+        match self.exposure {
+            Some(exposure) => {
+                let film = LensOptions::default();
+                let film_height = film.film_diagonal / f32::sqrt(1.0 + aspect_ratio * aspect_ratio);
+                let focal_length = 0.5 * film_height / f32::tan(degrees_to_radians(self.vfov) / 2.0);
+                focal_length / exposure.f_number / film.scale
+            }
+            None => self.aperture,
+        }
+    }
+
+    // A lens camera's options, with the stop set by the f-number for a physical camera.
+    fn lens_options(&self, system: &LensSystem, options: &LensOptions) -> LensOptions {
+        let mut options = *options;
+        if let (Some(exposure), Some(focal_length)) = (self.exposure, system.focal_length(options.film_diagonal)) {
+            options.aperture_diameter = Some(focal_length / exposure.f_number);
+        }
+        options
+    }
+
+    // The ray through (s, t) from the middle of the lens, as if it were a pinhole. A lens camera
+    // is taken to be a perspective camera with the same field of view, and a stereo rig to be
+    // one camera between the eyes with an eye's view.
+    fn pinhole_ray(&self, s: f32, t: f32, aspect_ratio: f32) -> Option<Ray> {
+        let mut pinhole = self.clone();
+        pinhole.aperture = 0.0;
+        pinhole.exposure = None;
+        let aspect_ratio = match pinhole.stereo.take() {
+            Some(rig) => rig.layout.eye_aspect_ratio(aspect_ratio),
+            None => aspect_ratio,
+        };
+        if let Projection::Lens { system, options } = &self.projection {
+            let film_height = options.film_diagonal / f32::sqrt(1.0 + aspect_ratio * aspect_ratio);
+            let focal_length = system.focal_length(options.film_diagonal)?;
+            pinhole.vfov = 2.0 * f32::atan(0.5 * film_height / focal_length).to_degrees();
+            pinhole.projection = Projection::Perspective;
+        }
+        pinhole.build(aspect_ratio).ok()?.get_ray(s, t)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if (self.lookfrom - self.lookat).near_zero() {
+            return Err("camera: lookfrom and lookat must be different points".to_string());
+        }
+        if Vec3::cross(&self.vup, &(self.lookfrom - self.lookat)).near_zero() {
+            return Err("camera: vup must not be parallel to the view direction".to_string());
+        }
+        if let Some(exposure) = &self.exposure {
+            exposure.validate()?;
+        }
+        if let Some(readout) = self.rolling_shutter.filter(|r| !is_positive(*r)) {
+            return Err(format!("camera.rolling_shutter must be positive, got {}", readout));
+        }
+        match &self.projection {
+            Projection::Perspective if !(self.vfov > 0.0 && self.vfov < 180.0) => {
+                return Err(format!("camera.vfov must be between 0 and 180 degrees, got {}", self.vfov));
+            }
+            Projection::Orthographic { view_height } if !is_positive(*view_height) => {
+                return Err(format!("camera.view_height must be positive, got {}", view_height));
+            }
+            Projection::Fisheye { fov, .. } if !(*fov > 0.0 && *fov <= 360.0) => {
+                return Err(format!("camera.fov must be between 0 and 360 degrees, got {}", fov));
+            }
+            Projection::Calibrated(intrinsics) => intrinsics.validate()?,
+            Projection::Lens { system, options } => {
+                if !is_positive(options.film_diagonal) || !is_positive(options.scale) {
+                    return Err("camera.film_diagonal and camera.lens_scale must be positive".to_string());
+                }
+                if let Some(diameter) = options.aperture_diameter.filter(|d| !is_positive(*d)) {
+                    return Err(format!("camera.lens_aperture must be positive, got {}", diameter));
+                }
+                if is_positive(self.focus_dist) {
+                    let options = self.lens_options(system, options);
+                    system.focused(&options, self.focus_dist).map_err(|e| format!("camera.lens: {}", e))?;
+                }
+            }
+            _ => {}
+        }
+        if !is_non_negative(self.aperture) {
+            return Err(format!("camera.aperture must be non-negative, got {}", self.aperture));
+        }
+        self.aperture_shape.validate().map_err(|e| format!("camera: {}", e))?;
+        self.effects.validate()?;
+        let moved = self.shift != (0.0, 0.0) || self.tilt != (0.0, 0.0);
+        if moved && !matches!(self.projection, Projection::Perspective) {
+            return Err("camera: shift and tilt only apply to a perspective camera".to_string());
+        }
+        if self.aperture_shape != ApertureShape::Disc && !matches!(self.projection, Projection::Perspective) {
+            // A lens camera's aperture is the stop in its prescription.
+            return Err("camera: aperture blades and masks only apply to a perspective camera".to_string());
+        }
+        if let Some(rig) = &self.stereo {
+            rig.validate()?;
+            if matches!(self.projection, Projection::Orthographic { .. }) {
+                return Err("camera: an orthographic camera can't be stereo, as both eyes would see the same".to_string());
+            }
+            if rig.convergence.is_some() && !matches!(self.projection, Projection::Perspective | Projection::Calibrated(_)) {
+                return Err("camera.convergence only applies to a perspective or calibrated camera".to_string());
+            }
+        }
+        if let Some(Autofocus::Point(s, t)) = self.autofocus {
+            if !((0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&t)) {
+                return Err(format!("camera.autofocus must be a point in the image, from [0, 0] to [1, 1], got [{}, {}]", s, t));
+            }
+        }
+        if !(self.shift.0.is_finite() && self.shift.1.is_finite()) {
+            return Err(format!("camera.shift must be finite, got {:?}", self.shift));
+        }
+        for (name, angle) in [("tilt", self.tilt.0), ("swing", self.tilt.1)] {
+            if !(angle > -90.0 && angle < 90.0) {
+                return Err(format!("camera.{} must be between -90 and 90 degrees, got {}", name, angle));
+            }
+        }
+        if !is_positive(self.focus_dist) {
+            return Err(format!("camera.focus_dist must be positive, got {}", self.focus_dist));
+        }
+        Ok(())
+    }
+}
+
+/// What rays that escape the scene see.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum Background {
+    /// White at the horizon fading to light blue overhead.
+    Sky,
+    Solid(Color),
+}
+
+impl Background {
+    pub fn color(&self, r: &Ray) -> Color {
+        match self {
+            Background::Sky => {
+                // Background gradient
+                let unit_direction = Vec3::unit_vector(&r.dir);
+                let t = 0.5 * (unit_direction.y + 1.0);
+                (1.0 - t) * Color::new(1.0, 1.0, 1.0) + (t * Color::new(0.5, 0.7, 1.0))
+            }
+            Background::Solid(color) => *color,
+        }
+    }
+}
+
+pub struct Scene {
+    /// The resolution and sampling the scene was set up for; see `RenderSettings::for_scene`.
+    pub image: ImageSettings,
+    pub camera: CameraSettings,
+    pub background: Background,
+    pub world: Vec<Object>,
+    pub animation: Animation,
+    /// The index in `world` of each named object.
+    pub names: BTreeMap<String, usize>,
+    /// Other views of the scene by name, which `use_camera` switches to.
+    pub cameras: BTreeMap<String, CameraSettings>,
+}
+
+impl Scene {
+    pub fn load(path: &Path) -> Result<Scene, SceneError> {
+        let source = fs::read_to_string(path).map_err(|e| SceneError::Io(path.to_path_buf(), e))?;
+        Scene::parse_in(&source, path.parent().unwrap_or(Path::new("")))
+    }
+
+    /// Files the scene refers to, such as lens prescriptions, are found relative to the current
+    /// directory.
+    pub fn parse(source: &str) -> Result<Scene, SceneError> {
+        Scene::parse_in(source, Path::new(""))
+    }
+
+    fn parse_in(source: &str, dir: &Path) -> Result<Scene, SceneError> {
+        let desc: SceneDesc = toml::from_str(source).map_err(SceneError::Parse)?;
+        desc.build(dir)
+    }
+
+    /// The scene posed as it is `time` seconds into its animation, as a still. Fails if the
+    /// camera's autofocus then asks for a focus it can't reach.
+    pub fn at(&self, time: f32) -> Result<Scene, String> {
+        // With a shutter, moving objects blur over the time it is open, and the camera keeps
+        // its tracks from then on, to move as it exposes the image.
+        let shutter = self.camera.has_shutter() || self.cameras.values().any(|c| c.has_shutter());
+        let mut world = self.world.clone();
+        for track in &self.animation.objects {
+            world[track.index] = match shutter {
+                true => track.during(time),
+                false => track.at(time),
+            };
+        }
+        let animation = match shutter {
+            true => Animation {
+                camera: self.animation.camera.shifted(time),
+                ..Animation::default()
+            },
+            false => Animation::default(),
+        };
+        let mut scene = Scene {
+            image: self.image,
+            camera: self.animation.camera.at(&self.camera, time),
+            background: self.background,
+            world,
+            animation,
+            names: self.names.clone(),
+            cameras: self.cameras.clone(),
+        };
+        // Keep the focus as the camera and objects move.
+        if let Some(focus_dist) = scene.autofocus_distance(&scene.camera)? {
+            scene.camera.focus_dist = focus_dist;
+            scene.camera.validate().map_err(|e| format!("at {}s: {}", time, e))?;
+        }
+        Ok(scene)
+    }
+
+    /// The camera, for an image with `image`'s settings. A camera with a shutter casts each ray
+    /// at a moment while it's open, from where the camera's tracks have it then.
+    pub fn build_camera(&self, image: &ImageSettings) -> Result<Camera, String> {
+        let camera = &self.camera;
+        let open = camera.exposure.map_or(0.0, |exposure| exposure.shutter);
+        let readout = camera.rolling_shutter.unwrap_or(0.0);
+        if open == 0.0 && readout == 0.0 {
+            return camera.build(image.aspect());
+        }
+        let tracks = &self.animation.camera;
+        if tracks.is_empty() {
+            return Ok(Shutter::new(camera.build(image.aspect())?, vec![Transform::identity()], open, readout).into());
+        }
+        // The camera is built once, where it is when the shutter opens, and its rays moved from
+        // there. A move per row is as fine as a rolling shutter can show.
+        let start = tracks.at(camera, 0.0);
+        let (u, v, w) = basis(start.lookfrom, start.lookat, start.vup);
+        let to_start = Mat3::from_rows(u, v, w);
+        let steps = image.height().max(1);
+        let moves = (0..=steps)
+            .map(|i| {
+                let now = tracks.at(camera, (open + readout) * i as f32 / steps as f32);
+                let (u, v, w) = basis(now.lookfrom, now.lookat, now.vup);
+                let rotation = Mat3::from_columns(u, v, w) * to_start;
+                Transform { rotation, translation: now.lookfrom - rotation * start.lookfrom }
+            })
+            .collect();
+        Ok(Shutter::new(start.build(image.aspect())?, moves, open, readout).into())
+    }
+
+    /// Set the camera's `focus_dist` by its autofocus, if it has one: to the distance along the
+    /// view direction to what it focuses on. If nothing is seen through the focus point, the
+    /// focus is left as it was.
+    pub fn focus_camera(&mut self) -> Result<(), String> {
+        if let Some(focus_dist) = self.autofocus_distance(&self.camera)? {
+            self.camera.focus_dist = focus_dist;
+        }
+        Ok(())
+    }
+
+    /// Look through the named camera from now on. It stands still, as the animation's camera
+    /// tracks belong to the scene's own camera.
+    pub fn use_camera(&mut self, name: &str) -> Result<(), String> {
+        let Some(camera) = self.cameras.get(name) else {
+            let names: Vec<_> = self.cameras.keys().map(|name| format!("\"{}\"", name)).collect();
+            return Err(match names.is_empty() {
+                true => format!("there is no camera named \"{}\"; the scene has no named cameras", name),
+                false => format!("there is no camera named \"{}\"; the scene has {}", name, names.join(", ")),
+            });
+        };
+        self.camera = camera.clone();
+        self.animation.camera = CameraTracks::default();
+        Ok(())
+    }
+
+    fn autofocus_distance(&self, camera: &CameraSettings) -> Result<Option<f32>, String> {
+        let target = match &camera.autofocus {
+            None => return Ok(None),
+            Some(Autofocus::Point(s, t)) => {
+                let Some(r) = camera.pinhole_ray(*s, *t, self.image.aspect()) else {
+                    return Ok(None);
+                };
+                let hits = self.world.iter().filter_map(|object| object.hit(&r, 0.001, f32::INFINITY));
+                match hits.min_by(|a, b| a.t.total_cmp(&b.t)) {
+                    Some(rec) => rec.p,
+                    None => return Ok(None),
+                }
+            }
+            Some(Autofocus::Object(name)) => match self.names.get(name) {
+                Some(&index) => self.world[index].center(),
+                None => return Err(format!("camera: there is no object named \"{}\" to focus on", name)),
+            },
+        };
+        let forward = Vec3::unit_vector(&(camera.lookat - camera.lookfrom));
+        let focus_dist = Vec3::dot(&(target - camera.lookfrom), &forward);
+        Ok(is_positive(focus_dist).then_some(focus_dist))
+    }
+
+    /// A hash of what's in the scene (not the image or camera settings), so separate processes
+    /// can check they built the same one. It's taken of the scene written out as TOML, which
+    /// doesn't change between builds.
+    pub fn fingerprint(&self) -> u64 {
+        #[derive(Serialize)]
+        struct Contents<'a> {
+            background: &'a Background,
+            world: &'a [Object],
+        }
+        let contents = Contents { background: &self.background, world: &self.world };
+        let text = toml::to_string(&contents).expect("scenes are made of tables, strings and floats");
+        fnv1a(text.as_bytes())
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(PathBuf, io::Error),
+    /// Malformed TOML, or a field of the wrong type or name. The message includes line and column.
+    Parse(toml::de::Error),
+    /// Well-formed but meaningless, e.g. a negative radius or a reference to an undefined material.
+    Invalid(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(path, e) => write!(f, "could not read scene {}: {}", path.display(), e),
+            SceneError::Parse(e) => write!(f, "invalid scene file: {}", e),
+            SceneError::Invalid(msg) => write!(f, "invalid scene: {}", msg),
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+fn invalid<T>(msg: String) -> Result<T, SceneError> {
+    Err(SceneError::Invalid(msg))
+}
+
+// The on-disk format. Kept separate from the render types so we can validate while converting.
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    #[serde(default)]
+    image: ImageDesc,
+    camera: Option<CameraDesc>,
+    /// Named cameras, as `[cameras.NAME]` tables.
+    #[serde(default)]
+    cameras: BTreeMap<String, CameraDesc>,
+    #[serde(default)]
+    background: BackgroundDesc,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDesc>,
+    #[serde(default)]
+    objects: Vec<ObjectDesc>,
+    animation: Option<AnimationDesc>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ImageDesc {
+    width: Option<usize>,
+    height: Option<usize>,
+    aspect_ratio: Option<f32>,
+    samples_per_pixel: Option<u32>,
+    max_depth: Option<i32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    #[serde(default)]
+    projection: ProjectionDesc,
+    /// `lookfrom`, `lookat` and `vup`, or `rotation` and `translation`, place the camera.
+    lookfrom: Option<[f32; 3]>,
+    lookat: Option<[f32; 3]>,
+    vup: Option<[f32; 3]>,
+    /// The world-to-camera transform, as OpenCV gives it: a rotation matrix or vector.
+    rotation: Option<RotationDesc>,
+    translation: Option<[f32; 3]>,
+    /// Required for perspective projection.
+    vfov: Option<f32>,
+    /// Required for orthographic projection.
+    view_height: Option<f32>,
+    /// Required for fisheye projection.
+    fov: Option<f32>,
+    mapping: Option<MappingDesc>,
+    /// Required for calibrated projection: the camera matrix, in pixels of an image
+    /// `image_size` wide and high.
+    intrinsics: Option<[[f32; 3]; 3]>,
+    image_size: Option<[f32; 2]>,
+    /// A lens camera's prescription file, relative to the scene file. The other lens fields
+    /// are optional.
+    lens: Option<String>,
+    film_diagonal: Option<f32>,
+    lens_aperture: Option<f32>,
+    lens_scale: Option<f32>,
+    aperture: Option<f32>,
+    /// A physical camera's exposure, given all together in place of `aperture`.
+    f_number: Option<f32>,
+    /// In seconds.
+    shutter: Option<f32>,
+    iso: Option<f32>,
+    /// A polygonal aperture with this many blades, turned `aperture_rotation` degrees.
+    aperture_blades: Option<u32>,
+    aperture_rotation: Option<f32>,
+    /// An image of the aperture's shape, relative to the scene file.
+    aperture_mask: Option<String>,
+    /// Lens shift across and up, in fractions of the image width and height.
+    shift: Option<[f32; 2]>,
+    /// `true` to focus on what's in the middle of the image, or a point [s, t] in it from
+    /// [0, 0] at the bottom left.
+    autofocus: Option<AutofocusDesc>,
+    /// The name of an object to focus on.
+    focus_on: Option<String>,
+    /// Brown-Conrady coefficients k1, k2, p1, p2 and optionally k3, as OpenCV gives them. For a
+    /// calibrated camera, they are measured exactly as OpenCV measures them.
+    distortion: Option<Vec<f32>>,
+    /// Relative focal length shifts of the red, green and blue channels.
+    chromatic_aberration: Option<[f32; 3]>,
+    #[serde(default)]
+    vignetting: bool,
+    /// Tilt and swing of the plane of focus, in degrees.
+    tilt: Option<f32>,
+    swing: Option<f32>,
+    /// The readout time of a rolling shutter, in seconds.
+    rolling_shutter: Option<f32>,
+    /// "side-by-side" or "top-bottom" for a view from each eye.
+    stereo: Option<StereoDesc>,
+    /// Defaults to 0.064, a typical distance between the eyes in metres.
+    eye_separation: Option<f32>,
+    convergence: Option<f32>,
+    focus_dist: Option<f32>,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ProjectionDesc {
+    #[default]
+    Perspective,
+    Orthographic,
+    Equirectangular,
+    Fisheye,
+    Lens,
+    Calibrated,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(untagged)]
+enum RotationDesc {
+    Matrix([[f32; 3]; 3]),
+    Vector([f32; 3]),
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(untagged)]
+enum AutofocusDesc {
+    Center(bool),
+    Point([f32; 2]),
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+enum StereoDesc {
+    SideBySide,
+    TopBottom,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum MappingDesc {
+    Equidistant,
+    Equisolid,
+}
+
+fn default_vup() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BackgroundDesc {
+    /// Only "sky" is accepted; checked in `build`.
+    Named(String),
+    Color([f32; 3]),
+}
+
+impl Default for BackgroundDesc {
+    fn default() -> BackgroundDesc {
+        BackgroundDesc::Named("sky".to_string())
+    }
+}
+
+/// Frame timing and camera keys. Objects carry their own keys, in `animate`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnimationDesc {
+    #[serde(default = "default_fps")]
+    fps: f32,
+    /// Defaults to enough frames to reach the last key.
+    frames: Option<u32>,
+    #[serde(default)]
+    interpolation: InterpolationDesc,
+    #[serde(default)]
+    camera: Vec<CameraKeyDesc>,
+}
+
+fn default_fps() -> f32 {
+    24.0
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum InterpolationDesc {
+    Linear,
+    #[default]
+    Spline,
+}
+
+/// The camera at one moment. Each property is interpolated between the keys that give it.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraKeyDesc {
+    time: f32,
+    lookfrom: Option<[f32; 3]>,
+    lookat: Option<[f32; 3]>,
+    vfov: Option<f32>,
+    focus_dist: Option<f32>,
+}
+
+/// An object's placement at one moment, replacing its `rotate` and `translate`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectKeyDesc {
+    time: f32,
+    rotate: Option<[f32; 3]>,
+    translate: Option<[f32; 3]>,
+}
+
+/// Either a plain `[r, g, b]` colour or a procedural texture table.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureDesc {
+    Color([f32; 3]),
+    Procedural(ProceduralDesc),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum ProceduralDesc {
+    Checker {
+        #[serde(default = "default_checker_scale")]
+        scale: f32,
+        even: [f32; 3],
+        odd: [f32; 3],
+    },
+    Noise {
+        #[serde(default = "default_noise_scale")]
+        scale: f32,
+        #[serde(default = "default_white")]
+        color: [f32; 3],
+    },
+}
+
+fn default_checker_scale() -> f32 {
+    1.0
+}
+
+fn default_noise_scale() -> f32 {
+    4.0
+}
+
+fn default_white() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: TextureDesc,
+    },
+    Metal {
+        albedo: [f32; 3],
+        #[serde(default)]
+        fuzz: f32,
+    },
+    #[serde(alias = "dialectric")]
+    Dielectric {
+        index_of_refraction: f32,
+    },
+    #[serde(alias = "diffuse_light")]
+    Light {
+        emit: TextureDesc,
+    },
+}
+
+/// Any object may also be placed with `rotate` (degrees about x, then y, then z) and `translate`,
+/// or moved over time with `animate` keys, and given a `name` for the camera to focus on.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum ObjectDesc {
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+        material: String,
+        #[serde(default)]
+        rotate: Option<[f32; 3]>,
+        #[serde(default)]
+        translate: Option<[f32; 3]>,
+        #[serde(default)]
+        animate: Vec<ObjectKeyDesc>,
+        name: Option<String>,
+    },
+    /// An axis-aligned rectangle at `axis = k`, from `min` to `max` in the other two axes.
+    Rect {
+        axis: AxisDesc,
+        min: [f32; 2],
+        max: [f32; 2],
+        k: f32,
+        material: String,
+        #[serde(default)]
+        rotate: Option<[f32; 3]>,
+        #[serde(default)]
+        translate: Option<[f32; 3]>,
+        #[serde(default)]
+        animate: Vec<ObjectKeyDesc>,
+        name: Option<String>,
+    },
+    Box {
+        min: [f32; 3],
+        max: [f32; 3],
+        material: String,
+        #[serde(default)]
+        rotate: Option<[f32; 3]>,
+        #[serde(default)]
+        translate: Option<[f32; 3]>,
+        #[serde(default)]
+        animate: Vec<ObjectKeyDesc>,
+        name: Option<String>,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum AxisDesc {
+    X,
+    Y,
+    Z,
+}
+
+fn vec3(v: [f32; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+fn mat3(rows: [[f32; 3]; 3]) -> Mat3 {
+    Mat3::from_rows(vec3(rows[0]), vec3(rows[1]), vec3(rows[2]))
+}
+
+// Both reject NaN and infinity along with out-of-range values.
+fn is_positive(v: f32) -> bool {
+    v.is_finite() && v > 0.0
+}
+
+fn is_non_negative(v: f32) -> bool {
+    v.is_finite() && v >= 0.0
+}
+
+fn check_color(what: &str, c: [f32; 3]) -> Result<Color, SceneError> {
+    if !c.iter().all(|v| is_non_negative(*v)) {
+        return invalid(format!("{} must be non-negative, got {:?}", what, c));
+    }
+    Ok(vec3(c))
+}
+
+impl SceneDesc {
+    fn build(self, dir: &Path) -> Result<Scene, SceneError> {
+        let image = self.image.build()?;
+        let mut cameras = BTreeMap::new();
+        for (name, desc) in &self.cameras {
+            // Each named camera is written to a file named after it, next to the output.
+            if name.is_empty() || name.contains(['/', '\\']) {
+                return invalid(format!("cameras.{:?}: a camera's name goes into a file name, so can't be empty or hold / or \\", name));
+            }
+            let in_camera = |e| match e {
+                SceneError::Invalid(msg) => SceneError::Invalid(format!("cameras.{}: {}", name, msg)),
+                e => e,
+            };
+            cameras.insert(name.clone(), desc.build(dir).map_err(in_camera)?);
+        }
+        // Without a [camera], the named camera first in alphabetical order is the scene's own.
+        let (camera_desc, camera) = match (&self.camera, self.cameras.values().next()) {
+            (Some(desc), _) => (desc, desc.build(dir)?),
+            (None, Some(desc)) => (desc, cameras.values().next().unwrap().clone()),
+            (None, None) => return invalid("the scene needs a [camera], or named ones in [cameras.NAME]".to_string()),
+        };
+        let background = match &self.background {
+            BackgroundDesc::Named(name) if name == "sky" => Background::Sky,
+            BackgroundDesc::Named(name) => {
+                return invalid(format!("background must be \"sky\" or an [r, g, b] colour, got \"{}\"", name))
+            }
+            BackgroundDesc::Color(c) => Background::Solid(check_color("background", *c)?),
+        };
+
+        let mut materials = BTreeMap::new();
+        for (name, desc) in &self.materials {
+            materials.insert(name.as_str(), desc.build(name)?);
+        }
+
+        let interpolation = match self.animation.as_ref().map_or_else(Default::default, |a| a.interpolation) {
+            InterpolationDesc::Linear => Interpolation::Linear,
+            InterpolationDesc::Spline => Interpolation::Spline,
+        };
+        let mut world = vec![];
+        let mut objects = vec![];
+        let mut names = BTreeMap::new();
+        for (i, object) in self.objects.iter().enumerate() {
+            let in_object = |msg| SceneError::Invalid(format!("objects[{}]: {}", i, msg));
+            if let Some(name) = object.name() {
+                if names.insert(name.to_string(), i).is_some() {
+                    return Err(in_object(format!("another object is already named \"{}\"", name)));
+                }
+            }
+            let (built, rotate, translate) = object.build(&materials).map_err(in_object)?;
+            let keys = object.keys();
+            if keys.is_empty() {
+                world.push(built.transformed(Transform::new(rotate, translate)));
+                continue;
+            }
+            let track = |pick: fn(&ObjectKeyDesc) -> Option<[f32; 3]>, fixed| {
+                let keys: Vec<_> = keys.iter().filter_map(|k| pick(k).map(|v| (k.time, vec3(v)))).collect();
+                if keys.is_empty() {
+                    return Ok(Track::constant(fixed));
+                }
+                Track::new(keys, interpolation).map_err(|e| in_object(format!("animate: {}", e)))
+            };
+            let track = ObjectTrack {
+                index: i,
+                object: built,
+                rotate: track(|k| k.rotate, rotate)?,
+                translate: track(|k| k.translate, translate)?,
+            };
+            world.push(track.at(0.0));
+            objects.push(track);
+        }
+
+        let focus_on_lookat = camera_desc.focus_dist.is_none() && camera.autofocus.is_none();
+        let animation = match &self.animation {
+            Some(desc) => desc.build(interpolation, focus_on_lookat, &camera, objects)?,
+            None => Animation { objects, ..Animation::default() },
+        };
+        let mut scene = Scene { image, camera, background, world, animation, names, cameras: BTreeMap::new() };
+        scene.focus_camera().map_err(SceneError::Invalid)?;
+        for (name, mut camera) in cameras {
+            let focus_dist = scene.autofocus_distance(&camera).map_err(|e| SceneError::Invalid(format!("cameras.{}: {}", name, e)))?;
+            camera.focus_dist = focus_dist.unwrap_or(camera.focus_dist);
+            scene.cameras.insert(name, camera);
+        }
+        Ok(scene)
+    }
+}
+
+impl AnimationDesc {
+    fn build(
+        &self,
+        interpolation: Interpolation,
+        focus_on_lookat: bool,
+        camera: &CameraSettings,
+        objects: Vec<ObjectTrack>,
+    ) -> Result<Animation, SceneError> {
+        if !is_positive(self.fps) {
+            return invalid(format!("animation.fps must be positive, got {}", self.fps));
+        }
+        if self.frames == Some(0) {
+            return invalid("animation.frames must be at least 1".to_string());
+        }
+
+        fn track<T: Keyable>(
+            keys: &[CameraKeyDesc],
+            name: &str,
+            interpolation: Interpolation,
+            pick: impl Fn(&CameraKeyDesc) -> Option<T>,
+        ) -> Result<Option<Track<T>>, SceneError> {
+            let keys: Vec<_> = keys.iter().filter_map(|k| pick(k).map(|v| (k.time, v))).collect();
+            if keys.is_empty() {
+                return Ok(None);
+            }
+            let track = Track::new(keys, interpolation).map_err(|e| SceneError::Invalid(format!("animation.camera.{}: {}", name, e)))?;
+            Ok(Some(track))
+        }
+        let keys = &self.camera;
+        let mut animation = Animation {
+            fps: self.fps,
+            frames: 1,
+            camera: CameraTracks {
+                lookfrom: track(keys, "lookfrom", interpolation, |k| k.lookfrom.map(vec3))?,
+                lookat: track(keys, "lookat", interpolation, |k| k.lookat.map(vec3))?,
+                vfov: track(keys, "vfov", interpolation, |k| k.vfov)?,
+                focus_dist: track(keys, "focus_dist", interpolation, |k| k.focus_dist)?,
+                focus_on_lookat,
+            },
+            objects,
+        };
+        animation.frames = self.frames.unwrap_or_else(|| animation.frames_to_end());
+
+        // Splines can overshoot, so check the camera where it will actually be.
+        for frame in 0..animation.frames {
+            let at = animation.camera.at(camera, animation.time(frame));
+            at.validate().map_err(|e| SceneError::Invalid(format!("animation frame {}: {}", frame, e)))?;
+        }
+        Ok(animation)
+    }
+}
+
+impl ObjectDesc {
+    /// The object before placement, with its rotation and translation.
+    fn build(&self, materials: &BTreeMap<&str, Material>) -> Result<(Object, Vec3, Vec3), String> {
+        let lookup = |name: &str| {
+            materials.get(name).cloned().ok_or_else(|| {
+                let defined: Vec<&str> = materials.keys().copied().collect();
+                format!(
+                    "unknown material \"{}\" (defined: {})",
+                    name,
+                    if defined.is_empty() { "none".to_string() } else { defined.join(", ") }
+                )
+            })
+        };
+
+        let (object, rotate, translate): (Object, _, _) = match self {
+            ObjectDesc::Sphere { center, radius, material, rotate, translate, .. } => {
+                if !is_positive(*radius) {
+                    return Err(format!("sphere radius must be positive, got {}", radius));
+                }
+                let sphere = Sphere {
+                    center: vec3(*center),
+                    radius: *radius,
+                    material: lookup(material)?,
+                };
+                (sphere.into(), rotate, translate)
+            }
+            ObjectDesc::Rect { axis, min, max, k, material, rotate, translate, .. } => {
+                if !(min[0] < max[0] && min[1] < max[1]) {
+                    return Err(format!("rect min {:?} must be less than max {:?}", min, max));
+                }
+                let axis = match axis {
+                    AxisDesc::X => Axis::X,
+                    AxisDesc::Y => Axis::Y,
+                    AxisDesc::Z => Axis::Z,
+                };
+                let rect = AxisRect {
+                    axis,
+                    min: (min[0], min[1]),
+                    max: (max[0], max[1]),
+                    k: *k,
+                    material: lookup(material)?,
+                };
+                (rect.into(), rotate, translate)
+            }
+            ObjectDesc::Box { min, max, material, rotate, translate, .. } => {
+                if !(0..3).all(|i| min[i] < max[i]) {
+                    return Err(format!("box min {:?} must be less than max {:?}", min, max));
+                }
+                (Cuboid::new(vec3(*min), vec3(*max), lookup(material)?).into(), rotate, translate)
+            }
+        };
+
+        Ok((object, vec3(rotate.unwrap_or_default()), vec3(translate.unwrap_or_default())))
+    }
+
+    fn keys(&self) -> &[ObjectKeyDesc] {
+        match self {
+            ObjectDesc::Sphere { animate, .. } | ObjectDesc::Rect { animate, .. } | ObjectDesc::Box { animate, .. } => animate,
+        }
+    }
+
+    fn name(&self) -> Option<&str> {
+        match self {
+            ObjectDesc::Sphere { name, .. } | ObjectDesc::Rect { name, .. } | ObjectDesc::Box { name, .. } => name.as_deref(),
+        }
+    }
+}
+
+impl TextureDesc {
+    fn build(&self, what: &str) -> Result<Texture, SceneError> {
+        let texture = match self {
+            TextureDesc::Color(c) => check_color(what, *c)?.into(),
+            TextureDesc::Procedural(ProceduralDesc::Checker { scale, even, odd }) => {
+                if !is_positive(*scale) {
+                    return invalid(format!("{}.scale must be positive, got {}", what, scale));
+                }
+                Checker {
+                    scale: *scale,
+                    even: check_color(&format!("{}.even", what), *even)?,
+                    odd: check_color(&format!("{}.odd", what), *odd)?,
+                }
+                .into()
+            }
+            TextureDesc::Procedural(ProceduralDesc::Noise { scale, color }) => {
+                if !is_positive(*scale) {
+                    return invalid(format!("{}.scale must be positive, got {}", what, scale));
+                }
+                Noise {
+                    perlin: Arc::new(Perlin::new()),
+                    scale: *scale,
+                    color: check_color(&format!("{}.color", what), *color)?,
+                }
+                .into()
+            }
+        };
+        Ok(texture)
+    }
+}
+
+impl ImageDesc {
+    fn build(&self) -> Result<ImageSettings, SceneError> {
+        let defaults = ImageSettings::default();
+        let width = self.width.unwrap_or(defaults.width);
+
+        let aspect_ratio = match (self.height, self.aspect_ratio) {
+            (Some(_), Some(_)) => return invalid("image: give either height or aspect_ratio, not both".to_string()),
+            (Some(0), None) => return invalid("image.height must be at least 1".to_string()),
+            (Some(height), None) => width as f32 / height as f32,
+            (None, Some(ratio)) => ratio,
+            (None, None) => defaults.aspect_ratio,
+        };
+
+        let image = ImageSettings {
+            width,
+            height: self.height,
+            aspect_ratio,
+            samples_per_pixel: self.samples_per_pixel.or(defaults.samples_per_pixel),
+            max_depth: self.max_depth.unwrap_or(defaults.max_depth),
+        };
+        image.validate().map_err(SceneError::Invalid)?;
+        Ok(image)
+    }
+}
+
+impl CameraDesc {
+    fn build(&self, dir: &Path) -> Result<CameraSettings, SceneError> {
+        let (lookfrom, lookat, vup) = match (self.rotation, self.translation) {
+            (None, None) => {
+                let (Some(lookfrom), Some(lookat)) = (self.lookfrom, self.lookat) else {
+                    return invalid("camera.lookfrom and camera.lookat are required, unless camera.rotation and camera.translation are given".to_string());
+                };
+                (vec3(lookfrom), vec3(lookat), vec3(self.vup.unwrap_or(default_vup())))
+            }
+            (Some(rotation), Some(translation)) => {
+                if self.lookfrom.is_some() || self.lookat.is_some() || self.vup.is_some() {
+                    return invalid("camera.rotation and camera.translation place the camera, so camera.lookfrom, camera.lookat and camera.vup can't be given".to_string());
+                }
+                let rotation = match rotation {
+                    RotationDesc::Matrix(rows) => mat3(rows),
+                    RotationDesc::Vector(r) => Mat3::rodrigues(vec3(r)),
+                };
+                if !rotation.is_rotation(1e-3) {
+                    return invalid(format!("camera.rotation must be a rotation matrix, got {:?}", rotation.rows));
+                }
+                pose_from_extrinsics(&rotation, vec3(translation))
+            }
+            _ => return invalid("camera.rotation and camera.translation must be given together".to_string()),
+        };
+        let distortion = match self.distortion.as_deref() {
+            None => None,
+            Some(&[k1, k2, p1, p2]) => Some(Distortion { k1, k2, p1, p2, k3: 0.0 }),
+            Some(&[k1, k2, p1, p2, k3]) => Some(Distortion { k1, k2, p1, p2, k3 }),
+            Some(c) => return invalid(format!("camera.distortion must be [k1, k2, p1, p2] or [k1, k2, p1, p2, k3], got {:?}", c)),
+        };
+
+        // Each projection has fields of its own, and takes no others.
+        let kind = self.projection;
+        let names = |projection| match projection {
+            ProjectionDesc::Perspective => "a perspective",
+            ProjectionDesc::Orthographic => "an orthographic",
+            ProjectionDesc::Equirectangular => "an equirectangular",
+            ProjectionDesc::Fisheye => "a fisheye",
+            ProjectionDesc::Lens => "a lens",
+            ProjectionDesc::Calibrated => "a calibrated",
+        };
+        let fields = [
+            ("vfov", self.vfov.is_some(), ProjectionDesc::Perspective),
+            ("view_height", self.view_height.is_some(), ProjectionDesc::Orthographic),
+            ("fov", self.fov.is_some(), ProjectionDesc::Fisheye),
+            ("mapping", self.mapping.is_some(), ProjectionDesc::Fisheye),
+            ("intrinsics", self.intrinsics.is_some(), ProjectionDesc::Calibrated),
+            ("image_size", self.image_size.is_some(), ProjectionDesc::Calibrated),
+            ("lens", self.lens.is_some(), ProjectionDesc::Lens),
+            ("film_diagonal", self.film_diagonal.is_some(), ProjectionDesc::Lens),
+            ("lens_aperture", self.lens_aperture.is_some(), ProjectionDesc::Lens),
+            ("lens_scale", self.lens_scale.is_some(), ProjectionDesc::Lens),
+            ("aperture_blades", self.aperture_blades.is_some(), ProjectionDesc::Perspective),
+            ("aperture_rotation", self.aperture_rotation.is_some(), ProjectionDesc::Perspective),
+            ("aperture_mask", self.aperture_mask.is_some(), ProjectionDesc::Perspective),
+            ("shift", self.shift.is_some(), ProjectionDesc::Perspective),
+            ("tilt", self.tilt.is_some(), ProjectionDesc::Perspective),
+            ("swing", self.swing.is_some(), ProjectionDesc::Perspective),
+        ];
+        if let Some((field, _, to)) = fields.iter().find(|(_, given, to)| *given && *to != kind) {
+            return invalid(format!("camera.{} only applies to {} camera", field, names(*to)));
+        }
+        let required = |field: &str, value: Option<f32>| {
+            value.ok_or_else(|| SceneError::Invalid(format!("camera.{} is required for {} camera", field, names(kind))))
+        };
+
+        let (projection, vfov) = match kind {
+            ProjectionDesc::Perspective => (Projection::Perspective, required("vfov", self.vfov)?),
+            ProjectionDesc::Orthographic => {
+                (Projection::Orthographic { view_height: required("view_height", self.view_height)? }, 0.0)
+            }
+            ProjectionDesc::Equirectangular => (Projection::Equirectangular, 0.0),
+            ProjectionDesc::Fisheye => {
+                let mapping = match self.mapping {
+                    None | Some(MappingDesc::Equidistant) => FisheyeMapping::Equidistant,
+                    Some(MappingDesc::Equisolid) => FisheyeMapping::Equisolid,
+                };
+                (Projection::Fisheye { fov: required("fov", self.fov)?, mapping }, 0.0)
+            }
+            ProjectionDesc::Calibrated => {
+                let (Some(k), Some([width, height])) = (self.intrinsics, self.image_size) else {
+                    return invalid("camera.intrinsics and camera.image_size are required for a calibrated camera".to_string());
+                };
+                if k[1][0] != 0.0 || k[2] != [0.0, 0.0, 1.0] {
+                    return invalid(format!("camera.intrinsics must be [[fx, skew, cx], [0, fy, cy], [0, 0, 1]], got {:?}", k));
+                }
+                let mut intrinsics = Intrinsics::from_matrix(&mat3(k), width, height);
+                intrinsics.distortion = distortion;
+                (Projection::Calibrated(intrinsics), intrinsics.vfov())
+            }
+            ProjectionDesc::Lens => {
+                let Some(path) = &self.lens else {
+                    return invalid("camera.lens is required for a lens camera".to_string());
+                };
+                let system = LensSystem::load(&dir.join(path)).map_err(|e| SceneError::Invalid(format!("camera.lens: {}", e)))?;
+                let defaults = LensOptions::default();
+                let options = LensOptions {
+                    film_diagonal: self.film_diagonal.unwrap_or(defaults.film_diagonal),
+                    aperture_diameter: self.lens_aperture,
+                    scale: self.lens_scale.unwrap_or(defaults.scale),
+                };
+                (Projection::Lens { system: Arc::new(system), options }, 0.0)
+            }
+        };
+        let aperture_shape = match (self.aperture_blades, &self.aperture_mask) {
+            (Some(_), Some(_)) => return invalid("camera.aperture_blades and camera.aperture_mask can't both be given".to_string()),
+            (Some(blades), None) => ApertureShape::Polygon {
+                blades,
+                rotation: self.aperture_rotation.unwrap_or(0.0),
+            },
+            (None, Some(path)) => {
+                let mask = ApertureMask::load(&dir.join(path)).map_err(|e| SceneError::Invalid(format!("camera.aperture_mask: {}", e)))?;
+                ApertureShape::Mask(Arc::new(mask))
+            }
+            (None, None) if self.aperture_rotation.is_some() => {
+                return invalid("camera.aperture_rotation needs camera.aperture_blades".to_string())
+            }
+            (None, None) => ApertureShape::Disc,
+        };
+        let exposure = match (self.f_number, self.shutter, self.iso) {
+            (None, None, None) => None,
+            (Some(f_number), Some(shutter), Some(iso)) => Some(Exposure { f_number, shutter, iso }),
+            _ => return invalid("camera.f_number, camera.shutter and camera.iso must be given together".to_string()),
+        };
+        if exposure.is_some() && (self.aperture.is_some() || self.lens_aperture.is_some()) {
+            return invalid("camera.f_number sets the aperture, so camera.aperture and camera.lens_aperture can't be given".to_string());
+        }
+        let autofocus = match (self.autofocus, &self.focus_on) {
+            (Some(_), Some(_)) => return invalid("camera.autofocus and camera.focus_on can't both be given".to_string()),
+            (Some(AutofocusDesc::Center(false)) | None, None) => None,
+            (Some(AutofocusDesc::Center(true)), None) => Some(Autofocus::Point(0.5, 0.5)),
+            (Some(AutofocusDesc::Point([s, t])), None) => Some(Autofocus::Point(s, t)),
+            (None, Some(name)) => Some(Autofocus::Object(name.clone())),
+        };
+        if autofocus.is_some() && self.focus_dist.is_some() {
+            return invalid("camera.focus_dist can't be given with autofocus".to_string());
+        }
+        let effects = LensEffects {
+            // A calibrated camera distorts its own rays.
+            distortion: distortion.filter(|_| kind != ProjectionDesc::Calibrated),
+            chromatic_aberration: self.chromatic_aberration,
+            vignetting: self.vignetting,
+        };
+        let stereo = match self.stereo {
+            Some(layout) => Some(StereoRig {
+                layout: match layout {
+                    StereoDesc::SideBySide => StereoLayout::SideBySide,
+                    StereoDesc::TopBottom => StereoLayout::TopBottom,
+                },
+                eye_separation: self.eye_separation.unwrap_or(0.064),
+                convergence: self.convergence,
+            }),
+            None if self.eye_separation.is_some() => return invalid("camera.eye_separation needs camera.stereo".to_string()),
+            None if self.convergence.is_some() => return invalid("camera.convergence needs camera.stereo".to_string()),
+            None => None,
+        };
+        let camera = CameraSettings {
+            projection,
+            lookfrom,
+            lookat,
+            vup,
+            vfov,
+            aperture: self.aperture.unwrap_or(0.0),
+            aperture_shape,
+            exposure,
+            shift: self.shift.map_or((0.0, 0.0), |[x, y]| (x, y)),
+            tilt: (self.tilt.unwrap_or(0.0), self.swing.unwrap_or(0.0)),
+            autofocus,
+            effects,
+            stereo,
+            rolling_shutter: self.rolling_shutter,
+            focus_dist: self.focus_dist.unwrap_or((lookfrom - lookat).length()),
+        };
+        camera.validate().map_err(SceneError::Invalid)?;
+        Ok(camera)
+    }
+}
+
+impl MaterialDesc {
+    fn build(&self, name: &str) -> Result<Material, SceneError> {
+        let material = match self {
+            MaterialDesc::Lambertian { albedo } => Lambertian {
+                albedo: albedo.build(&format!("materials.{}.albedo", name))?,
+            }
+            .into(),
+            MaterialDesc::Metal { albedo, fuzz } => {
+                if !(0.0..=1.0).contains(fuzz) {
+                    return invalid(format!("materials.{}.fuzz must be between 0 and 1, got {}", name, fuzz));
+                }
+                Metal {
+                    albedo: check_color(&format!("materials.{}.albedo", name), *albedo)?,
+                    fuzz: *fuzz,
+                }
+                .into()
+            }
+            MaterialDesc::Dielectric { index_of_refraction } => {
+                if !is_positive(*index_of_refraction) {
+                    return invalid(format!(
+                        "materials.{}.index_of_refraction must be positive, got {}",
+                        name, index_of_refraction
+                    ));
+                }
+                Dialectric {
+                    index_of_refraction: *index_of_refraction,
+                }
+                .into()
+            }
+            MaterialDesc::Light { emit } => DiffuseLight {
+                emit: emit.build(&format!("materials.{}.emit", name))?,
+            }
+            .into(),
+        };
+        Ok(material)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: &str = "[camera]\nlookfrom = [0, 0, 5]\nlookat = [0, 0, 0]\nvfov = 40\n";
+
+    fn error(source: &str) -> String {
+        match Scene::parse(source) {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn example_scene_loads() {
+        let scene = Scene::parse(include_str!("../scenes/three_spheres.toml")).unwrap();
+        assert_eq!(scene.world.len(), 4);
+        assert_eq!(scene.image.width, 600);
+        assert_eq!(scene.image.height(), 400);
+
+        // A height is kept as given, not recomputed from the aspect ratio.
+        let image = Scene::parse(&format!("[image]\nwidth = 100\nheight = 366\n{}", CAMERA)).unwrap().image;
+        assert_eq!(image.height(), 366);
+    }
+
+    #[test]
+    fn fingerprints_follow_the_contents() {
+        let source = include_str!("../scenes/orbit.toml");
+        let scene = Scene::parse(source).unwrap();
+        assert_eq!(scene.fingerprint(), Scene::parse(source).unwrap().fingerprint());
+        // Pinned, as processes from different builds compare them.
+        assert_eq!(fnv1a(b"a"), 0xAF63_DC4C_8601_EC8C);
+
+        let mut other = Scene::parse(source).unwrap();
+        other.camera.vfov += 1.0;
+        assert_eq!(other.fingerprint(), scene.fingerprint());
+        other.background = Background::Solid(Color::new(0.0, 0.0, 0.0));
+        assert_ne!(other.fingerprint(), scene.fingerprint());
+        assert_ne!(scene.at(0.5).unwrap().fingerprint(), scene.fingerprint());
+    }
+
+    #[test]
+    fn cornell_scene_loads() {
+        let scene = Scene::parse(include_str!("../scenes/cornell_box.toml")).unwrap();
+        assert_eq!(scene.world.len(), 8);
+        assert!(matches!(scene.background, Background::Solid(_)));
+        assert!(matches!(scene.world[6], Object::Instance(_)));
+    }
+
+    #[test]
+    fn lens_scene_loads() {
+        // The prescription is found next to the scene file.
+        let scene = Scene::load(Path::new("scenes/lens.toml")).unwrap();
+        assert!(matches!(&scene.camera.projection, Projection::Lens { system, .. } if system.elements.len() == 11));
+        let source = include_str!("../scenes/lens.toml");
+        assert!(error(source).contains("camera.lens"));
+        assert!(error(&source.replace("lens_scale = 1000", "lens_scale = 1000\nvfov = 20")).contains("only applies to a perspective"));
+        let too_wide = source.replace("lens_scale = 1000", "lens_scale = 1000\nlens_aperture = 40");
+        assert!(Scene::parse_in(&too_wide, Path::new("scenes")).is_err());
+    }
+
+    #[test]
+    fn loads_shift_and_tilt() {
+        let scene = Scene::parse(&format!("{}shift = [0, 0.25]\ntilt = 10\n", CAMERA)).unwrap();
+        assert_eq!((scene.camera.shift, scene.camera.tilt), ((0.0, 0.25), (10.0, 0.0)));
+
+        let source = format!("{}swing = 90\n", CAMERA);
+        assert_eq!(error(&source), "invalid scene: camera.swing must be between -90 and 90 degrees, got 90");
+        let source = "[camera]\nprojection = \"equirectangular\"\nlookfrom = [0, 1, 0]\nlookat = [0, 1, -1]\nshift = [0, 1]\n";
+        assert_eq!(error(source), "invalid scene: camera.shift only applies to a perspective camera");
+    }
+
+    #[test]
+    fn loads_lens_effects() {
+        let source = format!("{}distortion = [-0.1, 0.01, 0, 0]\nchromatic_aberration = [0.002, 0, -0.002]\nvignetting = true\n", CAMERA);
+        let effects = Scene::parse(&source).unwrap().camera.effects;
+        assert_eq!(effects.distortion, Some(Distortion { k1: -0.1, k2: 0.01, ..Default::default() }));
+        assert_eq!(effects.chromatic_aberration, Some([0.002, 0.0, -0.002]));
+        assert!(effects.vignetting);
+
+        let source = format!("{}distortion = [-0.1, 0.01]\n", CAMERA);
+        assert_eq!(
+            error(&source),
+            "invalid scene: camera.distortion must be [k1, k2, p1, p2] or [k1, k2, p1, p2, k3], got [-0.1, 0.01]"
+        );
+        let source = format!("{}chromatic_aberration = [-1, 0, 0]\n", CAMERA);
+        assert_eq!(
+            error(&source),
+            "invalid scene: camera.chromatic_aberration shifts must be more than -1, got [-1.0, 0.0, 0.0]"
+        );
+    }
+
+    #[test]
+    fn loads_stereo_rigs() {
+        let scene = Scene::parse(&format!("{}stereo = \"side-by-side\"\nconvergence = 5\n", CAMERA)).unwrap();
+        let rig = StereoRig { layout: StereoLayout::SideBySide, eye_separation: 0.064, convergence: Some(5.0) };
+        assert_eq!(scene.camera.stereo, Some(rig));
+        let scene = Scene::parse(include_str!("../scenes/vr360.toml")).unwrap();
+        assert_eq!(scene.camera.stereo.map(|rig| rig.layout), Some(StereoLayout::TopBottom));
+
+        let source = format!("{}eye_separation = 0.1\n", CAMERA);
+        assert_eq!(error(&source), "invalid scene: camera.eye_separation needs camera.stereo");
+        let source = "[camera]\nprojection = \"equirectangular\"\nlookfrom = [0, 1, 0]\nlookat = [0, 1, -1]\n\
+                      stereo = \"top-bottom\"\nconvergence = 2\n";
+        assert_eq!(error(source), "invalid scene: camera.convergence only applies to a perspective or calibrated camera");
+    }
+
+    #[test]
+    fn loads_calibrated_cameras() {
+        // Looking down -z from 5 along it, as OpenCV sees it: x right, y down, z forward.
+        let calibrated = "[camera]\nprojection = \"calibrated\"\n\
+                          intrinsics = [[500, 0, 319.5], [0, 500, 239.5], [0, 0, 1]]\nimage_size = [640, 480]\n";
+        let source = format!("{}rotation = [[1, 0, 0], [0, -1, 0], [0, 0, -1]]\ntranslation = [0, 0, 5]\n", calibrated);
+        let camera = Scene::parse(&source).unwrap().camera;
+        assert!((camera.lookfrom - Point3::new(0.0, 0.0, 5.0)).length() < 1e-6);
+        assert!((camera.lookat - Point3::zero()).length() < 1e-6);
+        assert!((camera.vup - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-6);
+        assert!((camera.vfov - 2.0 * f32::atan(240.0 / 500.0).to_degrees()).abs() < 1e-4);
+        // The same turn as a rotation vector: half a turn about x.
+        let source = format!("{}rotation = [3.14159265, 0, 0]\ntranslation = [0, 0, 5]\n", calibrated);
+        assert!((Scene::parse(&source).unwrap().camera.vup - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-6);
+
+        let source = format!("{}rotation = [[1, 0, 0], [0, 1, 0], [0, 0, -1]]\ntranslation = [0, 0, 5]\n", calibrated);
+        assert!(error(&source).starts_with("invalid scene: camera.rotation must be a rotation matrix"));
+        let source = format!("{}lookfrom = [0, 0, 5]\nlookat = [0, 0, 0]\nrotation = [0, 0, 0]\ntranslation = [0, 0, 5]\n", calibrated);
+        assert!(error(&source).contains("so camera.lookfrom, camera.lookat and camera.vup can't be given"));
+        assert_eq!(
+            error("[camera]\nvfov = 40\n"),
+            "invalid scene: camera.lookfrom and camera.lookat are required, unless camera.rotation and camera.translation are given"
+        );
+    }
+
+    #[test]
+    fn loads_named_cameras() {
+        let scene = Scene::parse(include_str!("../scenes/product.toml")).unwrap();
+        assert_eq!(scene.cameras.keys().collect::<Vec<_>>(), ["front", "side", "top"]);
+        // The named camera first in alphabetical order stands in for a missing [camera], and each focuses on its own.
+        assert_eq!(scene.camera.lookfrom, scene.cameras["front"].lookfrom);
+        assert!((scene.cameras["side"].focus_dist - 36.49f32.sqrt()).abs() < 1e-4);
+
+        let source = format!("{}[cameras.wide]\nlookfrom = [5, 0, 0]\nlookat = [0, 0, 0]\nvfov = 90\n", CAMERA);
+        let mut scene = Scene::parse(&source).unwrap();
+        assert_eq!(scene.camera.vfov, 40.0);
+        scene.use_camera("wide").unwrap();
+        assert_eq!((scene.camera.lookfrom, scene.camera.vfov), (Point3::new(5.0, 0.0, 0.0), 90.0));
+        assert_eq!(scene.use_camera("tele"), Err("there is no camera named \"tele\"; the scene has \"wide\"".to_string()));
+
+        assert_eq!(
+            error("[cameras.wide]\nlookfrom = [5, 0, 0]\nlookat = [0, 0, 0]\n"),
+            "invalid scene: cameras.wide: camera.vfov is required for a perspective camera"
+        );
+        assert_eq!(error("[image]\nwidth = 100\n"), "invalid scene: the scene needs a [camera], or named ones in [cameras.NAME]");
+        assert_eq!(
+            error("[cameras.\"../x\"]\nlookfrom = [5, 0, 0]\nlookat = [0, 0, 0]\nvfov = 90\n"),
+            "invalid scene: cameras.\"../x\": a camera's name goes into a file name, so can't be empty or hold / or \\"
+        );
+    }
+
+    #[test]
+    fn focuses_automatically() {
+        let spheres = "[materials.grey]\ntype = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]\n\n\
+                       [[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"grey\"\n\n\
+                       [[objects]]\nname = \"far\"\ntype = \"sphere\"\ncenter = [2, 0, -5]\nradius = 1\nmaterial = \"grey\"\n";
+        // The near sphere's surface is 4 from the camera, the far one's centre 10 along the view.
+        let scene = Scene::parse(&format!("{}autofocus = true\n{}", CAMERA, spheres)).unwrap();
+        assert!((scene.camera.focus_dist - 4.0).abs() < 1e-4);
+        let mut scene = Scene::parse(&format!("{}focus_on = \"far\"\n{}", CAMERA, spheres)).unwrap();
+        assert!((scene.camera.focus_dist - 10.0).abs() < 1e-4);
+
+        // Nothing is seen at the top of the image, so the focus stays where it was.
+        scene.camera.autofocus = Some(Autofocus::Point(0.5, 1.0));
+        scene.focus_camera().unwrap();
+        assert!((scene.camera.focus_dist - 10.0).abs() < 1e-4);
+
+        let source = format!("{}focus_on = \"near\"\n{}", CAMERA, spheres);
+        assert_eq!(error(&source), "invalid scene: camera: there is no object named \"near\" to focus on");
+        let source = format!("{}autofocus = true\nfocus_dist = 3\n", CAMERA);
+        assert_eq!(error(&source), "invalid scene: camera.focus_dist can't be given with autofocus");
+
+        // A lens can't focus on something a few centimetres away.
+        let mut scene = Scene::load(Path::new("scenes/lens.toml")).unwrap();
+        let forward = Vec3::unit_vector(&(scene.camera.lookat - scene.camera.lookfrom));
+        let Object::Sphere(mirror) = &mut scene.world[scene.names["mirror"]] else { panic!("expected a sphere") };
+        (mirror.center, mirror.radius) = (scene.camera.lookfrom + 0.03 * forward, 0.001);
+        let error = scene.at(0.0).err().unwrap();
+        assert!(error.starts_with("at 0s: camera.lens: "), "{}", error);
+    }
+
+    #[test]
+    fn physical_scene_loads() {
+        let scene = Scene::parse(include_str!("../scenes/physical.toml")).unwrap();
+        assert_eq!(scene.camera.exposure, Some(Exposure { f_number: 16.0, shutter: 0.033, iso: 100.0 }));
+
+        // The moving sphere is where its track puts it when each ray is cast.
+        let still = scene.at(0.0).unwrap();
+        assert!(matches!(still.world[3], Object::Moving(_)));
+        let ray = |time| Ray::new(Point3::new(6.0, 0.5, 10.0), Vec3::new(0.0, 0.0, -1.0)).with_time(time);
+        assert!(still.world[3].hit(&ray(0.0), 0.001, f32::INFINITY).is_some());
+        assert!(still.world[3].hit(&ray(0.5), 0.001, f32::INFINITY).is_none());
+
+        let source = format!("{}f_number = 8\n", CAMERA);
+        assert_eq!(error(&source), "invalid scene: camera.f_number, camera.shutter and camera.iso must be given together");
+        let source = format!("{}f_number = 8\nshutter = 0.01\niso = 100\naperture = 0.1\n", CAMERA);
+        assert!(error(&source).contains("camera.aperture and camera.lens_aperture can't be given"));
+        let source = format!("{}f_number = 8\nshutter = 0\niso = 100\n", CAMERA);
+        assert_eq!(error(&source), "invalid scene: camera.shutter must be positive, got 0");
+    }
+
+    #[test]
+    fn rolling_shutter_follows_the_camera() {
+        let mut scene = Scene::parse(include_str!("../scenes/orbit.toml")).unwrap();
+        scene.camera.rolling_shutter = Some(0.5);
+        let still = scene.at(0.5).unwrap();
+        assert!(matches!(still.world[still.world.len() - 1], Object::Moving(_)));
+
+        // The top row is cast from where the camera is at 0.5 seconds, the bottom from 1 second,
+        // looking where it looks then.
+        let image = ImageSettings { width: 30, height: Some(20), ..ImageSettings::default() };
+        let camera = still.build_camera(&image).unwrap();
+        for (t, time) in [(1.0, 0.5), (0.0, 1.0)] {
+            let r = camera.get_ray(0.5, t).unwrap();
+            assert_eq!(r.time, time - 0.5);
+            let now = scene.animation.camera.at(&scene.camera, time);
+            assert!((r.orig - now.lookfrom).length() < 1e-4);
+            let (u, _, w) = basis(now.lookfrom, now.lookat, now.vup);
+            let dir = Vec3::unit_vector(&r.dir);
+            assert!(Vec3::dot(&dir, &u).abs() < 1e-3 && Vec3::dot(&dir, &-w) > 0.9);
+        }
+
+        let source = format!("{}rolling_shutter = -0.01\n", CAMERA);
+        assert_eq!(error(&source), "invalid scene: camera.rolling_shutter must be positive, got -0.01");
+    }
+
+    #[test]
+    fn animated_scene_loads() {
+        let scene = Scene::parse(include_str!("../scenes/orbit.toml")).unwrap();
+        assert_eq!(scene.animation.frames, 49);
+        assert_eq!(scene.animation.objects.len(), 1);
+
+        // The camera passes through its keys, refocusing on lookat as it goes.
+        let end = scene.at(2.0).unwrap();
+        assert_eq!(end.camera.lookfrom, Point3::new(-12.0, 2.0, 5.0));
+        assert!((end.camera.focus_dist - (end.camera.lookfrom - end.camera.lookat).length()).abs() < 1e-5);
+
+        // The box moves from one side of the glass sphere to the other.
+        let ray = Ray::new(Point3::new(2.0, 0.5, 10.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(scene.at(0.0).unwrap().world[4].hit(&ray, 0.001, f32::INFINITY).is_some());
+        assert!(end.world[4].hit(&ray, 0.001, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn rejects_bad_animation() {
+        let source = format!("{}[animation]\nfps = 10\n[[animation.camera]]\ntime = 1\nvfov = 30\n[[animation.camera]]\ntime = 0\nvfov = 50\n", CAMERA);
+        assert_eq!(error(&source), "invalid scene: animation.camera.vfov: key times must increase, but 1 is followed by 0");
+        let source = format!("{}[animation]\n[[animation.camera]]\ntime = 1\nlookfrom = [0, 0, 0]\n", CAMERA);
+        assert!(error(&source).contains("animation frame 0: camera: lookfrom and lookat must be different points"));
+    }
+
+    #[test]
+    fn loads_orthographic_camera() {
+        let source = "[camera]\nprojection = \"orthographic\"\nlookfrom = [0, 10, 0]\nlookat = [0, 0, 0]\n\
+                      vup = [0, 0, -1]\nview_height = 8\n";
+        let scene = Scene::parse(source).unwrap();
+        assert_eq!(scene.camera.projection, Projection::Orthographic { view_height: 8.0 });
+
+        let source = format!("{}view_height = 8\n", CAMERA);
+        assert_eq!(error(&source), "invalid scene: camera.view_height only applies to an orthographic camera");
+    }
+
+    #[test]
+    fn loads_panoramic_cameras() {
+        let camera = "[camera]\nlookfrom = [0, 1, 0]\nlookat = [0, 1, -1]\n";
+        let scene = Scene::parse(&format!("{}projection = \"equirectangular\"\n", camera)).unwrap();
+        assert_eq!(scene.camera.projection, Projection::Equirectangular);
+
+        let source = format!("{}projection = \"fisheye\"\nfov = 220\nmapping = \"equisolid\"\n", camera);
+        let scene = Scene::parse(&source).unwrap();
+        assert_eq!(scene.camera.projection, Projection::Fisheye { fov: 220.0, mapping: FisheyeMapping::Equisolid });
+
+        let source = format!("{}projection = \"fisheye\"\n", camera);
+        assert_eq!(error(&source), "invalid scene: camera.fov is required for a fisheye camera");
+        let source = format!("{}projection = \"equirectangular\"\nvfov = 90\n", camera);
+        assert_eq!(error(&source), "invalid scene: camera.vfov only applies to a perspective camera");
+    }
+
+    #[test]
+    fn loads_aperture_shapes() {
+        let scene = Scene::parse(&format!("{}aperture_blades = 6\naperture_rotation = 30\n", CAMERA)).unwrap();
+        assert_eq!(scene.camera.aperture_shape, ApertureShape::Polygon { blades: 6, rotation: 30.0 });
+
+        let source = format!("{}aperture_blades = 2\n", CAMERA);
+        assert_eq!(error(&source), "invalid scene: camera: an aperture needs at least 3 blades, got 2");
+        let source = format!("{}aperture_rotation = 30\n", CAMERA);
+        assert_eq!(error(&source), "invalid scene: camera.aperture_rotation needs camera.aperture_blades");
+        let source = format!("{}aperture_mask = \"missing.png\"\n", CAMERA);
+        assert!(error(&source).starts_with("invalid scene: camera.aperture_mask: could not read aperture mask"));
+
+        // Say from the command line, for a lens camera.
+        let mut camera = scene.camera;
+        let system = LensSystem::parse(include_str!("../scenes/lenses/dgauss50.dat")).unwrap();
+        camera.projection = Projection::Lens { system: Arc::new(system), options: LensOptions::default() };
+        assert_eq!(camera.validate().unwrap_err(), "camera: aperture blades and masks only apply to a perspective camera");
+    }
+
+    #[test]
+    fn reports_unknown_material() {
+        let source = format!(
+            "{}[materials.glass]\ntype = \"dielectric\"\nindex_of_refraction = 1.5\n\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"glas\"\n",
+            CAMERA
+        );
+        assert_eq!(error(&source), "invalid scene: objects[0]: unknown material \"glas\" (defined: glass)");
+    }
+
+    #[test]
+    fn reports_location_of_syntax_errors() {
+        let source = format!("{}\n[image]\nwidth = \"wide\"\n", CAMERA);
+        let message = error(&source);
+        assert!(message.contains("line 7, column 9"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let source = format!("{}[image]\nwidht = 100\n", CAMERA);
+        assert!(error(&source).contains("unknown field `widht`"));
+        let source = format!("{}[[objects]]\ntype = \"box\"\nmin = [0, 0, 0]\nmax = [1, 1, 1]\nmaterial = \"m\"\nrotation = [0, 45, 0]\n", CAMERA);
+        assert!(error(&source).contains("unknown field `rotation`"));
+    }
+}
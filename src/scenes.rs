@@ -0,0 +1,369 @@
+//! Built-in scenes, selectable by name with `--builtin`.
+//!
+//! Scenes with random parts draw from the thread's generator, so seed it (stream 0) before
+//! building one to get the same scene for the same render seed.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::animation::*;
+use crate::aperture::ApertureShape;
+use crate::camera::LensEffects;
+use crate::hit::*;
+use crate::material::*;
+use crate::perlin::*;
+use crate::scene::*;
+use crate::texture::*;
+use crate::util::*;
+use crate::vec3::*;
+
+pub struct BuiltinScene {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub build: fn() -> Scene,
+}
+
+pub const BUILTIN_SCENES: &[BuiltinScene] = &[
+    BuiltinScene {
+        name: "random-spheres",
+        description: "The cover scene: three large spheres among a grid of small random ones",
+        build: random_spheres,
+    },
+    BuiltinScene {
+        name: "cornell-box",
+        description: "The Cornell box with two rotated blocks, lit from the ceiling",
+        build: cornell_box,
+    },
+    BuiltinScene {
+        name: "two-spheres",
+        description: "Two large checkered spheres, one above the other",
+        build: two_spheres,
+    },
+    BuiltinScene {
+        name: "perlin-spheres",
+        description: "A marble sphere resting on a marble ground",
+        build: perlin_spheres,
+    },
+    BuiltinScene {
+        name: "simple-light",
+        description: "Marble spheres lit only by a rectangular and a spherical light",
+        build: simple_light,
+    },
+    BuiltinScene {
+        name: "materials",
+        description: "A row of spheres showing each material on a checkered floor",
+        build: materials_showcase,
+    },
+];
+
+/// Look up a built-in scene by name.
+pub fn builtin(name: &str) -> Option<&'static BuiltinScene> {
+    BUILTIN_SCENES.iter().find(|scene| scene.name == name)
+}
+
+// The cover scene: three large spheres surrounded by a grid of small random ones.
+pub fn random_spheres() -> Scene {
+    // Camera
+    let lookfrom = Point3::new(13.0, 2.0, 3.0);
+    let lookat = Point3::new(0.0, 0.0, 0.0);
+    let vup = Point3::new(0.0, 1.0, 0.0);
+    let fov = 20.0;
+    let dist_to_focus = 10.0;
+    let aperture = 0.1;
+
+    // Scene
+    let mut world: Vec<Object> = vec![];
+    world.push(Sphere {
+        center: Point3::new(0.0, -1000.0, 0.0),
+        radius: 1000.0,
+        material: Lambertian {
+            albedo: Color::new(0.5, 0.5, 0.5).into(),
+        }.into(),
+    }.into());
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = random_f32();
+
+            let center = Point3::new(
+                a as f32 + 0.9 * random_f32(),
+                0.2,
+                b as f32 + 0.9 + random_f32(),
+            );
+            let radius = 0.2;
+
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                let material: Material = if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo = Color::random() * Color::random();
+                    Lambertian {
+                        albedo: albedo.into(),
+                    }.into()
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo = Color::random_range(0.5, 1.0);
+                    let fuzz = random_f32_range(0.0, 0.5);
+                    Metal {
+                        albedo,
+                        fuzz,
+                    }.into()
+                } else {
+                    // glass
+                    Dialectric {
+                        index_of_refraction: 1.5,
+                    }.into()
+                };
+                world.push(Sphere {
+                    center,
+                    radius,
+                    material,
+                }.into());
+            }
+        }
+    }
+
+    world.push(Sphere {
+        center: Point3::new(0.0, 1.0, 0.0),
+        radius: 1.0,
+        material: Dialectric {
+            index_of_refraction: 1.5,
+        }.into(),
+    }.into());
+    world.push(Sphere {
+        center: Point3::new(-4.0, 1.0, 0.0),
+        radius: 1.0,
+        material: Lambertian {
+            albedo: Color::new(0.4, 0.2, 0.1).into(),
+        }.into(),
+    }.into());
+    world.push(Sphere {
+        center: Point3::new(4.0, 1.0, 0.0),
+        radius: 1.0,
+        material: Metal {
+            albedo: Color::new(0.7, 0.6, 0.5),
+            fuzz: 0.0,
+        }.into(),
+    }.into());
+
+    Scene {
+        image: ImageSettings::default(),
+        camera: CameraSettings {
+            projection: Projection::Perspective,
+            lookfrom,
+            lookat,
+            vup,
+            vfov: fov,
+            aperture,
+            aperture_shape: ApertureShape::Disc,
+            exposure: None,
+            shift: (0.0, 0.0),
+            tilt: (0.0, 0.0),
+            autofocus: None,
+            effects: LensEffects::default(),
+            stereo: None,
+            rolling_shutter: None,
+            focus_dist: dist_to_focus,
+        },
+        background: Background::Sky,
+        world,
+        animation: Animation::default(),
+        names: BTreeMap::new(),
+        cameras: BTreeMap::new(),
+    }
+}
+
+/// Loaded from `scenes/cornell_box.toml`, so the file and the built-in scene stay the same.
+pub fn cornell_box() -> Scene {
+    Scene::parse(include_str!("../scenes/cornell_box.toml")).expect("the bundled Cornell box is valid")
+}
+
+pub fn two_spheres() -> Scene {
+    let checker: Material = Lambertian {
+        albedo: Checker {
+            scale: 0.32,
+            even: Color::new(0.2, 0.3, 0.1),
+            odd: Color::new(0.9, 0.9, 0.9),
+        }
+        .into(),
+    }
+    .into();
+
+    let world = vec![
+        Sphere { center: Point3::new(0.0, -10.0, 0.0), radius: 10.0, material: checker.clone() }.into(),
+        Sphere { center: Point3::new(0.0, 10.0, 0.0), radius: 10.0, material: checker }.into(),
+    ];
+
+    Scene {
+        image: ImageSettings {
+            width: 400,
+            height: None,
+            aspect_ratio: 16.0 / 9.0,
+            samples_per_pixel: Some(100),
+            max_depth: 50,
+        },
+        camera: distant_camera(Point3::new(13.0, 2.0, 3.0), Point3::zero()),
+        background: Background::Sky,
+        world,
+        animation: Animation::default(),
+        names: BTreeMap::new(),
+        cameras: BTreeMap::new(),
+    }
+}
+
+pub fn perlin_spheres() -> Scene {
+    let marble: Material = Lambertian { albedo: marble(4.0) }.into();
+
+    let world = vec![
+        Sphere { center: Point3::new(0.0, -1000.0, 0.0), radius: 1000.0, material: marble.clone() }.into(),
+        Sphere { center: Point3::new(0.0, 2.0, 0.0), radius: 2.0, material: marble }.into(),
+    ];
+
+    Scene {
+        image: ImageSettings {
+            width: 400,
+            height: None,
+            aspect_ratio: 16.0 / 9.0,
+            samples_per_pixel: Some(100),
+            max_depth: 50,
+        },
+        camera: distant_camera(Point3::new(13.0, 2.0, 3.0), Point3::zero()),
+        background: Background::Sky,
+        world,
+        animation: Animation::default(),
+        names: BTreeMap::new(),
+        cameras: BTreeMap::new(),
+    }
+}
+
+pub fn simple_light() -> Scene {
+    let marble: Material = Lambertian { albedo: marble(4.0) }.into();
+    let light: Material = DiffuseLight { emit: Color::new(4.0, 4.0, 4.0).into() }.into();
+
+    let world = vec![
+        Sphere { center: Point3::new(0.0, -1000.0, 0.0), radius: 1000.0, material: marble.clone() }.into(),
+        Sphere { center: Point3::new(0.0, 2.0, 0.0), radius: 2.0, material: marble }.into(),
+        AxisRect { axis: Axis::Z, min: (3.0, 1.0), max: (5.0, 3.0), k: -2.0, material: light.clone() }.into(),
+        Sphere { center: Point3::new(0.0, 7.0, 0.0), radius: 2.0, material: light }.into(),
+    ];
+
+    Scene {
+        image: ImageSettings {
+            width: 400,
+            height: None,
+            aspect_ratio: 16.0 / 9.0,
+            samples_per_pixel: Some(400),
+            max_depth: 50,
+        },
+        camera: distant_camera(Point3::new(26.0, 3.0, 6.0), Point3::new(0.0, 2.0, 0.0)),
+        background: Background::Solid(Color::zero()),
+        world,
+        animation: Animation::default(),
+        names: BTreeMap::new(),
+        cameras: BTreeMap::new(),
+    }
+}
+
+pub fn materials_showcase() -> Scene {
+    let floor: Material = Lambertian {
+        albedo: Checker {
+            scale: 1.0,
+            even: Color::new(0.2, 0.2, 0.2),
+            odd: Color::new(0.8, 0.8, 0.8),
+        }
+        .into(),
+    }
+    .into();
+    let materials: [Material; 5] = [
+        Lambertian { albedo: Color::new(0.7, 0.2, 0.2).into() }.into(),
+        Lambertian { albedo: marble(3.0) }.into(),
+        Metal { albedo: Color::new(0.8, 0.8, 0.8), fuzz: 0.0 }.into(),
+        Metal { albedo: Color::new(0.8, 0.6, 0.2), fuzz: 0.3 }.into(),
+        Dialectric { index_of_refraction: 1.5 }.into(),
+    ];
+
+    let mut world: Vec<Object> = vec![
+        Sphere { center: Point3::new(0.0, -1000.0, 0.0), radius: 1000.0, material: floor }.into(),
+    ];
+    for (i, material) in materials.into_iter().enumerate() {
+        world.push(Sphere { center: Point3::new(2.2 * (i as f32 - 2.0), 1.0, 0.0), radius: 1.0, material }.into());
+    }
+
+    Scene {
+        image: ImageSettings {
+            width: 800,
+            height: None,
+            aspect_ratio: 2.0,
+            samples_per_pixel: Some(200),
+            max_depth: 50,
+        },
+        camera: CameraSettings {
+            projection: Projection::Perspective,
+            lookfrom: Point3::new(0.0, 3.0, 12.0),
+            lookat: Point3::new(0.0, 1.0, 0.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 45.0,
+            aperture: 0.0,
+            aperture_shape: ApertureShape::Disc,
+            exposure: None,
+            shift: (0.0, 0.0),
+            tilt: (0.0, 0.0),
+            autofocus: None,
+            effects: LensEffects::default(),
+            stereo: None,
+            rolling_shutter: None,
+            focus_dist: 12.0,
+        },
+        background: Background::Sky,
+        world,
+        animation: Animation::default(),
+        names: BTreeMap::new(),
+        cameras: BTreeMap::new(),
+    }
+}
+
+// A pinhole camera with a narrow field of view, as used by the "Next Week" test scenes.
+fn distant_camera(lookfrom: Point3, lookat: Point3) -> CameraSettings {
+    CameraSettings {
+        projection: Projection::Perspective,
+        lookfrom,
+        lookat,
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov: 20.0,
+        aperture: 0.0,
+        aperture_shape: ApertureShape::Disc,
+        exposure: None,
+        shift: (0.0, 0.0),
+        tilt: (0.0, 0.0),
+        autofocus: None,
+        effects: LensEffects::default(),
+        stereo: None,
+        rolling_shutter: None,
+        focus_dist: 10.0,
+    }
+}
+
+fn marble(scale: f32) -> Texture {
+    Noise {
+        perlin: Arc::new(Perlin::new()),
+        scale,
+        color: Color::new(1.0, 1.0, 1.0),
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_scenes_are_valid() {
+        for scene in BUILTIN_SCENES {
+            assert!(builtin(scene.name).is_some());
+            let built = (scene.build)();
+            assert!(!built.world.is_empty(), "{} has no objects", scene.name);
+            built.image.validate().unwrap();
+            built.camera.validate().unwrap();
+        }
+        assert!(builtin("no-such-scene").is_none());
+    }
+}
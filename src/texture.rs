@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use enum_dispatch::enum_dispatch;
+use serde::Serialize;
+
+use crate::perlin::*;
+use crate::vec3::*;
+
+#[enum_dispatch]
+pub trait TextureBehavior {
+    /// Colour at surface coordinates (u, v), hit at point p.
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color;
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct SolidColor {
+    pub color: Color,
+}
+
+impl TextureBehavior for SolidColor {
+    fn value(&self, _: f32, _: f32, _: &Point3) -> Color {
+        self.color
+    }
+}
+
+/// A 3D checkerboard of cubes `scale` units across, so it wraps any shape without seams.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Checker {
+    pub scale: f32,
+    pub even: Color,
+    pub odd: Color,
+}
+
+impl TextureBehavior for Checker {
+    fn value(&self, _: f32, _: f32, p: &Point3) -> Color {
+        let cell = |c: f32| (c / self.scale).floor() as i64;
+        if (cell(p.x) + cell(p.y) + cell(p.z)) % 2 == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
+
+/// Marble-like Perlin noise: a sine pattern along z, perturbed by turbulence.
+#[derive(Clone, Debug, Serialize)]
+pub struct Noise {
+    pub perlin: Arc<Perlin>,
+    /// Frequency of the pattern; larger is finer.
+    pub scale: f32,
+    pub color: Color,
+}
+
+impl TextureBehavior for Noise {
+    fn value(&self, _: f32, _: f32, p: &Point3) -> Color {
+        let intensity = 0.5 * (1.0 + f32::sin(self.scale * p.z + 10.0 * self.perlin.turb(p, 7)));
+        intensity * self.color
+    }
+}
+
+#[enum_dispatch(TextureBehavior)]
+#[derive(Clone, Debug, Serialize)]
+pub enum Texture {
+    SolidColor,
+    Checker,
+    Noise,
+}
+
+impl From<Color> for Texture {
+    fn from(color: Color) -> Texture {
+        SolidColor { color }.into()
+    }
+}
@@ -0,0 +1,187 @@
+use std::sync::Mutex;
+
+use crate::film::*;
+
+/// A rectangle of pixels, `x0..x1` by `y0..y1`, with rows counted from the top like `Film`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Tile {
+    pub fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1 - self.y0
+    }
+
+    /// Whether the tile has pixels and they all lie in a `width` x `height` image.
+    pub fn is_within(&self, width: usize, height: usize) -> bool {
+        self.x0 < self.x1 && self.y0 < self.y1 && self.x1 <= width && self.y1 <= height
+    }
+
+    /// The tile's pixels as (x, y), row by row.
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> {
+        let Tile { x0, y0, x1, y1 } = *self;
+        (y0..y1).flat_map(move |y| (x0..x1).map(move |x| (x, y)))
+    }
+}
+
+/// The order tiles are handed out in. The image is the same whatever the order; it only
+/// changes which parts show up first in a flushed or previewed partial render.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TileOrder {
+    /// Left to right, top to bottom.
+    Scanline,
+    /// Outwards from the centre of the image, where the subject usually is.
+    #[default]
+    Spiral,
+    /// Along a Hilbert curve, so consecutive tiles are neighbours and share cache.
+    Hilbert,
+}
+
+impl TileOrder {
+    pub fn from_name(name: &str) -> Option<TileOrder> {
+        match name {
+            "scanline" => Some(TileOrder::Scanline),
+            "spiral" => Some(TileOrder::Spiral),
+            "hilbert" => Some(TileOrder::Hilbert),
+            _ => None,
+        }
+    }
+}
+
+/// Cut a `width` x `height` image into tiles of at most `size` x `size`, in the given order.
+pub fn tiles(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<Tile> {
+    let size = size.max(1);
+    let (nx, ny) = (width.div_ceil(size), height.div_ceil(size));
+    if nx == 0 || ny == 0 {
+        return vec![];
+    }
+    let mut grid: Vec<(usize, usize)> = (0..ny).flat_map(|ty| (0..nx).map(move |tx| (tx, ty))).collect();
+
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            // Ring by ring around the centre tile, each ring swept by angle.
+            let (cx, cy) = (((nx - 1) / 2) as f32, ((ny - 1) / 2) as f32);
+            let key = |&(tx, ty): &(usize, usize)| {
+                let (dx, dy) = (tx as f32 - cx, ty as f32 - cy);
+                (f32::max(dx.abs(), dy.abs()), f32::atan2(dy, dx))
+            };
+            grid.sort_by(|a, b| {
+                let (ka, kb) = (key(a), key(b));
+                ka.0.total_cmp(&kb.0).then(ka.1.total_cmp(&kb.1))
+            });
+        }
+        TileOrder::Hilbert => {
+            let n = nx.max(ny).next_power_of_two();
+            grid.sort_by_key(|&(tx, ty)| hilbert_index(n, tx, ty));
+        }
+    }
+
+    grid.into_iter()
+        .map(|(tx, ty)| Tile {
+            x0: tx * size,
+            y0: ty * size,
+            x1: ((tx + 1) * size).min(width),
+            y1: ((ty + 1) * size).min(height),
+        })
+        .collect()
+}
+
+// Distance of (x, y) along the Hilbert curve filling an n x n grid (n a power of two).
+fn hilbert_index(n: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = usize::from(x & s > 0);
+        let ry = usize::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+        // Rotate the quadrant so the curve joins up.
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x &= s - 1;
+        y &= s - 1;
+        s /= 2;
+    }
+    d
+}
+
+/// A film that render threads add finished tiles into, and that can be copied out at any time
+/// for a partial image.
+pub struct Framebuffer {
+    film: Mutex<Film>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer::from_film(Film::new(width, height))
+    }
+
+    /// Carry on accumulating into an existing film.
+    pub fn from_film(film: Film) -> Framebuffer {
+        Framebuffer { film: Mutex::new(film) }
+    }
+
+    /// Add a tile's samples, given row by row, to what is already there.
+    pub fn add_tile(&self, tile: &Tile, pixels: &[Pixel]) {
+        let mut film = self.film.lock().unwrap();
+        let width = film.width;
+        for (row, y) in pixels.chunks(tile.width()).zip(tile.y0..tile.y1) {
+            for (pixel, sample) in film.pixels[y * width + tile.x0..y * width + tile.x1].iter_mut().zip(row) {
+                pixel.merge(sample);
+            }
+        }
+    }
+
+    /// A copy of the film as it stands. Pixels with no samples yet are black.
+    pub fn snapshot(&self) -> Film {
+        self.film.lock().unwrap().clone()
+    }
+
+    pub fn into_film(self) -> Film {
+        self.film.into_inner().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_order_covers_each_pixel_once() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let mut covered = vec![0; 70 * 45];
+            for tile in tiles(70, 45, 16, order) {
+                for (x, y) in tile.pixels() {
+                    covered[y * 70 + x] += 1;
+                }
+            }
+            assert!(covered.iter().all(|&n| n == 1), "{:?}", order);
+            assert!(tiles(0, 45, 16, order).is_empty());
+        }
+    }
+
+    #[test]
+    fn orders_start_and_step_as_described() {
+        // 5 x 5 tiles: the spiral starts in the middle one.
+        assert_eq!(tiles(50, 50, 10, TileOrder::Spiral)[0], Tile { x0: 20, y0: 20, x1: 30, y1: 30 });
+        // Consecutive Hilbert tiles always share an edge.
+        let hilbert = tiles(80, 80, 10, TileOrder::Hilbert);
+        for pair in hilbert.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            assert_eq!(a.x0.abs_diff(b.x0) + a.y0.abs_diff(b.y0), 10, "{:?} -> {:?}", a, b);
+        }
+    }
+}
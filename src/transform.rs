@@ -0,0 +1,181 @@
+use core::ops::Mul;
+
+use serde::Serialize;
+
+use crate::util::degrees_to_radians;
+use crate::vec3::*;
+
+/// A 3x3 matrix, stored as rows.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Mat3 {
+    pub rows: [Vec3; 3],
+}
+
+impl Mat3 {
+    pub fn identity() -> Mat3 {
+        Mat3::from_rows(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0))
+    }
+
+    pub fn from_rows(r0: Vec3, r1: Vec3, r2: Vec3) -> Mat3 {
+        Mat3 { rows: [r0, r1, r2] }
+    }
+
+    pub fn from_columns(c0: Vec3, c1: Vec3, c2: Vec3) -> Mat3 {
+        Mat3::from_rows(c0, c1, c2).transpose()
+    }
+
+    pub fn transpose(&self) -> Mat3 {
+        let [a, b, c] = self.rows;
+        Mat3::from_rows(Vec3::new(a.x, b.x, c.x), Vec3::new(a.y, b.y, c.y), Vec3::new(a.z, b.z, c.z))
+    }
+
+    pub fn column(&self, i: usize) -> Vec3 {
+        Vec3::new(self.rows[0][i], self.rows[1][i], self.rows[2][i])
+    }
+
+    /// Rotation of `degrees` about the x axis, counter-clockwise looking down the axis.
+    pub fn rotation_x(degrees: f32) -> Mat3 {
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+        Mat3::from_rows(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, cos, -sin), Vec3::new(0.0, sin, cos))
+    }
+
+    pub fn rotation_y(degrees: f32) -> Mat3 {
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+        Mat3::from_rows(Vec3::new(cos, 0.0, sin), Vec3::new(0.0, 1.0, 0.0), Vec3::new(-sin, 0.0, cos))
+    }
+
+    pub fn rotation_z(degrees: f32) -> Mat3 {
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+        Mat3::from_rows(Vec3::new(cos, -sin, 0.0), Vec3::new(sin, cos, 0.0), Vec3::new(0.0, 0.0, 1.0))
+    }
+
+    /// Rotate about x, then y, then z, by the components of `degrees`.
+    pub fn rotation(degrees: Vec3) -> Mat3 {
+        Mat3::rotation_z(degrees.z) * Mat3::rotation_y(degrees.y) * Mat3::rotation_x(degrees.x)
+    }
+
+    /// Rotation of `r.length()` radians about `r`, counter-clockwise looking down it: a rotation
+    /// vector, as OpenCV's Rodrigues gives it.
+    pub fn rodrigues(r: Vec3) -> Mat3 {
+        let angle = r.length();
+        if angle == 0.0 {
+            return Mat3::identity();
+        }
+        let k = r / angle;
+        let (sin, cos) = angle.sin_cos();
+        let row = |i: usize, a: Vec3| (1.0 - cos) * k[i] * k + a;
+        Mat3::from_rows(
+            row(0, Vec3::new(cos, -sin * k.z, sin * k.y)),
+            row(1, Vec3::new(sin * k.z, cos, -sin * k.x)),
+            row(2, Vec3::new(-sin * k.y, sin * k.x, cos)),
+        )
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let [a, b, c] = self.rows;
+        Vec3::dot(&a, &Vec3::cross(&b, &c))
+    }
+
+    /// Whether this is a rotation, to within `tolerance`: orthonormal, and not a reflection.
+    pub fn is_rotation(&self, tolerance: f32) -> bool {
+        let product = *self * self.transpose();
+        let identity = Mat3::identity();
+        (0..3).all(|i| (product.rows[i] - identity.rows[i]).length() < tolerance)
+            && (self.determinant() - 1.0).abs() < tolerance
+    }
+}
+
+impl Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+    #[inline]
+    fn mul(self, v: Vec3) -> Vec3 {
+        Vec3::new(Vec3::dot(&self.rows[0], &v), Vec3::dot(&self.rows[1], &v), Vec3::dot(&self.rows[2], &v))
+    }
+}
+
+impl Mul<Mat3> for Mat3 {
+    type Output = Mat3;
+    fn mul(self, other: Mat3) -> Mat3 {
+        let cols = [other.column(0), other.column(1), other.column(2)];
+        let row = |r: &Vec3| Vec3::new(Vec3::dot(r, &cols[0]), Vec3::dot(r, &cols[1]), Vec3::dot(r, &cols[2]));
+        Mat3::from_rows(row(&self.rows[0]), row(&self.rows[1]), row(&self.rows[2]))
+    }
+}
+
+/// A rigid transform from object space to world space: rotate, then translate.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Transform {
+    pub rotation: Mat3,
+    pub translation: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            rotation: Mat3::identity(),
+            translation: Vec3::zero(),
+        }
+    }
+
+    /// Rotate about x, then y, then z (in degrees), then translate.
+    pub fn new(rotation_degrees: Vec3, translation: Vec3) -> Transform {
+        Transform {
+            rotation: Mat3::rotation(rotation_degrees),
+            translation,
+        }
+    }
+
+    pub fn point_to_world(&self, p: &Point3) -> Point3 {
+        self.rotation * *p + self.translation
+    }
+
+    pub fn point_to_object(&self, p: &Point3) -> Point3 {
+        // Rotations are orthonormal, so the inverse is the transpose.
+        self.rotation.transpose() * (*p - self.translation)
+    }
+
+    pub fn vector_to_world(&self, v: &Vec3) -> Vec3 {
+        self.rotation * *v
+    }
+
+    pub fn vector_to_object(&self, v: &Vec3) -> Vec3 {
+        self.rotation.transpose() * *v
+    }
+
+    /// A fraction `f` of the way from `self` to `other`, blending the matrices. It's only
+    /// nearly rigid when the two are close.
+    pub fn lerp(&self, other: &Transform, f: f32) -> Transform {
+        let blend = |a: Vec3, b: Vec3| (1.0 - f) * a + f * b;
+        let [a, b] = [self.rotation.rows, other.rotation.rows];
+        Transform {
+            rotation: Mat3::from_rows(blend(a[0], b[0]), blend(a[1], b[1]), blend(a[2], b[2])),
+            translation: blend(self.translation, other.translation),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::PI;
+
+    #[test]
+    fn round_trips_points() {
+        let t = Transform::new(Vec3::new(10.0, 45.0, -30.0), Vec3::new(1.0, 2.0, 3.0));
+        let p = Point3::new(0.5, -2.0, 4.0);
+        assert!((t.point_to_object(&t.point_to_world(&p)) - p).length() < 1e-5);
+        // A quarter turn about y takes +x to -z.
+        let r = Mat3::rotation_y(90.0) * Vec3::new(1.0, 0.0, 0.0);
+        assert!((r - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-6);
+        let r = Mat3::rodrigues(Vec3::new(0.0, PI / 2.0, 0.0)) * Vec3::new(1.0, 0.0, 0.0);
+        assert!((r - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-6);
+        assert!(Mat3::rodrigues(Vec3::new(0.3, -1.0, 2.0)).is_rotation(1e-5));
+        assert!(!Mat3::from_rows(Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)).is_rotation(1e-3));
+    }
+}
@@ -1,23 +1,57 @@
-use rand::Rng;
+use std::cell::RefCell;
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
 
 pub const INFINITY: f32 = f32::INFINITY;
 pub const PI: f32 = std::f32::consts::PI;
 
+thread_local! {
+    // Each thread has its own generator so sampling never contends; `seed_rng` makes it repeatable.
+    // A small, fast one, as every sample reseeds it.
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+}
+
+/// Reseed this thread's generator. Seeding from `(seed, stream)`, e.g. the scene seed and a row
+/// index, gives the same samples however rayon happens to schedule the work.
+pub fn seed_rng(seed: u64, stream: u64) {
+    let seed = derive_seed(seed, stream);
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+}
+
+/// The seed for stream `stream` of `seed`, unrelated to its neighbours'.
+pub fn derive_seed(seed: u64, stream: u64) -> u64 {
+    // splitmix64 finalizer.
+    let mut z = seed ^ stream.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// A fresh seed for when the user didn't ask for one.
+pub fn random_seed() -> u64 {
+    rand::random()
+}
+
+/// The 64-bit FNV-1a hash of `bytes`, which unlike the standard library's hashers is the same in
+/// every build.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3))
+}
+
 #[inline]
 pub fn random_f32() -> f32 {
-    let mut rng = rand::thread_rng();
-    rng.gen::<f32>()
+    RNG.with(|rng| rng.borrow_mut().gen::<f32>())
 }
 
 #[inline]
 pub fn random_f32_range(min: f32, max: f32) -> f32 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(min..max)
+    RNG.with(|rng| rng.borrow_mut().gen_range(min..max))
 }
 
 #[inline]
 pub fn degrees_to_radians(degrees: f32) -> f32 {
-    return degrees * PI / 180.0;
+    degrees * PI / 180.0
 }
 
 #[inline]
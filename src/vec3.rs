@@ -1,18 +1,20 @@
 use core::{fmt, ops::*};
 use std::iter::Sum;
 
+use serde::{Deserialize, Serialize};
+
 use crate::util::*;
 
 ///
 /// Ideas for SIMD stuff eventually: https://docs.rs/glam/0.8.7/src/glam/f32/vec3.rs.html#24
 ///
 
-#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Default)]
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Default, Serialize, Deserialize)]
 // pub struct Vec3(pub(crate) f32, pub(crate) f32, pub(crate) f32);
 pub struct Vec3 {
-    pub(crate) x: f32,
-    pub(crate) y: f32,
-    pub(crate) z: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
 }
 
 pub type Point3 = Vec3;